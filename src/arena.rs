@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+use crate::grid::{self, Cell, WALL_TILE};
+
+/// Seconds between the hurry-up warning and the first closing tile.
+const WARNING_TIME: f64 = 3.0;
+
+/// Floor tiles left open in the middle of the arena.
+const OPEN_TILES: usize = 9;
+
+/// `Rocks` metadata: how many rocks closing tiles took, they weren't destroyed by anyone.
+const CLOSED_ROCKS_META: &str = "closed_rocks";
+
+/// Server only: the hurry-up phase of a round, floor tiles turn into walls one by one
+/// in a spiral from the outside in.
+pub struct HurryUp {
+    /// In closing order
+    cells: Vec<Cell>,
+    next: usize,
+    /// Seconds between two tiles
+    interval: f64,
+    /// Seconds until the next tile closes
+    next_in: f64,
+}
+
+impl HurryUp {
+    /// The arena is closed within `duration` seconds, after the warning.
+    pub fn new(tilemap: TRef<TileMap>, duration: f64) -> Self {
+        let mut cells = spiral(tilemap);
+        cells.truncate(cells.len().saturating_sub(OPEN_TILES));
+
+        let interval = (duration - WARNING_TIME).max(1.0) / (cells.len() + 1) as f64;
+
+        HurryUp {
+            cells,
+            next: 0,
+            interval,
+            next_in: WARNING_TIME,
+        }
+    }
+
+    /// Tiles closing during the last `delta` seconds.
+    pub fn update(&mut self, delta: f64) -> Vec<Cell> {
+        let mut closing = Vec::new();
+
+        self.next_in -= delta;
+        while self.next_in <= 0.0 && self.next < self.cells.len() {
+            closing.push(self.cells[self.next]);
+            self.next += 1;
+            self.next_in += self.interval;
+        }
+
+        closing
+    }
+
+    /// Tiles closing within the next `within` seconds, in closing order.
+    pub fn upcoming(&self, within: f64) -> &[Cell] {
+        let count = if within < self.next_in {
+            0
+        } else {
+            ((within - self.next_in) / self.interval) as usize + 1
+        };
+
+        let end = (self.next + count).min(self.cells.len());
+        &self.cells[self.next..end]
+    }
+}
+
+/// Floor tiles ring by ring from the outside in, clockwise from the top left corner.
+/// Works on the tiles of the world, so the baked map and generated ones close the same way.
+fn spiral(tilemap: TRef<TileMap>) -> Vec<Cell> {
+    let floor = floor_cells(tilemap);
+    if floor.is_empty() {
+        return Vec::new();
+    }
+
+    let mut left = floor.iter().map(|cell| cell.0).min().unwrap();
+    let mut right = floor.iter().map(|cell| cell.0).max().unwrap();
+    let mut top = floor.iter().map(|cell| cell.1).min().unwrap();
+    let mut bottom = floor.iter().map(|cell| cell.1).max().unwrap();
+
+    let mut order = Vec::new();
+    while left <= right && top <= bottom {
+        order.extend((left..=right).map(|x| (x, top)));
+        order.extend((top + 1..=bottom).map(|y| (right, y)));
+        if top < bottom {
+            order.extend((left..right).rev().map(|x| (x, bottom)));
+        }
+        if left < right {
+            order.extend((top + 1..bottom).rev().map(|y| (left, y)));
+        }
+
+        left += 1;
+        right -= 1;
+        top += 1;
+        bottom -= 1;
+    }
+
+    order
+        .into_iter()
+        .filter(|cell| floor.contains(cell))
+        .collect()
+}
+
+/// Hazard tiles close like the plain floor.
+fn floor_cells(tilemap: TRef<TileMap>) -> HashSet<Cell> {
+    tilemap
+        .get_used_cells()
+        .iter()
+        .map(|cell| {
+            let cell = cell.to_vector2();
+            (cell.x as i32, cell.y as i32)
+        })
+        .filter(|cell| grid::is_floor(tilemap, *cell))
+        .collect()
+}
+
+/// A floor tile becomes a wall, rocks and power-ups on it are gone.
+/// Players in it are moved out and bombs on it set off by the server, see `nearest_open_cell`.
+pub fn close_cell(world: TRef<Node2D>, cell: Cell) {
+    let tilemap = grid::get_tilemap(world);
+    tilemap.set_cell(
+        cell.0 as i64,
+        cell.1 as i64,
+        WALL_TILE,
+        false,
+        false,
+        false,
+        Vector2::zero(),
+    );
+
+    for group in ["Rocks", "PowerUps"].iter() {
+        let parent = unsafe { world.get_node(*group).unwrap().assume_safe() };
+        for child in parent.get_children().iter() {
+            // Rock or PowerUp
+            let child = child.try_to_object::<Node2D>().unwrap();
+            let child = unsafe { child.assume_safe() };
+            //
+
+            if grid::to_cell(tilemap, child.position()) == cell && !child.is_queued_for_deletion() {
+                child.queue_free();
+                if *group == "Rocks" {
+                    set_closed_rocks(&parent, closed_rocks(&parent) + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Rocks taken by closing tiles, `rocks` is the `Rocks` node of the world.
+pub fn closed_rocks(rocks: &Node) -> i64 {
+    if rocks.has_meta(CLOSED_ROCKS_META) {
+        rocks.get_meta(CLOSED_ROCKS_META).to_i64()
+    } else {
+        0
+    }
+}
+
+/// Reset by `ReplayViewer`, which closes the tiles again when seeking.
+pub fn set_closed_rocks(rocks: &Node, count: i64) {
+    rocks.set_meta(CLOSED_ROCKS_META, count);
+}
+
+/// The closest floor tile still open, where players caught in `cell` are moved.
+pub fn nearest_open_cell(tilemap: TRef<TileMap>, cell: Cell) -> Cell {
+    floor_cells(tilemap)
+        .into_iter()
+        .min_by_key(|open| ((open.0 - cell.0).abs() + (open.1 - cell.1).abs(), *open))
+        .unwrap_or(cell)
+}
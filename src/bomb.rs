@@ -1,14 +1,145 @@
+use crate::grid::{self, Cell};
+use crate::hazard::{self, CONVEYOR_BOMB_SPEED};
+use crate::player::{BASE_BLAST_RANGE, PLAYER_RADIUS};
+use crate::replay;
+use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
 
+/// Flashing of the bomb animation in seconds, sped up or slowed down to the fuse.
+const FLASHING_TIME: f64 = 2.8;
+
+/// Seconds the blast is shown before the bomb is freed.
+const BLAST_DURATION: f64 = 0.6;
+
+/// Kicked bombs slide at 5 tiles per second, thrown ones fly faster.
+const KICK_SPEED: f64 = 240.0;
+const THROW_SPEED: f64 = 360.0;
+/// Crosses any map within a frame.
+const TELEPORT_SPEED: f64 = 100_000.0;
+
+/// Tiles of a throw, a bomb which can't land there bounces on, at most `MAX_BOUNCES` more tiles.
+const THROW_DISTANCE: i32 = 3;
+const MAX_BOUNCES: i32 = 3;
+
+/// Seconds before a mine goes off under a player, its owner gets away meanwhile.
+const MINE_ARM_TIME: f64 = 1.0;
+
+/// Bomb variants, picked with power-ups and sent as an index with `setup_bomb`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BombKind {
+    Normal,
+    /// Set off by its player with the detonator key, no fuse
+    Remote,
+    /// The blast goes through rocks, only walls stop it
+    Piercing,
+    /// Set off by the first player walking over it, no fuse
+    Mine,
+}
+
+impl BombKind {
+    pub const ALL: [BombKind; 4] = [
+        BombKind::Normal,
+        BombKind::Remote,
+        BombKind::Piercing,
+        BombKind::Mine,
+    ];
+
+    pub fn from_i64(value: i64) -> Self {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or(BombKind::Normal)
+    }
+
+    pub fn to_i64(self) -> i64 {
+        Self::ALL.iter().position(|kind| *kind == self).unwrap() as i64
+    }
+
+    /// Shown in the HUD, empty for normal bombs.
+    pub fn name(self) -> &'static str {
+        match self {
+            BombKind::Normal => "",
+            BombKind::Remote => "remote",
+            BombKind::Piercing => "piercing",
+            BombKind::Mine => "mine",
+        }
+    }
+
+    /// Bombs exploding on their own when the fuse burns down.
+    pub fn has_fuse(self) -> bool {
+        matches!(self, BombKind::Normal | BombKind::Piercing)
+    }
+
+    /// Tint of the bomb.
+    fn color(self) -> Color {
+        match self {
+            BombKind::Normal => Color::rgb(1.0, 1.0, 1.0),
+            BombKind::Remote => Color::rgb(0.5, 0.7, 1.0),
+            BombKind::Piercing => Color::rgb(1.0, 0.5, 0.3),
+            BombKind::Mine => Color::rgba(0.6, 0.6, 0.6, 0.6),
+        }
+    }
+
+    /// Outline of the blast tiles.
+    fn blast_color(self) -> Color {
+        match self {
+            BombKind::Piercing => Color::rgba(1.0, 0.2, 0.2, 0.8),
+            _ => Color::rgba(1.0, 0.5, 0.1, 0.8),
+        }
+    }
+}
+
 #[derive(NativeClass)]
 #[inherit(Area2D)]
 pub struct Bomb {
     // preloads
-    #[property]
-    in_area: VariantArray,
+    explosion_texture: Option<Ref<Texture>>,
+
     #[property]
     from_player_id: i64,
+    /// In tiles, set by the player who placed the bomb
+    #[property]
+    blast_range: i64,
+    /// Seconds until the explosion, counted down by every peer
+    #[property]
+    fuse_left: f64,
+    /// Index of `BombKind::ALL`
+    #[property]
+    kind: i64,
+    /// Mines only: seconds before the mine can go off
+    arming_left: f64,
+
+    #[property]
+    exploded: bool,
+    /// Blocks players, from the moment its player is out of its tile
+    #[property]
+    solid: bool,
+    /// Players who were in the way when the bomb turned solid or moved, chosen by the server,
+    /// they walk through it until they are out of its tile
+    passing: Vec<String>,
+    /// Sliding or flying, it can't be kicked or thrown meanwhile
+    #[property]
+    moving: bool,
+    /// Name of the player holding the bomb over their head, empty when it lies on the floor.
+    /// Set by the server on every peer
+    #[property]
+    carried_by: String,
+    /// Where the bomb is moving to and how fast, set by the server on every peer
+    target: Option<Vector2>,
+    speed: f64,
+    /// Server only: a kicked bomb keeps sliding this way
+    slide_direction: Option<Cell>,
+    /// Server only: direction of the last move, a bomb keeps it on ice
+    heading: Option<Cell>,
+    /// Server only: coming out of a teleporter, it doesn't send the bomb back
+    teleporting: bool,
+    /// Seconds the blast is still shown
+    blast_left: f64,
+    /// Position in a chain reaction, 0 for a bomb exploding on its own
+    chain_depth: i64,
+    /// Cells hit by the explosion, drawn until the bomb is done
+    blast: Vec<Cell>,
 }
 
 #[methods]
@@ -16,90 +147,725 @@ pub struct Bomb {
 impl Bomb {
     fn new(_owner: TRef<Area2D>) -> Self {
         Bomb {
-            in_area: VariantArray::new_shared(),
+            explosion_texture: None,
             from_player_id: 0,
+            blast_range: BASE_BLAST_RANGE,
+            fuse_left: 0.0,
+            kind: 0,
+            arming_left: MINE_ARM_TIME,
+
+            exploded: false,
+            solid: false,
+            passing: Vec::new(),
+            moving: false,
+            carried_by: String::new(),
+            target: None,
+            speed: 0.0,
+            slide_direction: None,
+            heading: None,
+            teleporting: false,
+            blast_left: BLAST_DURATION,
+            chain_depth: 0,
+            blast: Vec::new(),
         }
     }
 
     #[export]
     fn _ready(&mut self, owner: TRef<Area2D>) {
-        if let Err(e) = self.connect_signals(owner) {
-            godot_error!("`Bomb` => GodotError at `connect_signals` function: {}", e);
+        self.preload_instances();
+
+        let kind = BombKind::from_i64(self.kind);
+        let sprite = owner.get_node("Sprite").unwrap();
+        unsafe { sprite.assume_safe() }
+            .cast::<Sprite>()
+            .unwrap()
+            .set_modulate(kind.color());
+
+        // The flashing ends with the fuse, bombs without one don't flash
+        if !kind.has_fuse() {
+            self.get_animation(owner).stop(true);
+        } else if self.fuse_left > 0.0 {
+            self.get_animation(owner)
+                .set_speed_scale(FLASHING_TIME / self.fuse_left);
         }
     }
 
     #[export]
-    fn _on_bomb_body_entered(&self, _owner: TRef<Area2D>, body: Variant) {
-        // Body
-        let body = body.try_to_object::<Node>().unwrap();
-        let body = unsafe { body.assume_safe() };
-        //
+    fn _process(&mut self, owner: TRef<Area2D>, delta: f64) {
+        if let Some(target) = self.target {
+            let step = (self.speed * delta) as f32;
+            let to_target = target - owner.position();
+            if to_target.length() <= step {
+                owner.set_position(target);
+                self.arrived(owner);
+            } else {
+                owner.set_position(owner.position() + to_target.normalize() * step);
+            }
+        }
 
-        if !self.in_area.contains(body) {
-            unsafe {
-                self.in_area.push(body);
+        if !self.exploded {
+            // A carried bomb is neither solid nor on the floor, the fuse keeps burning
+            if !self.carried_by.is_empty() {
+                self.follow_carrier(owner);
+            } else if !self.solid {
+                self.check_placer_left(owner);
+            } else {
+                self.release_passing(owner);
+                // A moving bomb may run into players
+                if self.moving {
+                    self.let_in_players(owner);
+                }
+            }
+
+            if !self.moving && self.carried_by.is_empty() {
+                self.ride_conveyor(owner);
+            }
+
+            match BombKind::from_i64(self.kind) {
+                BombKind::Normal | BombKind::Piercing => {
+                    self.fuse_left -= delta;
+                    if self.fuse_left <= 0.0 {
+                        self.explode(owner);
+                    }
+                }
+                BombKind::Mine => {
+                    self.arming_left -= delta;
+                    if self.arming_left <= 0.0 && !self.moving && self.carried_by.is_empty() {
+                        self.check_mine(owner);
+                    }
+                }
+                BombKind::Remote => {}
+            }
+        } else {
+            self.blast_left -= delta;
+            if self.blast_left <= 0.0 {
+                self.done(owner);
             }
         }
     }
 
-    #[export]
-    fn _on_bomb_body_exited(&self, _owner: TRef<Area2D>, body: Variant) {
-        // Body
-        let body = body.try_to_object::<Node>().unwrap();
-        let body = unsafe { body.assume_safe() };
-        //
+    /// The blast is drawn by every peer, hits are only sent by the master (the server).
+    fn explode(&mut self, owner: TRef<Area2D>) {
+        // Already set off by another bomb
+        if self.exploded {
+            return;
+        }
+        self.exploded = true;
+        self.target = None;
+        self.moving = false;
+        self.carried_by = String::new();
+        self.slide_direction = None;
+        self.get_body(owner).set_collision_layer(0);
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
 
+        let walls = grid::walls(tilemap);
+        let rocks = grid::rocks(world);
+        let center = grid::to_cell(tilemap, owner.position());
+        self.blast = match BombKind::from_i64(self.kind) {
+            // Every rock in reach is hit
+            BombKind::Piercing => grid::blast_cells(
+                center,
+                self.blast_range,
+                |cell| walls.contains(&cell),
+                |_| false,
+            ),
+            BombKind::Normal | BombKind::Remote | BombKind::Mine => grid::blast_cells(
+                center,
+                self.blast_range,
+                |cell| walls.contains(&cell),
+                |cell| rocks.contains(&cell),
+            ),
+        };
+        owner.update();
+
+        self.get_animation(owner).stop(true);
+        let sprite = owner.get_node("Sprite").unwrap();
+        unsafe { sprite.assume_safe() }
+            .cast::<Sprite>()
+            .unwrap()
+            .hide();
+
+        // One bomb less for its player
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
         unsafe {
-            self.in_area.erase(body);
+            gamestate.call_deferred("update_hud", &[]);
         }
-    }
 
-    #[export(rpc = "master")]
-    fn explode(&self, owner: TRef<Area2D>) {
-        if !owner.is_network_master() {
-            godot_warn!("`explode` function is only available for `master`!");
+        // Watching a replay, explosions are recorded events
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
             return;
         }
 
-        for object in self.in_area.iter() {
-            if object.has_method("exploded") {
-                // Exploded has a master keyword, so it will only be received by the master.
-                // Player
-                let object = object.try_to_object::<Node>().unwrap();
+        for group in ["Players", "Rocks"].iter() {
+            for object in unsafe { world.get_node(*group).unwrap().assume_safe() }
+                .get_children()
+                .iter()
+            {
+                // Player or Rock
+                let object = object.try_to_object::<Node2D>().unwrap();
                 let object = unsafe { object.assume_safe() };
                 //
 
-                println!(
-                    "self.from_player_id: {:?}",
-                    self.from_player_id.to_variant()
-                );
-                object.rpc("exploded", &[self.from_player_id.to_variant()]);
+                if self
+                    .blast
+                    .contains(&grid::to_cell(tilemap, object.position()))
+                {
+                    // Exploded has a master keyword, so it will only be received by the master.
+                    object.rpc("exploded", &[self.from_player_id.to_variant()]);
+                }
+            }
+        }
+
+        self.set_off_chain(owner, world);
+    }
+
+    /// Server only: live bombs in the blast explode next, in the order of their names.
+    /// Deferred, so every bomb of the chain explodes in its own call.
+    fn set_off_chain(&self, owner: TRef<Area2D>, world: TRef<Node2D>) {
+        let tilemap = grid::get_tilemap(world);
+
+        // Bombs are the only `Area2D` children of `World`
+        let mut bombs = Vec::new();
+        for child in world.get_children().iter() {
+            let child = unsafe { child.try_to_object::<Node>().unwrap().assume_safe() };
+            if let Some(bomb) = child.cast::<Area2D>() {
+                if bomb.get_instance_id() != owner.get_instance_id()
+                    && !bomb.get("exploded").to_bool()
+                    && self
+                        .blast
+                        .contains(&grid::to_cell(tilemap, bomb.position()))
+                {
+                    bombs.push(bomb);
+                }
+            }
+        }
+        bombs.sort_by_key(|bomb| bomb.name().to_string());
+
+        let args = [
+            (self.chain_depth + 1).to_variant(),
+            owner.name().to_variant(),
+        ];
+        for bomb in bombs {
+            bomb.rpc("detonate", &args);
+            unsafe {
+                bomb.call_deferred("detonate", &args);
+            }
+        }
+    }
+
+    /// Server only: the bomb slides tile by tile towards `(dx, dy)`
+    /// until a wall, a rock, a bomb or a player is in the way.
+    #[export]
+    fn kick(&mut self, owner: TRef<Area2D>, dx: i64, dy: i64) {
+        if self.exploded || self.moving {
+            return;
+        }
+
+        self.slide_direction = Some((dx.signum() as i32, dy.signum() as i32));
+        self.slide(owner);
+    }
+
+    /// Server only: the bomb flies over everything towards `(dx, dy)` and lands
+    /// `THROW_DISTANCE` tiles away, or further on the first free tile.
+    #[export]
+    fn throw(&mut self, owner: TRef<Area2D>, dx: i64, dy: i64) {
+        if self.exploded || self.moving {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let (dx, dy) = (dx.signum() as i32, dy.signum() as i32);
+        let from = grid::to_cell(tilemap, owner.position());
+        let landing = (THROW_DISTANCE..=THROW_DISTANCE + MAX_BOUNCES)
+            .map(|distance| (from.0 + dx * distance, from.1 + dy * distance))
+            .find(|cell| !self.is_blocked(owner, world, *cell));
+
+        if let Some(landing) = landing {
+            self.heading = Some((dx, dy));
+            self.send_move(owner, grid::cell_center(tilemap, landing), THROW_SPEED);
+        } else if !self.carried_by.is_empty() {
+            self.send_put_down(owner);
+        }
+    }
+
+    /// Server (Sync): `carrier` (a player name) lifts the bomb, it's carried until it's thrown.
+    #[export(rpc = "puppet")]
+    fn pick_up(&mut self, owner: TRef<Area2D>, carrier: String) {
+        if self.exploded || self.moving {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "pick_up", &[carrier.to_variant()]);
+
+        self.carried_by = carrier;
+        self.slide_direction = None;
+        // Turns solid again once it lies on the floor, away from its player
+        self.solid = false;
+        self.get_body(owner).set_collision_layer(0);
+    }
+
+    /// The bomb stays over the head of its carrier, the server puts it down
+    /// when the carrier is stunned or out of the round.
+    fn follow_carrier(&mut self, owner: TRef<Area2D>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let carrier = world
+            .get_node(format!("Players/{}", self.carried_by))
+            .and_then(|carrier| unsafe { carrier.assume_safe() }.cast::<Node2D>());
+
+        if let Some(carrier) = carrier {
+            owner.set_position(carrier.position());
+        }
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
+            return;
+        }
+
+        let dropped = carrier
+            .map(|carrier| carrier.get("stunned").to_bool() || carrier.get("eliminated").to_bool())
+            .unwrap_or(true);
+        if dropped {
+            self.send_put_down(owner);
+        }
+    }
+
+    fn send_put_down(&mut self, owner: TRef<Area2D>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let position = grid::snap(grid::get_tilemap(world), owner.position());
+
+        owner.rpc("put_down", &[position.to_variant()]);
+        self.put_down(owner, position);
+    }
+
+    /// Server (Sync): the carrier lets go of the bomb, it lies at `position`.
+    #[export(rpc = "puppet")]
+    fn put_down(&mut self, owner: TRef<Area2D>, position: Vector2) {
+        if self.exploded {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "put_down", &[position.to_variant()]);
+
+        self.carried_by = String::new();
+        owner.set_position(position);
+    }
+
+    /// Server only: one more tile of a kicked bomb, if it's free.
+    fn slide(&mut self, owner: TRef<Area2D>) {
+        let (dx, dy) = match self.slide_direction {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let cell = grid::to_cell(tilemap, owner.position());
+        let next = (cell.0 + dx, cell.1 + dy);
+        if self.is_blocked(owner, world, next) {
+            self.slide_direction = None;
+            return;
+        }
+
+        self.heading = Some((dx, dy));
+        self.send_move(owner, grid::cell_center(tilemap, next), KICK_SPEED);
+    }
+
+    /// Server only: a bomb lying on a conveyor is carried to the next tile, once it's free.
+    fn ride_conveyor(&mut self, owner: TRef<Area2D>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let cell = grid::to_cell(tilemap, owner.position());
+        let (dx, dy) = match hazard::conveyor_direction(tilemap, cell) {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        let next = (cell.0 + dx, cell.1 + dy);
+        if !self.is_blocked(owner, world, next) {
+            self.heading = Some((dx, dy));
+            self.send_move(owner, grid::cell_center(tilemap, next), CONVEYOR_BOMB_SPEED);
+        }
+    }
+
+    /// Server only: on ice, a thrown or carried bomb goes on one more tile the way it came.
+    fn glide(&mut self, owner: TRef<Area2D>) {
+        let (dx, dy) = match self.heading {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let cell = grid::to_cell(tilemap, owner.position());
+        let next = (cell.0 + dx, cell.1 + dy);
+        if hazard::is_ice(tilemap, cell) && !self.is_blocked(owner, world, next) {
+            self.send_move(owner, grid::cell_center(tilemap, next), KICK_SPEED);
+        }
+    }
+
+    /// Server only: a bomb moved onto a teleporter comes out of the other one, if it's free.
+    fn teleport(&mut self, owner: TRef<Area2D>) -> bool {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let cell = grid::to_cell(tilemap, owner.position());
+        match hazard::teleporter_exit(tilemap, cell) {
+            Some(exit) if !self.is_blocked(owner, world, exit) => {
+                self.teleporting = true;
+                self.send_move(owner, grid::cell_center(tilemap, exit), TELEPORT_SPEED);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Server only: bombs can't move into solid tiles, rocks, other bombs or players.
+    fn is_blocked(&self, owner: TRef<Area2D>, world: TRef<Node2D>, cell: Cell) -> bool {
+        let tilemap = grid::get_tilemap(world);
+        if !grid::is_floor(tilemap, cell) || grid::rocks(world).contains(&cell) {
+            return true;
+        }
+
+        // Bombs are the only `Area2D` children of `World`
+        for child in world.get_children().iter() {
+            let child = unsafe { child.try_to_object::<Node>().unwrap().assume_safe() };
+            if let Some(bomb) = child.cast::<Area2D>() {
+                if bomb.get_instance_id() != owner.get_instance_id()
+                    && !bomb.get("exploded").to_bool()
+                    && grid::to_cell(tilemap, bomb.position()) == cell
+                {
+                    return true;
+                }
+            }
+        }
+
+        unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+            .map(|player| unsafe { player.try_to_object::<Node2D>().unwrap().assume_safe() })
+            .any(|player| {
+                !player.get("eliminated").to_bool()
+                    && grid::to_cell(tilemap, player.position()) == cell
+            })
+    }
+
+    fn send_move(&mut self, owner: TRef<Area2D>, target: Vector2, speed: f64) {
+        owner.rpc("move_to", &[target.to_variant(), speed.to_variant()]);
+        self.move_to(owner, target, speed);
+    }
+
+    /// Server (Sync): the bomb moves to `target` at `speed` pixels per second.
+    #[export(rpc = "puppet")]
+    fn move_to(&mut self, owner: TRef<Area2D>, target: Vector2, speed: f64) {
+        if self.exploded {
+            return;
+        }
+
+        replay::record_event(
+            owner.as_ref(),
+            "move_to",
+            &[target.to_variant(), speed.to_variant()],
+        );
+
+        // Thrown by its carrier
+        self.carried_by = String::new();
+        self.target = Some(target);
+        self.speed = speed;
+        self.moving = true;
+    }
+
+    /// A kicked bomb slides on, a thrown one lies where it landed.
+    /// Teleporters send it on and it keeps going on ice.
+    fn arrived(&mut self, owner: TRef<Area2D>) {
+        self.target = None;
+        self.moving = false;
+
+        if self.solid {
+            self.release_passing(owner);
+            self.let_in_players(owner);
+        }
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.has_network_peer() && owner.is_network_master() {
+            if !self.teleporting && self.teleport(owner) {
+                return;
+            }
+            self.teleporting = false;
+
+            if self.slide_direction.is_some() {
+                self.slide(owner);
+            } else {
+                self.glide(owner);
             }
         }
     }
 
-    fn connect_signals(&self, owner: TRef<Area2D>) -> Result<(), GodotError> {
-        owner.connect(
-            "body_entered",
-            owner,
-            "_on_bomb_body_entered",
-            VariantArray::new_shared(),
-            0,
-        )?;
+    /// Server only: the bomb turns solid once its player is out of its tile, or out of the round.
+    /// Mines stay flat, players have to walk over them.
+    fn check_placer_left(&mut self, owner: TRef<Area2D>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
+            return;
+        }
 
-        owner.connect(
-            "body_exited",
-            owner,
-            "_on_bomb_body_exited",
-            VariantArray::new_shared(),
-            0,
-        )?;
+        if BombKind::from_i64(self.kind) == BombKind::Mine {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let placer = world.get_node(format!("Players/{}", self.from_player_id));
+        let left = match placer {
+            Some(placer) => {
+                let placer = unsafe { placer.assume_safe() }.cast::<Node2D>().unwrap();
+                placer.get("eliminated").to_bool() || !self.overlaps(owner, placer.position())
+            }
+            None => true,
+        };
 
-        Ok(())
+        if left {
+            let passing = self.players_inside(owner);
+            owner.rpc("solidify", &[passing.to_variant()]);
+            self.solidify(owner, passing);
+        }
+    }
+
+    /// Server (Sync): players are blocked by the bomb from now on,
+    /// except `passing` (player names), the server decides both for every peer.
+    #[export(rpc = "puppet")]
+    fn solidify(&mut self, owner: TRef<Area2D>, passing: VariantArray) {
+        if self.solid || self.exploded {
+            return;
+        }
+
+        self.solid = true;
+        // Layer of rocks and walls, players collide with it
+        self.get_body(owner).set_collision_layer(1);
+        self.let_through(owner, passing);
+    }
+
+    /// Server only: players a moving bomb runs into walk through it.
+    fn let_in_players(&mut self, owner: TRef<Area2D>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
+            return;
+        }
+
+        let passing = self.players_inside(owner);
+        if !passing.is_empty() {
+            owner.rpc("let_through", &[passing.to_variant()]);
+            self.let_through(owner, passing);
+        }
     }
 
+    /// Server (Sync): `passing` (player names) walk through the bomb until they are out of its tile.
+    #[export(rpc = "puppet")]
+    fn let_through(&mut self, owner: TRef<Area2D>, passing: VariantArray) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let body = self.get_body(owner);
+
+        for name in passing.iter() {
+            let name = name.to_string();
+            if self.passing.contains(&name) {
+                continue;
+            }
+
+            if let Some(player) = world.get_node(format!("Players/{}", name)) {
+                let player = unsafe { player.assume_safe() };
+                player
+                    .cast::<KinematicBody2D>()
+                    .unwrap()
+                    .add_collision_exception_with(body);
+                self.passing.push(name);
+            }
+        }
+    }
+
+    /// Server only: names of the players in the tile of the bomb not passing through it yet.
+    fn players_inside(&self, owner: TRef<Area2D>) -> VariantArray {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        let inside = VariantArray::new();
+        for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let player = unsafe { player.try_to_object::<Node2D>().unwrap().assume_safe() };
+            let name = player.name().to_string();
+            if !self.passing.contains(&name)
+                && !player.get("eliminated").to_bool()
+                && self.overlaps(owner, player.position())
+            {
+                inside.push(name);
+            }
+        }
+        inside.into_shared()
+    }
+
+    /// Players out of the tile of the bomb can't pass anymore, each peer sees it on its own.
+    fn release_passing(&mut self, owner: TRef<Area2D>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let body = self.get_body(owner);
+
+        for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let player = player.try_to_object::<KinematicBody2D>().unwrap();
+            let player = unsafe { player.assume_safe() };
+
+            let name = player.name().to_string();
+            let inside =
+                !player.get("eliminated").to_bool() && self.overlaps(owner, player.position());
+
+            if self.passing.contains(&name) && !inside {
+                player.remove_collision_exception_with(body);
+                self.passing.retain(|other| *other != name);
+            }
+        }
+    }
+
+    /// A player at `position` touches the tile of the bomb.
+    fn overlaps(&self, owner: TRef<Area2D>, position: Vector2) -> bool {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let reach =
+            grid::get_tilemap(world).cell_size() / 2.0 + Vector2::new(1.0, 1.0) * PLAYER_RADIUS;
+
+        let distance = position - owner.position();
+        distance.x.abs() < reach.x && distance.y.abs() < reach.y
+    }
+
+    /// Server only: an armed mine goes off under the first player standing on it.
+    fn check_mine(&mut self, owner: TRef<Area2D>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || !owner.is_network_master() {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+        let cell = grid::to_cell(tilemap, owner.position());
+
+        let stepped_on = unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+            .map(|player| unsafe { player.try_to_object::<Node2D>().unwrap().assume_safe() })
+            .any(|player| {
+                !player.get("eliminated").to_bool()
+                    && grid::to_cell(tilemap, player.position()) == cell
+            });
+
+        if stepped_on {
+            owner.rpc("trigger", &[]);
+            self.trigger(owner);
+        }
+    }
+
+    /// Server (Sync): a remote bomb set off by its player, a mine stepped on, or a bomb on a closing tile.
+    #[export(rpc = "puppet")]
+    fn trigger(&mut self, owner: TRef<Area2D>) {
+        if self.exploded {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "trigger", &[]);
+        self.explode(owner);
+    }
+
+    /// Server (Sync): caught in the blast of `set_off_by`, the fuse is skipped.
+    #[export(rpc = "puppet")]
+    fn detonate(&mut self, owner: TRef<Area2D>, chain_depth: i64, set_off_by: String) {
+        if self.exploded {
+            return;
+        }
+
+        // Owner of the bomb for the statistics, it's not in the bomb name
+        replay::record_event(
+            owner.as_ref(),
+            "detonate",
+            &[
+                chain_depth.to_variant(),
+                set_off_by.to_variant(),
+                self.from_player_id.to_variant(),
+            ],
+        );
+
+        self.chain_depth = chain_depth;
+        self.explode(owner);
+    }
+
+    /// Explosion over every hit tile, with its outline.
     #[export]
+    fn _draw(&self, owner: TRef<Area2D>) {
+        if self.blast.is_empty() {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+        let cell_size = tilemap.cell_size();
+
+        for cell in self.blast.iter() {
+            let top_left = grid::cell_center(tilemap, *cell) - cell_size / 2.0 - owner.position();
+            let rect = Rect2::new(
+                Point2::new(top_left.x, top_left.y),
+                Size2::new(cell_size.x, cell_size.y),
+            );
+
+            if let Some(texture) = &self.explosion_texture {
+                owner.draw_texture_rect(
+                    texture,
+                    rect,
+                    false,
+                    Color::rgb(1.0, 1.0, 1.0),
+                    false,
+                    Null::null(),
+                );
+            }
+            owner.draw_rect(
+                rect,
+                BombKind::from_i64(self.kind).blast_color(),
+                false,
+                2.0,
+                false,
+            );
+        }
+    }
+
     fn done(&self, owner: TRef<Area2D>) {
         owner.queue_free();
     }
+
+    fn get_body<'a>(&self, owner: TRef<'a, Area2D>) -> TRef<'a, StaticBody2D> {
+        let body = owner.get_node("Body").unwrap();
+        let body = unsafe { body.assume_safe() };
+        body.cast::<StaticBody2D>().unwrap()
+    }
+
+    fn get_animation(&self, owner: TRef<Area2D>) -> TRef<AnimationPlayer> {
+        let anim_player = owner.get_node("AnimationPlayer").unwrap();
+        let anim_player = unsafe { anim_player.assume_safe() };
+        anim_player.cast::<AnimationPlayer>().unwrap()
+    }
+
+    fn preload_instances(&mut self) {
+        let texture = ResourceLoader::godot_singleton()
+            .load("res://res/graphics/explosion.png", "Texture", false)
+            .unwrap();
+        let texture = unsafe { texture.assume_unique().into_shared() };
+
+        self.explosion_texture = texture.cast::<Texture>();
+    }
 }
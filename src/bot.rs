@@ -0,0 +1,387 @@
+use std::collections::{HashSet, VecDeque};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::grid::{self, Cell, DIRECTIONS};
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// How many steps a bot can walk before its own bomb explodes.
+const ESCAPE_STEPS: usize = 4;
+
+/// Bot is at the cell center when it's closer than this (in pixels).
+const CELL_REACHED_DISTANCE: f32 = 3.0;
+
+/// Tiles of the closing arena are dangerous this many seconds before they close.
+const CLOSING_WARNING: f64 = 2.0;
+
+/// Bot difficulty levels, sent by the lobby as an index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            0 => Difficulty::Easy,
+            2 => Difficulty::Hard,
+            _ => Difficulty::Normal,
+        }
+    }
+
+    pub fn to_i64(self) -> i64 {
+        match self {
+            Difficulty::Easy => 0,
+            Difficulty::Normal => 1,
+            Difficulty::Hard => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    /// Seconds between two decisions.
+    fn think_interval(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    /// Chance to wander around instead of doing something useful.
+    fn wander_chance(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.1,
+            Difficulty::Hard => 0.0,
+        }
+    }
+
+    /// Easy bots only go for rocks.
+    fn hunts_players(self) -> bool {
+        self != Difficulty::Easy
+    }
+}
+
+/// What a bot knows about the world, gathered every time it thinks.
+pub struct BotView {
+    pub me: Cell,
+    pub position: Vector2,
+    pub cell_size: Vector2,
+    /// Solid tiles, the closed tiles of the arena too
+    pub walls: HashSet<Cell>,
+    /// Walls, rocks and solid bombs
+    pub blocked: HashSet<Cell>,
+    pub rocks: HashSet<Cell>,
+    /// Cells which will be hit by live bombs or closed by the arena soon
+    pub danger: HashSet<Cell>,
+    /// Opponents which can be stunned
+    pub opponents: Vec<Cell>,
+    pub stunned: bool,
+    /// Of the bot's own bombs
+    pub blast_range: i64,
+}
+
+impl BotView {
+    /// Looking around from the `player` node.
+    pub fn look_around(player: TRef<KinematicBody2D>, stunned: bool) -> Self {
+        let world = unsafe { utils::get_world(player.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+        let to_cell = |position: Vector2| grid::to_cell(tilemap, position);
+
+        // The closing arena turns floor into walls, they are read every time
+        let walls = grid::walls(tilemap);
+        let mut blocked = walls.clone();
+
+        // Rocks
+        let rocks = grid::rocks(world);
+        blocked.extend(rocks.iter().cloned());
+
+        // Bombs are the only `Area2D` children of `World`
+        let mut danger = HashSet::new();
+        for child in world.get_children().iter() {
+            let child = child.try_to_object::<Node>().unwrap();
+            let child = unsafe { child.assume_safe() };
+            if let Some(bomb) = child.cast::<Area2D>() {
+                // Solid bombs are in the way, unless the bot is passing through
+                let bomb_cell = to_cell(bomb.position());
+                if bomb.get("solid").to_bool() && bomb_cell != to_cell(player.position()) {
+                    blocked.insert(bomb_cell);
+                }
+
+                danger.extend(grid::blast_cells(
+                    bomb_cell,
+                    bomb.get("blast_range").to_i64(),
+                    |cell| walls.contains(&cell),
+                    |cell| rocks.contains(&cell),
+                ));
+            }
+        }
+
+        // Tiles of the closing arena about to become walls
+        let func_args = VariantArray::new();
+        func_args.push(CLOSING_WARNING);
+        let closing = unsafe {
+            utils::get_gamestate_singleton(player.as_ref())
+                .callv("get_closing_cells", func_args.into_shared())
+        };
+        danger.extend(closing.to_array().iter().map(|cell| {
+            let cell = cell.to_vector2();
+            (cell.x as i32, cell.y as i32)
+        }));
+
+        // Opponents, teammates are left alone
+        let team = player.get("team").to_i64();
+        let mut opponents = Vec::new();
+        for other in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let other = other.try_to_object::<KinematicBody2D>().unwrap();
+            let other = unsafe { other.assume_safe() };
+            if other.name() != player.name()
+                && !other.get("stunned").to_bool()
+                && !other.get("eliminated").to_bool()
+                && (team == 0 || other.get("team").to_i64() != team)
+            {
+                opponents.push(to_cell(other.position()));
+            }
+        }
+
+        BotView {
+            me: to_cell(player.position()),
+            position: player.position(),
+            cell_size: tilemap.cell_size(),
+            walls,
+            blocked,
+            rocks,
+            danger,
+            opponents,
+            stunned,
+            blast_range: player.get("blast_range").to_i64(),
+        }
+    }
+
+    /// Cells hit by a bomb of the bot placed at `center`.
+    fn blast_from(&self, center: Cell) -> Vec<Cell> {
+        grid::blast_cells(
+            center,
+            self.blast_range,
+            |cell| self.walls.contains(&cell),
+            |cell| self.rocks.contains(&cell),
+        )
+    }
+
+    fn cell_center(&self, cell: Cell) -> Vector2 {
+        Vector2::new(
+            (cell.0 as f32 + 0.5) * self.cell_size.x,
+            (cell.1 as f32 + 0.5) * self.cell_size.y,
+        )
+    }
+
+    fn is_walkable(&self, cell: Cell) -> bool {
+        !self.blocked.contains(&cell)
+    }
+
+    /// Breadth-first search from the bot's cell to the nearest cell matching `goal`,
+    /// the path doesn't include the bot's cell.
+    fn find_path<F>(&self, avoid: &HashSet<Cell>, max_steps: usize, goal: F) -> Option<Vec<Cell>>
+    where
+        F: Fn(Cell) -> bool,
+    {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        visited.insert(self.me);
+        queue.push_back((self.me, Vec::new()));
+
+        while let Some((cell, path)) = queue.pop_front() {
+            if goal(cell) {
+                return Some(path);
+            }
+            if path.len() >= max_steps {
+                continue;
+            }
+
+            for (dx, dy) in DIRECTIONS.iter() {
+                let next = (cell.0 + dx, cell.1 + dy);
+                if !self.is_walkable(next) || avoid.contains(&next) || !visited.insert(next) {
+                    continue;
+                }
+
+                let mut next_path = path.clone();
+                next_path.push(next);
+                queue.push_back((next, next_path));
+            }
+        }
+
+        None
+    }
+
+    /// Is there a safe cell to run to if a bomb is placed right here?
+    fn can_escape_own_bomb(&self) -> bool {
+        let mut danger = self.danger.clone();
+        danger.extend(self.blast_from(self.me));
+
+        let no_danger = HashSet::new();
+        self.find_path(&no_danger, ESCAPE_STEPS, |cell| !danger.contains(&cell))
+            .is_some()
+    }
+
+    /// Is `target` caught by a bomb placed at the bot's cell?
+    fn in_blast_from_here(&self, target: Cell) -> bool {
+        self.blast_from(self.me).contains(&target)
+    }
+}
+
+/// Server-side brain of a bot player.
+pub struct BotBrain {
+    difficulty: Difficulty,
+    rng: StdRng,
+    /// Cells to walk through, the first one is the next
+    path: Vec<Cell>,
+    think_cooldown: f64,
+    /// Bomb is placed on a rising edge, the same way as the `ui_select` action
+    bombing: bool,
+}
+
+impl BotBrain {
+    pub fn new(difficulty: Difficulty) -> Self {
+        BotBrain {
+            difficulty,
+            rng: StdRng::from_entropy(),
+            path: Vec::new(),
+            think_cooldown: 0.0,
+            bombing: false,
+        }
+    }
+
+    /// Returns motion direction and whether the bomb button is pressed, like the player input does.
+    pub fn update(
+        &mut self,
+        player: TRef<KinematicBody2D>,
+        stunned: bool,
+        delta: f64,
+    ) -> (Vector2, bool) {
+        self.think_cooldown -= delta;
+
+        let view = BotView::look_around(player, stunned);
+
+        if self.bombing {
+            // Releasing the button
+            self.bombing = false;
+        } else if self.think_cooldown <= 0.0 {
+            self.think_cooldown = self.difficulty.think_interval();
+            self.think(&view);
+        }
+
+        (self.follow_path(&view), self.bombing)
+    }
+
+    fn think(&mut self, view: &BotView) {
+        if view.stunned {
+            self.path.clear();
+            return;
+        }
+
+        let no_avoid = HashSet::new();
+
+        // Running away from bombs comes first
+        if view.danger.contains(&view.me) {
+            if let Some(path) =
+                view.find_path(&no_avoid, usize::MAX, |cell| !view.danger.contains(&cell))
+            {
+                self.path = path;
+            }
+            return;
+        }
+
+        if self.rng.gen_bool(self.difficulty.wander_chance()) {
+            self.wander(view);
+            return;
+        }
+
+        // Bombing rocks and opponents around
+        let rock_in_range = view.rocks.iter().any(|rock| view.in_blast_from_here(*rock));
+        let opponent_in_range = self.difficulty.hunts_players()
+            && view
+                .opponents
+                .iter()
+                .any(|other| view.in_blast_from_here(*other));
+
+        if (rock_in_range || opponent_in_range) && view.can_escape_own_bomb() {
+            self.bombing = true;
+            self.path.clear();
+            return;
+        }
+
+        // Walking to the nearest place to bomb something, never through blasts
+        let hunts_players = self.difficulty.hunts_players();
+        let target = view.find_path(&view.danger, usize::MAX, |cell| {
+            view.blast_from(cell).iter().any(|hit| {
+                view.rocks.contains(hit) || (hunts_players && view.opponents.contains(hit))
+            })
+        });
+
+        match target {
+            Some(path) => self.path = path,
+            None => self.wander(view),
+        }
+    }
+
+    fn wander(&mut self, view: &BotView) {
+        let (dx, dy) = DIRECTIONS[self.rng.gen_range(0..DIRECTIONS.len())];
+        let next = (view.me.0 + dx, view.me.1 + dy);
+
+        if view.is_walkable(next) && !view.danger.contains(&next) {
+            self.path = vec![next];
+        }
+    }
+
+    fn follow_path(&mut self, view: &BotView) -> Vector2 {
+        let next = match self.path.first() {
+            Some(next) => *next,
+            // Staying at the center of the cell, so turns don't get stuck at corners
+            None => return direction_to(view.position, view.cell_center(view.me)),
+        };
+
+        // Something has blocked the way (a rock or a new bomb)
+        if !view.is_walkable(next) {
+            self.path.clear();
+            return Vector2::zero();
+        }
+
+        let target = view.cell_center(next);
+        if view.position.distance_to(target) < CELL_REACHED_DISTANCE {
+            self.path.remove(0);
+        }
+
+        direction_to(view.position, target)
+    }
+}
+
+/// Axis aligned direction, movement is on the grid.
+fn direction_to(from: Vector2, to: Vector2) -> Vector2 {
+    let diff = to - from;
+
+    if diff.length() < CELL_REACHED_DISTANCE {
+        Vector2::zero()
+    } else if diff.x.abs() > diff.y.abs() {
+        Vector2::new(diff.x.signum(), 0.0)
+    } else {
+        Vector2::new(0.0, diff.y.signum())
+    }
+}
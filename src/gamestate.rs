@@ -1,5 +1,20 @@
-use rand::{self, Rng};
-
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{self, Rng, SeedableRng};
+
+use crate::arena::{self, HurryUp};
+use crate::bot::Difficulty;
+use crate::grid;
+use crate::leaderboard::{Leaderboard, Standing};
+use crate::loadtest::LoadTest;
+use crate::map::{self, MapLayout};
+use crate::map_validator;
+use crate::mapgen;
+use crate::profile::Profile;
+use crate::replay::{self, ReplayRecorder};
+use crate::rules::{self, GameMode, MatchRules};
+use crate::settings::{Overtime, Settings};
+use crate::stats::MatchStats;
 use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
@@ -7,10 +22,25 @@ use gdnative::prelude::*;
 /// Default game server port. Can be any number between 1024 and 49151.
 /// Not on the list of registered or common ports as of November 2020:
 /// https://en.wikipedia.org/wiki/List_of_TCP_and_UDP_port_numbers
-const DEFAULT_PORT: i64 = 10567;
+pub const DEFAULT_PORT: i64 = 10567;
 
 /// Max number of players.
-const MAX_PEERS: i64 = 12;
+pub const MAX_PEERS: i64 = 12;
+
+/// Names for bots, so they look like everyone else.
+const BOT_NAMES: [&str; 12] = [
+    "Alex", "Sam", "Robin", "Charlie", "Jordan", "Taylor", "Casey", "Riley", "Morgan", "Quinn",
+    "Avery", "Jamie",
+];
+
+/// The world scene, map files replace its tiles, rocks and spawn points.
+const WORLD_MAP: &str = "res://scenes/World/World.tscn";
+
+/// Seconds between the end of a round and the start of the next one.
+const ROUND_BREAK: f64 = 3.0;
+
+/// Seconds between two clock syncs of a client.
+const CLOCK_SYNC_INTERVAL: f64 = 5.0;
 
 #[derive(NativeClass)]
 #[inherit(Node)]
@@ -28,6 +58,91 @@ pub struct GameState {
     /// Names for remote players in id:name format.
     #[property]
     players: Dictionary,
+
+    /// Server only: bots in id:difficulty format, they are in `players` too.
+    bots: Dictionary,
+    /// Server only: leaderboard keys in id:key format, players without a key aren't rated.
+    player_keys: Dictionary,
+
+    /// Seed of the current match, chosen by the server.
+    #[property]
+    match_seed: i64,
+    /// Map file of the current match.
+    #[property]
+    map_path: String,
+    /// Server only: every round is played on a generated map instead.
+    procedural_map: bool,
+    /// Contents of the map file, the server sends it to clients who don't have it.
+    map_text: Option<String>,
+    /// Map of the current round, `None` for the one baked into the world scene.
+    map_layout: Option<MapLayout>,
+    /// Server only: `create_world` arguments waiting for every client to have the map.
+    pending_round: Option<(i64, i64, Dictionary, Dictionary)>,
+    /// Server only: clients who didn't confirm they have the map of `pending_round`.
+    map_waiting: Vec<i64>,
+    /// Client only: hash of the map being downloaded.
+    map_download: Option<String>,
+    /// Maps of the lobby, sent by the server: map dictionaries with their `path`,
+    /// an empty `path` is a generated map.
+    #[property]
+    map_choices: VariantArray,
+    /// Index of `map_choices` of the next match, -1 if it isn't one of them.
+    #[property]
+    map_choice: i64,
+    /// Players vote for the map of the next match.
+    #[property]
+    map_vote: bool,
+    /// Votes of the lobby in id:index format.
+    #[property]
+    map_votes: Dictionary,
+    /// The server plays its map rotation, nobody picks the map.
+    #[property]
+    map_rotation: bool,
+    /// Server only: matches played, the map rotation goes on with every one.
+    rotation_index: usize,
+
+    /// Current round of the match, starting from 1.
+    #[property]
+    round: i64,
+    /// Server only: game mode chosen in the lobby.
+    game_mode: GameMode,
+    /// Number of teams chosen in the lobby, 0 means no teams.
+    #[property]
+    teams: i64,
+    /// Teams picked in the lobby in id:team format, players who didn't pick are balanced.
+    #[property]
+    team_choices: Dictionary,
+    /// Server only: blasts hit teammates.
+    friendly_fire: bool,
+    /// Rules of the current match, chosen by the server.
+    rules: MatchRules,
+    /// Round wins of the current match in id:wins format.
+    #[property]
+    round_wins: Dictionary,
+    /// Server only: the winner of the current round is known.
+    round_over: bool,
+    /// Server only: decides power-up drops, seeded by the round.
+    powerup_rng: Option<StdRng>,
+    /// Server only: seconds left in the current round, `None` without a time limit.
+    time_left: Option<f64>,
+    /// Server only: the arena is closing, `None` before the hurry-up phase.
+    hurry_up: Option<HurryUp>,
+    /// The time is up, the next point wins the round.
+    #[property]
+    sudden_death: bool,
+
+    /// Client only: server clock minus local clock in msec, `None` until the first sync.
+    clock_offset_msec: Option<i64>,
+    /// Client only: seconds until the next clock sync, `None` when not connected.
+    clock_sync_in: Option<f64>,
+
+    settings: Settings,
+    /// Replay of the current match, if it's being recorded.
+    recorder: Option<ReplayRecorder>,
+    /// Statistics of the current match, saved by the server.
+    stats: Option<MatchStats>,
+    /// Local profile, `player_name` comes from it.
+    profile: Profile,
 }
 
 #[methods]
@@ -40,11 +155,60 @@ impl GameState {
 
             player_name: "The Warrior".to_string(),
             players: Dictionary::new().into_shared(),
+            bots: Dictionary::new().into_shared(),
+            player_keys: Dictionary::new().into_shared(),
+
+            match_seed: 0,
+            map_path: map::DEFAULT_MAP.to_string(),
+            procedural_map: false,
+            map_text: None,
+            map_layout: None,
+            pending_round: None,
+            map_waiting: Vec::new(),
+            map_download: None,
+            map_choices: VariantArray::new_shared(),
+            map_choice: -1,
+            map_vote: false,
+            map_votes: Dictionary::new().into_shared(),
+            map_rotation: false,
+            rotation_index: 0,
+
+            round: 0,
+            game_mode: GameMode::Score,
+            teams: 0,
+            team_choices: Dictionary::new().into_shared(),
+            friendly_fire: true,
+            rules: MatchRules::default(),
+            round_wins: Dictionary::new().into_shared(),
+            round_over: false,
+            powerup_rng: None,
+            time_left: None,
+            hurry_up: None,
+            sudden_death: false,
+
+            clock_offset_msec: None,
+            clock_sync_in: None,
+
+            settings: Settings::default(),
+            recorder: None,
+            stats: None,
+            profile: Profile::default(),
         }
     }
 
     #[export]
     fn _ready(&mut self, owner: TRef<Node>) {
+        self.settings = Settings::load();
+        if let Some(path) = self.settings.validate_map.as_ref() {
+            let tree = unsafe { utils::get_tree(owner.as_ref()) };
+            map_validator::run(tree, path, self.settings.allow_shared_spawns);
+            return;
+        }
+
+        self.map_path = self.settings.map_path.clone();
+        self.procedural_map = self.settings.procedural_map;
+        self.profile = Profile::load();
+        self.player_name = self.profile.name.clone();
         self.preload_instances();
 
         if let Err(e) = self.connect_network_signals(owner) {
@@ -53,6 +217,21 @@ impl GameState {
                 e
             );
         }
+
+        if let Some(clients) = self.settings.loadtest_clients {
+            let loadtest = LoadTest::new_instance(clients, self.settings.connect_address.clone());
+
+            // The root is still busy setting up its children
+            let root = unsafe { utils::get_root(owner.as_ref()) };
+            unsafe {
+                root.call_deferred("add_child", &[loadtest.to_variant()]);
+            }
+        }
+    }
+
+    /// Max number of players, can be raised for load tests.
+    fn max_peers(&self) -> i64 {
+        self.settings.max_peers.unwrap_or(MAX_PEERS)
     }
 
     #[export]
@@ -62,7 +241,7 @@ impl GameState {
         let tree = unsafe { utils::get_tree(owner.as_ref()) };
 
         let host = NetworkedMultiplayerENet::new();
-        if let Err(e) = host.create_server(DEFAULT_PORT, MAX_PEERS, 0, 0) {
+        if let Err(e) = host.create_server(DEFAULT_PORT, self.max_peers(), 0, 0) {
             godot_error!(
                 "`Gamestate` => GodotError at `create_server` function: {}",
                 e
@@ -70,9 +249,13 @@ impl GameState {
         }
 
         self.player_name = player_name.to_string();
+        self.save_profile_name();
 
+        // Clients only talk to the server, it passes on what the others need
+        host.set_server_relay_enabled(false);
         tree.set_network_peer(host);
 
+        self.load_map_choices();
         self.self_register_player(owner);
 
         godot_print!("Game hosted!");
@@ -94,6 +277,7 @@ impl GameState {
         }
 
         self.player_name = player_name.to_string();
+        self.save_profile_name();
 
         tree.set_network_peer(client);
 
@@ -144,44 +328,218 @@ impl GameState {
     }
 
     /// # The First Step
-    /// Rpc-ing `create_world` to all clients and to server
+    /// Rpc-ing `create_world` to all clients and to server,
+    /// also used by the `Rematch` button with the same roster.
     #[export]
     fn start_game(&mut self, owner: TRef<Node>) {
         // Tree
         let tree = unsafe { utils::get_tree(owner.as_ref()) };
 
         if tree.is_network_server() {
-            self.create_world(owner);
-            owner.rpc("create_world", &[]);
+            self.rules = MatchRules {
+                mode: self.game_mode,
+                rounds_to_win: self.settings.rounds_to_win.max(1),
+                lives: self.settings.lives.max(1),
+                teams: self.teams,
+                friendly_fire: self.friendly_fire,
+                team_of: self.balance_teams(),
+                powerups: self.settings.powerups.clone(),
+                fuse_time: self.settings.fuse_time,
+            };
+
+            if self.map_rotation {
+                self.select_rotation_map();
+            } else if self.map_vote {
+                if let Some(index) = self.count_map_votes() {
+                    self.select_map(index);
+                }
+                // A new vote for the next match
+                self.map_votes = Dictionary::new_shared();
+            }
+            self.send_maps(owner);
+
+            self.map_text = if self.procedural_map {
+                None
+            } else {
+                Self::read_map(&self.map_path)
+            };
+
+            self.start_round(owner, 1);
+        }
+    }
+
+    /// Server only: teams picked in the lobby are kept,
+    /// everyone else joins the smallest team.
+    fn balance_teams(&self) -> Dictionary {
+        let team_of = Dictionary::new();
+        if self.teams == 0 {
+            return team_of.into_shared();
+        }
+
+        let mut ids: Vec<i64> = self.players.keys().iter().map(|id| id.to_i64()).collect();
+        ids.sort_unstable();
+
+        let mut sizes = vec![0; self.teams as usize];
+        let mut unassigned = Vec::new();
+        for id in ids {
+            let choice = self.team_choices.get(id).to_i64();
+            if choice >= 1 && choice <= self.teams {
+                team_of.insert(id, choice);
+                sizes[choice as usize - 1] += 1;
+            } else {
+                unassigned.push(id);
+            }
+        }
+
+        for id in unassigned {
+            let smallest = (0..sizes.len()).min_by_key(|team| sizes[*team]).unwrap();
+            team_of.insert(id, smallest as i64 + 1);
+            sizes[smallest] += 1;
+        }
+
+        team_of.into_shared()
+    }
+
+    /// Server only: every round is played in a new world.
+    fn start_round(&mut self, owner: TRef<Node>, round: i64) {
+        let seed = rand::thread_rng().gen::<u32>() as i64;
+
+        let rules = self.rules.to_dictionary();
+
+        // Generated maps are sent whole, map files by their hash.
+        // Empty for the map baked into the world scene.
+        let map = if self.procedural_map {
+            let params = self.settings.map_params;
+            mapgen::generate(seed, params, self.players.len() as usize).to_dictionary()
+        } else if let Some(text) = &self.map_text {
+            let map = Dictionary::new();
+            map.insert("path", self.map_path.as_str());
+            map.insert("hash", map::hash_map_text(text));
+            map.into_shared()
+        } else {
+            Dictionary::new_shared()
+        };
+
+        // The round starts once every client has the map file
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let peers: Vec<i64> = tree
+            .get_network_connected_peers()
+            .read()
+            .iter()
+            .map(|id| *id as i64)
+            .collect();
+        if map.contains("hash") && !peers.is_empty() {
+            self.map_waiting = peers;
+            owner.rpc("check_map", &[map.get("hash")]);
+            self.pending_round = Some((seed, round, rules, map));
+            return;
         }
+
+        self.launch_round(owner, seed, round, rules, map);
+    }
+
+    /// Server only: `create_world` here and on every client.
+    fn launch_round(
+        &mut self,
+        owner: TRef<Node>,
+        seed: i64,
+        round: i64,
+        rules: Dictionary,
+        map: Dictionary,
+    ) {
+        self.create_world(owner, seed, round, rules.new_ref(), map.new_ref());
+        owner.rpc(
+            "create_world",
+            &[
+                seed.to_variant(),
+                round.to_variant(),
+                rules.to_variant(),
+                map.to_variant(),
+            ],
+        );
     }
 
     /// # Second step
-    /// Creating world
+    /// Creating world, the previous round's one is replaced
     #[export(rpc = "remote")]
-    fn create_world(&self, owner: TRef<Node>) {
-        godot_print!("creating world...");
+    fn create_world(
+        &mut self,
+        owner: TRef<Node>,
+        seed: i64,
+        round: i64,
+        rules: Dictionary,
+        map: Dictionary,
+    ) {
+        godot_print!("creating world (seed: {}, round: {})...", seed, round);
+
+        self.map_layout = match self.load_map(&map) {
+            Ok(layout) => layout,
+            Err(e) => {
+                self.game_error(owner, &format!("Can't load the map: {}", e));
+                return;
+            }
+        };
+
+        self.match_seed = seed;
+        self.round = round;
+        self.rules = MatchRules::from_dictionary(&rules);
+        self.round_over = false;
+        self.sudden_death = false;
+        self.time_left = None;
+        self.hurry_up = None;
+        self.powerup_rng = Some(StdRng::seed_from_u64(seed as u64));
+
+        if round == 1 {
+            unsafe {
+                self.round_wins.clear();
+            }
+            self.save_stats(owner, None); // Unfinished match, if any
+            self.stats = Some(MatchStats::start(seed, &self.map_path, &self.players));
+            // The whole match goes into a single replay
+            self.start_recording(owner);
+        }
+
+        // The replay viewer rebuilds the world from here, generated maps change every round
+        if let Some(recorder) = &self.recorder {
+            let layout = match &self.map_layout {
+                Some(layout) => layout.to_dictionary(),
+                None => Dictionary::new_shared(),
+            };
+
+            let args = VariantArray::new();
+            args.push(round);
+            args.push(seed);
+            args.push(layout);
+            recorder.record(".", "round_started", &args.into_shared());
+        }
 
         // let tree = unsafe { utils::get_tree(owner.as_ref()) };
         let root = unsafe { utils::get_root(owner.as_ref()) };
 
-        // World
-        let world_packed_scene = unsafe { self.preload_world.assume_safe() };
-        let world_packed_scene = world_packed_scene.cast::<PackedScene>().unwrap();
-
-        // instancing world packed scene
-        let world = world_packed_scene
-            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
-            .unwrap();
-        let world = unsafe { world.assume_safe() };
-        //
+        // Previous round
+        if let Some(old_world) = root.get_node("World") {
+            root.remove_child(old_world);
+            unsafe { old_world.assume_safe() }.queue_free();
+        }
 
+        // World
+        let world = Self::instance_world(self.preload_world.clone());
+        if let Some(layout) = &self.map_layout {
+            layout.apply(world);
+        }
         root.add_child(world, false);
         //
 
-        // Player
-        let player_packed_scene = unsafe { self.preload_player.assume_safe() };
-        let player_packed_scene = player_packed_scene.cast::<PackedScene>().unwrap();
+        // Round
+        let round_label = world.get_node("Round").unwrap();
+        let round_label = unsafe { round_label.assume_safe() };
+        let round_label = round_label.cast::<Label>().unwrap();
+        round_label.set_text(format!(
+            "{} - Round {} (first to {})",
+            self.rules.mode.name(),
+            round,
+            self.rules.rounds_to_win
+        ));
         //
 
         // Score
@@ -196,27 +554,43 @@ impl GameState {
         let players = players.cast::<Node2D>().unwrap();
         //
 
-        let mut rng = rand::thread_rng();
-
         for (player_id, player_name) in self.players.iter() {
             godot_print!("creating {} player...", player_id.to_i64());
 
-            // instancing player packed scene
-            let new_player = player_packed_scene
-                .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
-                .unwrap();
-            let new_player = unsafe { new_player.assume_safe() };
-            let new_player = new_player.cast::<KinematicBody2D>().unwrap();
-            //
+            let new_player = self.instance_player(&player_id, &player_name);
+
+            // Bots are controlled by the server, clients don't know about them
+            let is_bot = self.bots.contains(player_id.clone());
+            if is_bot {
+                new_player.set_network_master(1, true);
 
-            new_player.set_name(player_id.to_godot_string()); // Use unique ID as node name.
-            new_player.set_network_master(player_id.to_i64(), true);
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(self.bots.get(player_id.clone()));
+                    new_player.callv("make_bot", func_args);
+                }
+            } else {
+                new_player.set_network_master(player_id.to_i64(), true);
+            }
 
             unsafe {
-                if player_id.to_i64() == utils::get_tree(owner.as_ref()).get_network_unique_id() {
+                if is_bot
+                    || player_id.to_i64() == utils::get_tree(owner.as_ref()).get_network_unique_id()
+                {
+                    // Players take turns on the spawn points of map files
+                    let spawn_index = match &self.map_layout {
+                        Some(layout) if !layout.spawns.is_empty() => {
+                            spawn_slot(&self.players, &player_id) % layout.spawns.len()
+                        }
+                        _ => {
+                            let mut rng = StdRng::seed_from_u64((seed ^ player_id.to_i64()) as u64);
+                            rng.gen_range(0..=11)
+                        }
+                    };
+
                     // world_spawn_point
                     let world_spawn_point: TRef<Position2D> = world
-                        .get_node(format!("SpawnPoints/{}", rng.gen_range(0..=11)))
+                        .get_node(format!("SpawnPoints/{}", spawn_index))
                         .unwrap()
                         .assume_safe()
                         .cast::<Position2D>()
@@ -227,18 +601,34 @@ impl GameState {
                 }
             }
 
-            let func_args = VariantArray::new_shared();
-            unsafe {
-                func_args.push(player_name.clone());
-                new_player.callv("set_player_name", func_args);
+            if self.rules.teams > 0 {
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(self.rules.team_of.get(player_id.clone()));
+                    func_args.push(self.rules.friendly_fire);
+                    new_player.callv("set_team", func_args);
+                }
+            } else if player_id.to_i64()
+                == unsafe { utils::get_tree(owner.as_ref()) }.get_network_unique_id()
+            {
+                // Preferred color is only seen locally
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(self.profile.color);
+                    new_player.callv("set_player_color", func_args);
+                }
             }
 
-            players.add_child(new_player, false);
-
-            unsafe {
-                self.players.insert(player_id.to_i64(), new_player);
+            if self.rules.mode == GameMode::Elimination {
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(self.rules.lives);
+                    new_player.callv("set_lives", func_args);
+                }
             }
 
+            players.add_child(new_player, false);
+
             let func_args = VariantArray::new_shared();
             unsafe {
                 func_args.push(player_id.clone());
@@ -250,163 +640,1626 @@ impl GameState {
             godot_print!("player {} created!", player_id.to_i64());
         }
 
+        // Team totals
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(self.rules.team_of.new_ref());
+            score.callv("set_teams", func_args);
+        }
+
+        // The server counts down, peers are told the seconds left
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.is_network_server() && self.settings.time_limit > 0 {
+            self.time_left = Some(self.settings.time_limit as f64);
+            self.update_timer(owner, self.settings.time_limit, false);
+            owner.rpc(
+                "update_timer",
+                &[self.settings.time_limit.to_variant(), false.to_variant()],
+            );
+        }
+
+        unsafe {
+            owner.call_deferred("update_hud", &[]);
+        }
+
         let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
         lobby.hide();
 
         godot_print!("world created!");
     }
 
-    // Network signals
-
-    /// Callback from SceneTree.
+    /// Watching a replay: the world is built from the replay header without any network peer,
+    /// then `ReplayViewer` plays the recorded events.
     #[export]
-    fn _player_connected(&self, owner: TRef<Node>, id: i64) {
-        godot_print!("New player (id: {}) connected", id);
-
-        // Tree
-        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+    fn watch_replay(&mut self, owner: TRef<Node>, path: String) -> bool {
+        if let Err(e) = self.create_replay_world(owner, &path) {
+            godot_error!("`Gamestate` => can't watch replay: {}", e);
+            return false;
+        }
 
-        // Registration of a client beings here,
-        // tell the connected player that we are here.
-        owner.rpc_id(
-            id,
-            "register_player",
-            &[
-                tree.get_network_unique_id().to_variant(),
-                self.player_name.to_variant(),
-            ],
-        );
+        true
     }
 
-    /// Callback from SceneTree.
-    /// player disconnected
+    /// Opening the map editor in an empty world.
     #[export]
-    fn _player_disconnected(&self, owner: TRef<Node>, id: i64) {
-        godot_print!("Player (id: {}) disconnected", id);
+    fn open_map_editor(&mut self, owner: TRef<Node>) {
+        let root = unsafe { utils::get_root(owner.as_ref()) };
 
-        unsafe {
-            if !utils::get_lobby(owner.as_ref()).is_visible() {
-                self.game_error(owner, "Player disconnected");
-            }
-        }
+        // World
+        let world = Self::instance_world(self.preload_world.clone());
+        root.add_child(world, false);
+        //
 
-        self.unregister_player(owner, id);
-    }
+        // Map editor
+        let editor_scene = ResourceLoader::godot_singleton()
+            .load(
+                "res://scenes/MapEditor/MapEditor.tscn",
+                "PackedScene",
+                false,
+            )
+            .unwrap();
+        let editor_scene = unsafe { editor_scene.assume_safe() };
+        let editor_scene = editor_scene.cast::<PackedScene>().unwrap();
 
-    /// Callback from SceneTree, only for clients (not server).
-    /// We just connected to a server
-    #[export]
-    fn _connected_ok(&self, owner: TRef<Node>) {
-        godot_print!("user connected to the server successfully");
+        let editor = editor_scene
+            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+            .unwrap();
+        world.add_child(editor, false);
+        //
 
-        self.self_register_player(owner);
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        lobby.hide();
     }
 
-    /// Callback from SceneTree, only for clients (not server).
+    /// Playing a map of the editor against bots, hosted here.
     #[export]
-    fn _server_disconnected(&self, owner: TRef<Node>) {
-        self.game_error(owner, "Server disconnected");
-    }
+    fn test_map(&mut self, owner: TRef<Node>, path: String, bots: i64) {
+        godot_print!("test playing map {} against {} bots...", path, bots);
 
-    /// Callback from SceneTree, only for clients (not server).
-    #[export]
-    fn _connected_fail(&self, owner: TRef<Node>) {
-        self.game_error(owner, "User connected to the server failure");
+        let player_name = self.player_name.clone();
+        self.host_game(owner, player_name.to_variant());
+
+        for _ in 0..bots {
+            self.add_bot(owner, Difficulty::Normal.to_i64());
+        }
+
+        self.map_path = path;
+        self.procedural_map = false;
+        self.map_rotation = false;
+        self.map_vote = false;
+        self.start_game(owner);
     }
 
-    fn game_error(&self, owner: TRef<Node>, error: &str) {
+    fn create_replay_world(&mut self, owner: TRef<Node>, path: &str) -> Result<(), String> {
+        let header = replay::load_header(path)?;
+
+        godot_print!("watching replay {} (map: {})...", path, header.map);
+        self.rules = MatchRules::default();
+
         let tree = unsafe { utils::get_tree(owner.as_ref()) };
-        tree.set_network_peer(Null::null()); // Remove peer
+        tree.set_network_peer(Null::null());
 
-        unsafe {
-            self.players.clear();
+        let root = unsafe { utils::get_root(owner.as_ref()) };
+
+        // World
+        let world = Self::instance_world(self.preload_world.clone());
+        if let Some(layout) = &header.layout {
+            layout.apply(world);
         }
+        root.add_child(world, false);
+        //
+
+        // Score
+        let score = world.get_node("Score").unwrap();
+        let score = unsafe { score.assume_safe() };
+        //
+
+        // Players
+        let players = world.get_node("Players").unwrap();
+        let players = unsafe { players.assume_safe() };
+        //
+
+        for (player_id, player_name) in header.roster.iter() {
+            let new_player =
+                self.instance_player(&player_id.to_variant(), &player_name.to_variant());
+            players.add_child(new_player, false);
+
+            let func_args = VariantArray::new_shared();
+            unsafe {
+                func_args.push(player_id);
+                func_args.push(player_name.as_str());
+
+                score.callv("add_player", func_args);
+            }
+        }
+
+        // Replay viewer
+        let viewer_scene = ResourceLoader::godot_singleton()
+            .load(
+                "res://scenes/Replay/ReplayViewer.tscn",
+                "PackedScene",
+                false,
+            )
+            .unwrap();
+        let viewer_scene = unsafe { viewer_scene.assume_safe() };
+        let viewer_scene = viewer_scene.cast::<PackedScene>().unwrap();
+
+        let viewer = viewer_scene
+            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+            .unwrap();
+        let viewer = unsafe { viewer.assume_safe() };
+        world.add_child(viewer, false);
 
-        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
         let func_args = VariantArray::new_shared();
-        unsafe {
-            func_args.push(error);
-            lobby.callv("game_error", func_args);
+        let loaded = unsafe {
+            func_args.push(path);
+            viewer.callv("load", func_args).to_bool()
+        };
+
+        if !loaded {
+            world.queue_free();
+            return Err(format!("can't load replay {}", path));
         }
+        //
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        lobby.hide();
+
+        Ok(())
     }
 
-    #[export]
-    fn end_game(&self, owner: TRef<Node>) {
-        let tree = unsafe { utils::get_tree(owner.as_ref()) };
-        tree.set_network_peer(Null::null()); // Remove peer
+    /// Map of `create_world`, `None` for the map baked into the world scene.
+    fn load_map(&self, map: &Dictionary) -> Result<Option<MapLayout>, String> {
+        if map.contains("tiles") {
+            return MapLayout::from_dictionary(map).map(Some);
+        }
 
-        unsafe {
-            self.players.clear();
+        if !map.contains("hash") {
+            return Ok(None);
         }
 
-        unsafe {
-            utils::get_world(owner.as_ref()).queue_free();
-            utils::get_lobby(owner.as_ref()).callv("game_ended", VariantArray::new_shared());
+        // `check_map` made sure it's here
+        let hash = map.get("hash").to_string();
+        match &self.map_text {
+            Some(text) if self.has_map_text(&hash) => MapLayout::parse(text).map(Some),
+            _ => Err(format!("map {} wasn't received", hash)),
         }
     }
 
-    // Register the new player
-    #[export(rpc = "remote")]
-    fn register_player(&self, owner: TRef<Node>, id: Variant, p_name: Variant) {
-        godot_print!(
-            "register player {} (id:{})",
-            p_name.to_string(),
-            id.to_i64()
-        );
-
-        unsafe {
-            self.players.insert(id, p_name);
+    fn has_map_text(&self, hash: &str) -> bool {
+        match &self.map_text {
+            Some(text) => map::hash_map_text(text) == hash,
+            None => false,
         }
+    }
 
-        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
-        unsafe {
-            lobby.callv("refresh_lobby", VariantArray::new_shared());
+    /// Server only: contents of a map file, checked before the match starts.
+    fn read_map(path: &str) -> Option<String> {
+        let text = map::read_map_file(path).and_then(|text| {
+            MapLayout::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+            Ok(text)
+        });
+
+        match text {
+            Ok(text) => Some(text),
+            Err(e) => {
+                godot_error!(
+                    "`Gamestate` => can't load map, using the world scene: {}",
+                    e
+                );
+                None
+            }
         }
     }
 
-    fn unregister_player(&self, owner: TRef<Node>, id: i64) {
-        godot_print!("unregister player id:{}", id);
-
-        unsafe {
-            self.players.erase(id);
+    /// Sending the map file to a client who doesn't have it.
+    #[export(rpc = "remote")]
+    fn request_map(&self, owner: TRef<Node>, hash: String) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
         }
 
-        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
-        unsafe {
-            lobby.callv("refresh_lobby", VariantArray::new_shared());
+        if self.has_map_text(&hash) {
+            let text = self.map_text.as_ref().unwrap();
+            owner.rpc_id(
+                tree.get_rpc_sender_id(),
+                "receive_map",
+                &[text.to_variant()],
+            );
         }
     }
 
-    fn self_register_player(&self, owner: TRef<Node>) {
-        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
-        unsafe {
-            lobby.callv("change_to_players_lobby", VariantArray::new_shared());
+    /// Server (Sync): the next round is played on the map file with `hash`,
+    /// clients who don't have it download it first.
+    #[export(rpc = "puppet")]
+    fn check_map(&mut self, owner: TRef<Node>, hash: String) {
+        if !self.has_map_text(&hash) {
+            match map::find_map(&hash).and_then(|path| map::read_map_file(&path).ok()) {
+                Some(text) => self.map_text = Some(text),
+                None => {
+                    godot_print!("downloading map {}...", hash);
+                    owner.rpc_id(1, "request_map", &[hash.to_variant()]);
+                    self.map_download = Some(hash);
+                    return;
+                }
+            }
         }
 
-        let tree = unsafe { utils::get_tree(owner.as_ref()) };
-        self.register_player(
-            owner,
-            tree.get_network_unique_id().to_variant(),
-            self.player_name.to_variant(),
-        );
+        owner.rpc_id(1, "map_ready", &[]);
     }
 
-    /// Preloading `PackedScene` instances (World, Player)
-    fn preload_instances(&mut self) {
-        // World
-        let world_scene = ResourceLoader::godot_singleton()
-            .load("res://scenes/World/World.tscn", "PackedScene", false)
-            .unwrap();
-        let world_scene = unsafe { world_scene.assume_unique().into_shared() };
+    #[export(rpc = "puppet")]
+    fn receive_map(&mut self, owner: TRef<Node>, text: String) {
+        let hash = match self.map_download.take() {
+            Some(hash) => hash,
+            None => return,
+        };
 
-        self.preload_world = world_scene.cast::<PackedScene>().unwrap();
+        if map::hash_map_text(&text) != hash {
+            self.game_error(owner, "The map from the server doesn't match its hash");
+            return;
+        }
 
-        // Player
-        let player_scene = ResourceLoader::godot_singleton()
-            .load("res://scenes/Player/Player.tscn", "PackedScene", false)
-            .unwrap();
-        let player_scene = unsafe { player_scene.assume_unique().into_shared() };
+        match map::save_downloaded_map(&text) {
+            Ok(path) => godot_print!("map saved: {}", path),
+            Err(e) => godot_error!(
+                "`Gamestate` => GodotError at `save_downloaded_map` function: {}",
+                e
+            ),
+        }
 
-        self.preload_player = player_scene.cast::<PackedScene>().unwrap();
+        self.map_text = Some(text);
+        owner.rpc_id(1, "map_ready", &[]);
     }
+
+    /// Server: a client has the map of the next round.
+    #[export(rpc = "remote")]
+    fn map_ready(&mut self, owner: TRef<Node>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        self.map_acknowledged(owner, tree.get_rpc_sender_id());
+    }
+
+    /// Server only: the round starts when the last client has the map, or left.
+    fn map_acknowledged(&mut self, owner: TRef<Node>, id: i64) {
+        self.map_waiting.retain(|waiting| *waiting != id);
+        if !self.map_waiting.is_empty() {
+            return;
+        }
+
+        if let Some((seed, round, rules, map)) = self.pending_round.take() {
+            self.launch_round(owner, seed, round, rules, map);
+        }
+    }
+
+    // Network signals
+
+    /// Callback from SceneTree.
+    #[export]
+    fn _player_connected(&self, owner: TRef<Node>, id: i64) {
+        godot_print!("New player (id: {}) connected", id);
+
+        // Tree
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+
+        // Clients only see the server connect,
+        // registration of a client begins here.
+        if !tree.is_network_server() {
+            owner.rpc_id(
+                id,
+                "register_player",
+                &[
+                    tree.get_network_unique_id().to_variant(),
+                    self.player_name.to_variant(),
+                ],
+            );
+            return;
+        }
+
+        // Bot ids aren't reserved by ENet, a peer can't share one
+        if self.bots.contains(id) {
+            godot_print!("Player (id: {}) rejected, a bot has the same id", id);
+            if let Some(peer) = tree.network_peer() {
+                let peer = unsafe { peer.assume_safe() };
+                if let Some(host) = peer.cast::<NetworkedMultiplayerENet>() {
+                    host.disconnect_peer(id, false);
+                }
+            }
+            return;
+        }
+
+        // Everyone in the lobby, the server and bots included, looks the same to the new player
+        for (player_id, name) in self.players.iter() {
+            owner.rpc_id(id, "register_player", &[player_id, name]);
+        }
+
+        owner.rpc_id(
+            id,
+            "update_teams",
+            &[self.teams.to_variant(), self.team_choices.to_variant()],
+        );
+        owner.rpc_id(id, "update_maps", &self.maps_args());
+    }
+
+    /// Server only: adding a bot to the lobby.
+    /// `difficulty` is an index of `Difficulty::ALL`.
+    #[export]
+    fn add_bot(&self, owner: TRef<Node>, difficulty: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() || self.players.len() as i64 >= self.max_peers() {
+            return;
+        }
+
+        // Random id, like the ones peers get,
+        // peers connecting later with the same id are rejected
+        let peers = tree.get_network_connected_peers();
+        let peers = peers.read();
+        let mut rng = rand::thread_rng();
+        let mut bot_id = rng.gen_range(2..i32::MAX) as i64;
+        while self.players.contains(bot_id) || peers.iter().any(|peer| *peer as i64 == bot_id) {
+            bot_id = rng.gen_range(2..i32::MAX) as i64;
+        }
+
+        let taken_names: Vec<String> = self
+            .players
+            .values()
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        let bot_name = BOT_NAMES
+            .iter()
+            .find(|name| !taken_names.iter().any(|taken| taken == *name))
+            .unwrap_or(&BOT_NAMES[0]);
+
+        godot_print!(
+            "adding {} bot {} (id: {})",
+            Difficulty::from_i64(difficulty).name(),
+            bot_name,
+            bot_id
+        );
+
+        unsafe {
+            self.bots.insert(bot_id, difficulty);
+        }
+
+        self.register_player(owner, bot_id.to_variant(), bot_name.to_variant());
+        owner.rpc(
+            "register_player",
+            &[bot_id.to_variant(), bot_name.to_variant()],
+        );
+    }
+
+    /// Server only: removing the last added bot.
+    #[export]
+    fn remove_bot(&self, owner: TRef<Node>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let bot_id = match self.bots.keys().iter().last() {
+            Some(bot_id) => bot_id.to_i64(),
+            None => return,
+        };
+
+        unsafe {
+            self.bots.erase(bot_id);
+        }
+
+        self.unregister_player(owner, bot_id);
+        owner.rpc("unregister_player", &[bot_id.to_variant()]);
+    }
+
+    /// Callback from SceneTree.
+    /// player disconnected
+    #[export]
+    fn _player_disconnected(&mut self, owner: TRef<Node>, id: i64) {
+        godot_print!("Player (id: {}) disconnected", id);
+
+        // A peer rejected for the id of a bot, the bot stays
+        if self.bots.contains(id) {
+            return;
+        }
+
+        unsafe {
+            if !utils::get_lobby(owner.as_ref()).is_visible() {
+                self.game_error(owner, "Player disconnected");
+            }
+        }
+
+        self.unregister_player(owner, id);
+
+        // Clients don't see each other disconnect.
+        // The round doesn't wait for the map on a player who left.
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.has_network_peer() && tree.is_network_server() {
+            owner.rpc("unregister_player", &[id.to_variant()]);
+            self.map_acknowledged(owner, id);
+        }
+    }
+
+    /// Callback from SceneTree, only for clients (not server).
+    /// We just connected to a server
+    #[export]
+    fn _connected_ok(&mut self, owner: TRef<Node>) {
+        godot_print!("user connected to the server successfully");
+
+        self.clock_sync_in = Some(0.0);
+        self.self_register_player(owner);
+    }
+
+    /// Callback from SceneTree, only for clients (not server).
+    #[export]
+    fn _server_disconnected(&mut self, owner: TRef<Node>) {
+        self.game_error(owner, "Server disconnected");
+    }
+
+    /// Callback from SceneTree, only for clients (not server).
+    #[export]
+    fn _connected_fail(&mut self, owner: TRef<Node>) {
+        self.game_error(owner, "User connected to the server failure");
+    }
+
+    fn game_error(&mut self, owner: TRef<Node>, error: &str) {
+        self.save_stats(owner, None);
+        self.stop_recording();
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        tree.set_network_peer(Null::null()); // Remove peer
+        self.clock_offset_msec = None;
+        self.clock_sync_in = None;
+        self.pending_round = None;
+        self.map_waiting.clear();
+        self.map_download = None;
+
+        unsafe {
+            self.players.clear();
+            self.bots.clear();
+            self.player_keys.clear();
+            self.team_choices.clear();
+        }
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(error);
+            lobby.callv("game_error", func_args);
+        }
+    }
+
+    #[export]
+    fn end_game(&mut self, owner: TRef<Node>) {
+        self.save_stats(owner, None);
+        self.stop_recording();
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        tree.set_network_peer(Null::null()); // Remove peer
+        self.clock_offset_msec = None;
+        self.clock_sync_in = None;
+        self.pending_round = None;
+        self.map_waiting.clear();
+        self.map_download = None;
+
+        unsafe {
+            self.players.clear();
+            self.bots.clear();
+            self.player_keys.clear();
+            self.team_choices.clear();
+        }
+
+        unsafe {
+            utils::get_world(owner.as_ref()).queue_free();
+            utils::get_lobby(owner.as_ref()).callv("game_ended", VariantArray::new_shared());
+        }
+    }
+
+    // Register the new player
+    #[export(rpc = "remote")]
+    fn register_player(&self, owner: TRef<Node>, id: Variant, p_name: Variant) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let sender = tree.get_rpc_sender_id();
+        if sender != 0 {
+            if !tree.is_network_server() {
+                // Clients only hear about the others from the server
+                if sender != 1 {
+                    return;
+                }
+            } else {
+                // A client only registers itself, the server passes it on
+                if id.to_i64() != sender {
+                    return;
+                }
+                for peer in tree.get_network_connected_peers().read().iter() {
+                    let peer = *peer as i64;
+                    if peer != sender {
+                        owner.rpc_id(peer, "register_player", &[id.clone(), p_name.clone()]);
+                    }
+                }
+            }
+        }
+
+        godot_print!(
+            "register player {} (id:{})",
+            p_name.to_string(),
+            id.to_i64()
+        );
+
+        unsafe {
+            self.players.insert(id, p_name);
+        }
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.callv("refresh_lobby", VariantArray::new_shared());
+        }
+    }
+
+    /// Called by the server for bots, peers are unregistered on disconnection.
+    #[export(rpc = "remote")]
+    fn unregister_player(&self, owner: TRef<Node>, id: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let sender = tree.get_rpc_sender_id();
+        if sender != 0 && sender != 1 {
+            return;
+        }
+
+        godot_print!("unregister player id:{}", id);
+
+        unsafe {
+            self.players.erase(id);
+            self.player_keys.erase(id);
+            self.team_choices.erase(id);
+            self.map_votes.erase(id);
+        }
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.callv("refresh_lobby", VariantArray::new_shared());
+        }
+    }
+
+    fn self_register_player(&self, owner: TRef<Node>) {
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.callv("change_to_players_lobby", VariantArray::new_shared());
+        }
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        self.register_player(
+            owner,
+            tree.get_network_unique_id().to_variant(),
+            self.player_name.to_variant(),
+        );
+
+        // Only the server needs to know who we are
+        if tree.is_network_server() {
+            self.register_player_key(owner, self.profile.key.clone());
+        } else {
+            owner.rpc_id(1, "register_player_key", &[self.profile.key.to_variant()]);
+        }
+    }
+
+    /// Server only: leaderboard identity of the sender, sent once after `register_player`.
+    #[export(rpc = "remote")]
+    fn register_player_key(&self, owner: TRef<Node>, key: String) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() || key.is_empty() {
+            return;
+        }
+
+        let sender = match tree.get_rpc_sender_id() {
+            0 => tree.get_network_unique_id(),
+            sender => sender,
+        };
+
+        unsafe {
+            self.player_keys.insert(sender, key);
+        }
+    }
+
+    /// Asking the server for the leaderboard, it's shown by the lobby when received.
+    #[export(rpc = "remote")]
+    fn request_leaderboard(&self, owner: TRef<Node>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            owner.rpc_id(1, "request_leaderboard", &[]);
+            return;
+        }
+
+        let rows = Leaderboard::load().rows();
+        match tree.get_rpc_sender_id() {
+            0 => self.receive_leaderboard(owner, rows),
+            sender => {
+                owner.rpc_id(sender, "receive_leaderboard", &[rows.to_variant()]);
+            }
+        }
+    }
+
+    #[export(rpc = "puppet")]
+    fn receive_leaderboard(&self, owner: TRef<Node>, rows: VariantArray) {
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(rows);
+            lobby.callv("show_leaderboard", func_args);
+        }
+    }
+
+    fn instance_world<'a>(world_packed_scene: Ref<PackedScene>) -> TRef<'a, Node2D> {
+        let world_packed_scene = unsafe { world_packed_scene.assume_safe() };
+
+        // instancing world packed scene
+        let world = world_packed_scene
+            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+            .unwrap();
+        let world = unsafe { world.assume_safe() };
+
+        world.cast::<Node2D>().unwrap()
+    }
+
+    fn instance_player<'a>(
+        &self,
+        player_id: &Variant,
+        player_name: &Variant,
+    ) -> TRef<'a, KinematicBody2D> {
+        let player_packed_scene = unsafe { self.preload_player.assume_safe() };
+
+        // instancing player packed scene
+        let new_player = player_packed_scene
+            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+            .unwrap();
+        let new_player = unsafe { new_player.assume_safe() };
+        let new_player = new_player.cast::<KinematicBody2D>().unwrap();
+        //
+
+        new_player.set_name(player_id.to_godot_string()); // Use unique ID as node name.
+
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(player_name.clone());
+            new_player.callv("set_player_name", func_args);
+        }
+
+        new_player
+    }
+
+    /// Recording an event into the replay and the match statistics,
+    /// called by `replay::record_event`.
+    #[export]
+    fn record_event(
+        &mut self,
+        _owner: TRef<Node>,
+        node: String,
+        event: String,
+        args: VariantArray,
+    ) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&node, &event, &args);
+        }
+
+        if let Some(stats) = &mut self.stats {
+            stats.record(&node, &event, &args);
+        }
+    }
+
+    /// Server only: called by `Score` for every point, the first point of sudden death wins the round.
+    #[export]
+    fn on_point_scored(&mut self, owner: TRef<Node>, for_who: i64) {
+        if self.sudden_death {
+            let side = self.rules.side_of(for_who);
+            self.finish_round(owner, side);
+        }
+    }
+
+    #[export]
+    fn _process(&mut self, owner: TRef<Node>, delta: f64) {
+        self.update_clock_sync(owner, delta);
+        self.update_hurry_up(owner, delta);
+
+        let time_left = match self.time_left {
+            Some(time_left) if !self.round_over => time_left,
+            _ => return,
+        };
+
+        let new_time_left = time_left - delta;
+        if new_time_left <= 0.0 {
+            self.time_left = None;
+            self.on_time_up(owner);
+            return;
+        }
+        self.time_left = Some(new_time_left);
+
+        // The last seconds of the round
+        let hurry_up = self.settings.hurry_up as f64;
+        if self.hurry_up.is_none() && hurry_up > 0.0 && new_time_left <= hurry_up {
+            self.start_hurry_up(owner, new_time_left);
+        }
+
+        // Peers only get whole seconds
+        if new_time_left.ceil() < time_left.ceil() {
+            let seconds_left = new_time_left.ceil() as i64;
+            self.update_timer(owner, seconds_left, false);
+            owner.rpc(
+                "update_timer",
+                &[seconds_left.to_variant(), false.to_variant()],
+            );
+        }
+    }
+
+    /// Client only: the server clock is asked for every few seconds,
+    /// bomb fuses count down against it.
+    fn update_clock_sync(&mut self, owner: TRef<Node>, delta: f64) {
+        let sync_in = match self.clock_sync_in {
+            Some(sync_in) => sync_in - delta,
+            None => return,
+        };
+
+        if sync_in > 0.0 {
+            self.clock_sync_in = Some(sync_in);
+            return;
+        }
+        self.clock_sync_in = Some(CLOCK_SYNC_INTERVAL);
+
+        let ticks = OS::godot_singleton().get_ticks_msec();
+        owner.rpc_id(1, "request_clock", &[ticks.to_variant()]);
+    }
+
+    /// Server: the clock of the server, sent back with the clock of the client.
+    #[export(rpc = "remote")]
+    fn request_clock(&self, owner: TRef<Node>, client_msec: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let server_msec = OS::godot_singleton().get_ticks_msec();
+        owner.rpc_id(
+            tree.get_rpc_sender_id(),
+            "sync_clock",
+            &[client_msec.to_variant(), server_msec.to_variant()],
+        );
+    }
+
+    /// Server (Sync): the server clock was `server_msec` half way
+    /// between `client_msec` and now. Later syncs only smooth out the offset.
+    #[export(rpc = "puppet")]
+    fn sync_clock(&mut self, _owner: TRef<Node>, client_msec: i64, server_msec: i64) {
+        let now = OS::godot_singleton().get_ticks_msec();
+        let offset = server_msec + (now - client_msec) / 2 - now;
+
+        self.clock_offset_msec = Some(match self.clock_offset_msec {
+            Some(previous) => previous + (offset - previous) / 4,
+            None => offset,
+        });
+    }
+
+    /// Server only: server time of the explosion of a bomb placed now.
+    #[export]
+    fn bomb_explode_at(&self, _owner: TRef<Node>) -> i64 {
+        OS::godot_singleton().get_ticks_msec() + (self.rules.fuse_time * 1000.0) as i64
+    }
+
+    /// Msec until a bomb exploding at `explode_at` (server time) goes off.
+    /// Before the first clock sync, the bomb gets the whole fuse.
+    #[export]
+    fn fuse_left_msec(&self, owner: TRef<Node>, explode_at: i64) -> i64 {
+        let fuse_msec = (self.rules.fuse_time * 1000.0) as i64;
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let offset = if tree.is_network_server() {
+            0
+        } else {
+            match self.clock_offset_msec {
+                Some(offset) => offset,
+                None => return fuse_msec,
+            }
+        };
+
+        let server_now = OS::godot_singleton().get_ticks_msec() + offset;
+        (explode_at - server_now).max(0).min(fuse_msec)
+    }
+
+    /// Server only: the time of the round is up.
+    fn on_time_up(&mut self, owner: TRef<Node>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        // Lives left in elimination, rock scores otherwise
+        let scores = match self.rules.mode {
+            GameMode::Score => unsafe {
+                world
+                    .get_node("Score")
+                    .unwrap()
+                    .assume_safe()
+                    .callv("get_scores", VariantArray::new_shared())
+                    .to_dictionary()
+            },
+            GameMode::Elimination => {
+                let lives = Dictionary::new();
+                for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+                    .get_children()
+                    .iter()
+                {
+                    let player = unsafe { player.try_to_object::<Node>().unwrap().assume_safe() };
+                    let player_lives =
+                        unsafe { player.callv("get_lives", VariantArray::new_shared()) };
+                    // Player id is the node name
+                    let player_id = player.name().to_string().parse::<i64>().unwrap_or(0);
+                    lives.insert(player_id, player_lives);
+                }
+                lives.into_shared()
+            }
+        };
+
+        let leader = rules::leading_side(&scores, &self.rules.team_of);
+
+        // Sudden death is about points, the survivors of an elimination round are compared right away
+        let overtime = match self.rules.mode {
+            GameMode::Score => self.settings.overtime,
+            GameMode::Elimination => Overtime::HighestScore,
+        };
+
+        match overtime {
+            // A tie is a draw
+            Overtime::HighestScore => self.finish_round(owner, leader),
+            // The leader wins, a tie is decided by the next point
+            Overtime::SuddenDeath if leader != 0 => self.finish_round(owner, leader),
+            Overtime::SuddenDeath => {
+                godot_print!("sudden death!");
+
+                self.update_timer(owner, 0, true);
+                owner.rpc("update_timer", &[0.to_variant(), true.to_variant()]);
+            }
+        }
+    }
+
+    /// Server only: the arena closes in the rest of the round.
+    fn start_hurry_up(&mut self, owner: TRef<Node>, duration: f64) {
+        godot_print!("hurry up!");
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        self.hurry_up = Some(HurryUp::new(grid::get_tilemap(world), duration));
+
+        self.hurry_up_started(owner);
+        owner.rpc("hurry_up_started", &[]);
+    }
+
+    /// Server only: closing the tiles whose time has come, it goes on in overtime.
+    fn update_hurry_up(&mut self, owner: TRef<Node>, delta: f64) {
+        let closing = match &mut self.hurry_up {
+            Some(hurry_up) if !self.round_over => hurry_up.update(delta),
+            _ => return,
+        };
+
+        for cell in closing {
+            let args = [cell.0.to_variant(), cell.1.to_variant()];
+            owner.rpc("close_arena_cell", &args);
+            // Players caught in it are told by this instance
+            unsafe {
+                owner.call_deferred("close_arena_cell", &args);
+            }
+        }
+    }
+
+    /// Warning of the hurry-up phase, sent by the server.
+    #[export(rpc = "puppet")]
+    fn hurry_up_started(&mut self, owner: TRef<Node>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        // `HurryUp` Label
+        let hurry_up = world.get_node("HurryUp").unwrap();
+        let hurry_up = unsafe { hurry_up.assume_safe() };
+        let hurry_up = hurry_up.cast::<Label>().unwrap();
+        //
+
+        hurry_up.show();
+    }
+
+    /// Server only: tiles of the closing arena becoming walls within `within` seconds,
+    /// as `Vector2` cells. Bots keep away from them.
+    #[export]
+    fn get_closing_cells(&self, _owner: TRef<Node>, within: f64) -> VariantArray {
+        let cells = VariantArray::new();
+        if let Some(hurry_up) = &self.hurry_up {
+            for cell in hurry_up.upcoming(within) {
+                cells.push(Vector2::new(cell.0 as f32, cell.1 as f32));
+            }
+        }
+
+        cells.into_shared()
+    }
+
+    /// A tile of the closing arena becomes a wall, sent by the server.
+    /// Players in it are stunned, or eliminated in elimination mode.
+    #[export(rpc = "puppet")]
+    fn close_arena_cell(&mut self, owner: TRef<Node>, x: i32, y: i32) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        arena::close_cell(world, (x, y));
+
+        if let Some(recorder) = &self.recorder {
+            let args = VariantArray::new();
+            args.push(x);
+            args.push(y);
+            recorder.record("TileMap", "close_cell", &args.into_shared());
+        }
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let tilemap = grid::get_tilemap(world);
+
+        // Bombs are the only `Area2D` children of `World`, they go off before the wall is up
+        for child in world.get_children().iter() {
+            let child = unsafe { child.try_to_object::<Node>().unwrap().assume_safe() };
+            if let Some(bomb) = child.cast::<Area2D>() {
+                if !bomb.get("exploded").to_bool()
+                    && grid::to_cell(tilemap, bomb.position()) == (x, y)
+                {
+                    bomb.rpc("trigger", &[]);
+                    unsafe {
+                        bomb.call_deferred("trigger", &[]);
+                    }
+                }
+            }
+        }
+
+        let position = grid::cell_center(tilemap, arena::nearest_open_cell(tilemap, (x, y)));
+        for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let player = player.try_to_object::<Node2D>().unwrap();
+            let player = unsafe { player.assume_safe() };
+            if grid::to_cell(tilemap, player.position()) == (x, y) {
+                // Received by the master of the player
+                player.rpc("arena_closed", &[position.to_variant()]);
+            }
+        }
+    }
+
+    /// Sent by the server when the seconds left change.
+    #[export(rpc = "puppet")]
+    fn update_timer(&mut self, owner: TRef<Node>, seconds_left: i64, sudden_death: bool) {
+        self.sudden_death = sudden_death;
+
+        let timer = unsafe { utils::get_world(owner.as_ref()) }
+            .get_node("Timer")
+            .unwrap();
+        let timer = unsafe { timer.assume_safe() };
+        let timer = timer.cast::<Label>().unwrap();
+
+        if sudden_death {
+            timer.set_text("SUDDEN DEATH");
+        } else {
+            timer.set_text(format!("{}:{:02}", seconds_left / 60, seconds_left % 60));
+        }
+    }
+
+    /// A player has no lives left, called by the `Player` on every peer.
+    #[export]
+    fn player_eliminated(&mut self, owner: TRef<Node>, id: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        // Watching the rest of the round
+        if id == tree.get_network_unique_id() {
+            let spectator = world.get_node("Spectator").unwrap();
+            let spectator = unsafe { spectator.assume_safe() };
+            spectator.cast::<Label>().unwrap().show();
+        }
+
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let mut survivors = Vec::new();
+        for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let player = unsafe { player.try_to_object::<Node>().unwrap().assume_safe() };
+            if !player.get("eliminated").to_bool() {
+                let player_id = player.name().to_string().parse::<i64>().unwrap_or(0);
+                survivors.push(self.rules.side_of(player_id));
+            }
+        }
+        survivors.sort_unstable();
+        survivors.dedup();
+
+        // The last player (or team) standing wins, nobody left is a draw
+        match survivors.as_slice() {
+            [] => self.finish_round(owner, 0),
+            [winner] => self.finish_round(owner, *winner),
+            _ => {}
+        }
+    }
+
+    /// Server only: called by `Score` when no rocks are left, by `player_eliminated`,
+    /// or when the time is up. `winner` is 0 when the round is a draw.
+    #[export]
+    fn finish_round(&mut self, owner: TRef<Node>, winner: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() || self.round_over {
+            return;
+        }
+        self.round_over = true;
+
+        owner.rpc("round_finished", &[winner.to_variant()]);
+        self.round_finished(owner, winner);
+    }
+
+    /// `winner` is a team in team mode, a player otherwise.
+    /// Counting the round win, then either the next round is started by the server
+    /// or the match is over.
+    #[export(rpc = "puppet")]
+    fn round_finished(&mut self, owner: TRef<Node>, winner: i64) {
+        if winner != 0 {
+            let wins = self.round_wins.get(winner).to_i64() + 1;
+            unsafe {
+                self.round_wins.insert(winner, wins);
+            }
+        }
+
+        let winner_name = self.rules.side_name(winner, &self.players);
+        let match_over = self.round_wins.get(winner).to_i64() >= self.rules.rounds_to_win;
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let text = if match_over {
+            format!("THE WINNER IS:\n{}", winner_name)
+        } else if winner == 0 {
+            format!("ROUND {}:\nDRAW", self.round)
+        } else {
+            format!("ROUND {} WON BY:\n{}", self.round, winner_name)
+        };
+
+        // The replay viewer shows the same result
+        if let Some(recorder) = &self.recorder {
+            let args = VariantArray::new();
+            args.push(winner);
+            args.push(text.as_str());
+            recorder.record(".", "round_finished", &args.into_shared());
+        }
+
+        // Only the host can restart the match
+        let score = unsafe { utils::get_world(owner.as_ref()) }
+            .get_node("Score")
+            .unwrap();
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(text);
+            func_args.push(match_over);
+            func_args.push(match_over && tree.is_network_server());
+            score.assume_safe().callv("show_result", func_args);
+        }
+
+        if match_over {
+            self.finish_match(owner, winner);
+        } else if tree.is_network_server() {
+            let timer = tree.create_timer(ROUND_BREAK, false).unwrap();
+            let timer = unsafe { timer.assume_safe() };
+            if let Err(e) = timer.connect(
+                "timeout",
+                owner,
+                "_on_round_break_timeout",
+                VariantArray::new_shared(),
+                0,
+            ) {
+                godot_error!(
+                    "`Gamestate` => GodotError at `timer.connect` function: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    /// Server only: the next round, unless the game was left during the break.
+    #[export]
+    fn _on_round_break_timeout(&mut self, owner: TRef<Node>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() || self.stats.is_none() {
+            return;
+        }
+
+        self.start_round(owner, self.round + 1);
+    }
+
+    fn finish_match(&mut self, owner: TRef<Node>, winner: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+
+        // Load test matches aren't rated
+        if tree.is_network_server() && self.settings.loadtest_clients.is_none() {
+            self.rate_match(winner);
+        }
+
+        self.save_stats(owner, Some(winner));
+        self.stop_recording();
+        self.rotation_index += 1;
+    }
+
+    /// Server only: updating the leaderboard from the final standings (round wins).
+    fn rate_match(&self, winner: i64) {
+        let mut standings = Vec::new();
+        for (id, name) in self.players.iter() {
+            // Bots have no key, they aren't rated
+            let key = self.player_keys.get(id.clone());
+            if key.is_nil() {
+                continue;
+            }
+
+            // Teammates share round wins, sides are rated against each other
+            let side = self.rules.side_of(id.to_i64());
+            standings.push(Standing {
+                key: key.to_string(),
+                name: name.to_string(),
+                score: self.round_wins.get(side).to_i64(),
+                side,
+                won: side == winner,
+            });
+        }
+
+        let mut leaderboard = Leaderboard::load();
+        leaderboard.add_match(&standings);
+        if let Err(e) = leaderboard.save() {
+            godot_error!(
+                "`Gamestate` => GodotError at `Leaderboard::save` function: {}",
+                e
+            );
+        }
+    }
+
+    /// Adding the match to the local profile, the server also exports the statistics.
+    /// `winner` is `None` when the match was interrupted.
+    fn save_stats(&mut self, owner: TRef<Node>, winner: Option<i64>) {
+        let stats = match self.stats.take() {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let my_id = tree.get_network_unique_id();
+
+        if self.uses_profile() {
+            let won = winner == Some(self.rules.side_of(my_id));
+            self.profile.add_match(stats.player(my_id), won);
+            if let Err(e) = self.profile.save() {
+                godot_error!(
+                    "`Gamestate` => GodotError at `Profile::save` function: {}",
+                    e
+                );
+            }
+        }
+
+        if tree.is_network_server() {
+            if let Err(e) = stats.finish(winner) {
+                godot_error!(
+                    "`Gamestate` => GodotError at `MatchStats::finish` function: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    /// Load tests don't touch the profile of the machine they run on.
+    fn uses_profile(&self) -> bool {
+        self.settings.loadtest_clients.is_none()
+    }
+
+    /// Server only: game mode of the next match, `mode` is an index of `GameMode::ALL`.
+    #[export]
+    fn set_game_mode(&mut self, _owner: TRef<Node>, mode: i64) {
+        self.game_mode = GameMode::from_i64(mode);
+    }
+
+    /// Server only: number of teams of the next match, 0 means no teams.
+    #[export]
+    fn set_teams(&mut self, owner: TRef<Node>, teams: i64) {
+        self.teams = teams.clamp(0, rules::MAX_TEAMS);
+        self.send_teams(owner);
+    }
+
+    #[export]
+    fn set_friendly_fire(&mut self, _owner: TRef<Node>, friendly_fire: bool) {
+        self.friendly_fire = friendly_fire;
+    }
+
+    /// Picking a team in the lobby, 0 lets the server choose.
+    #[export(rpc = "remote")]
+    fn choose_team(&self, owner: TRef<Node>, team: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            owner.rpc_id(1, "choose_team", &[team.to_variant()]);
+            return;
+        }
+
+        let sender = match tree.get_rpc_sender_id() {
+            0 => tree.get_network_unique_id(),
+            sender => sender,
+        };
+
+        unsafe {
+            self.team_choices.insert(sender, team);
+        }
+        self.send_teams(owner);
+    }
+
+    /// Server only: lobby teams to everyone.
+    fn send_teams(&self, owner: TRef<Node>) {
+        owner.rpc(
+            "update_teams",
+            &[self.teams.to_variant(), self.team_choices.to_variant()],
+        );
+
+        // The lobby reads `teams` back, not while `set_teams` holds this instance
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.call_deferred("refresh_lobby", &[]);
+        }
+    }
+
+    #[export(rpc = "puppet")]
+    fn update_teams(&mut self, owner: TRef<Node>, teams: i64, team_choices: Dictionary) {
+        self.teams = teams;
+        self.team_choices = team_choices;
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.call_deferred("refresh_lobby", &[]);
+        }
+    }
+
+    /// Server only: maps the lobby can pick from, the map of the settings is picked.
+    fn load_map_choices(&mut self) {
+        let mut paths = map::list_maps();
+        for path in self.settings.map_rotation.iter() {
+            if path != map::PROCEDURAL_MAP && !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+
+        let choices = VariantArray::new();
+        for path in paths {
+            match MapLayout::load(&path) {
+                Ok(layout) => {
+                    let choice = layout.to_dictionary();
+                    unsafe {
+                        choice.insert("path", path.as_str());
+                    }
+                    choices.push(choice);
+                }
+                Err(e) => godot_warn!("`Gamestate` => map left out of the lobby: {}", e),
+            }
+        }
+
+        let generated = Dictionary::new();
+        generated.insert("path", "");
+        generated.insert("name", "Generated");
+        choices.push(generated.into_shared());
+        self.map_choices = choices.into_shared();
+
+        self.map_vote = false;
+        self.map_votes = Dictionary::new_shared();
+        self.map_rotation = !self.settings.map_rotation.is_empty();
+        self.rotation_index = 0;
+        if self.map_rotation {
+            self.select_rotation_map();
+        } else {
+            self.map_choice = self.current_map_choice();
+        }
+    }
+
+    /// Index of `map_choices` of `map_path`, or of the generated map.
+    fn current_map_choice(&self) -> i64 {
+        let path = if self.procedural_map {
+            ""
+        } else {
+            self.map_path.as_str()
+        };
+
+        self.map_choices
+            .iter()
+            .position(|choice| choice.to_dictionary().get("path").to_string() == path)
+            .map_or(-1, |index| index as i64)
+    }
+
+    /// Server only: `index` is an index of `map_choices`.
+    fn select_map(&mut self, index: i64) {
+        if index < 0 || index >= self.map_choices.len() as i64 {
+            return;
+        }
+
+        let path = self
+            .map_choices
+            .get(index as i32)
+            .to_dictionary()
+            .get("path")
+            .to_string();
+        self.procedural_map = path.is_empty();
+        if !self.procedural_map {
+            self.map_path = path;
+        }
+        self.map_choice = index;
+    }
+
+    /// Server only: the map of the rotation for the current match.
+    fn select_rotation_map(&mut self) {
+        let rotation = &self.settings.map_rotation;
+        let path = rotation[self.rotation_index % rotation.len()].clone();
+
+        self.procedural_map = path == map::PROCEDURAL_MAP;
+        if !self.procedural_map {
+            self.map_path = path;
+        }
+        self.map_choice = self.current_map_choice();
+    }
+
+    /// Server only: the map with the most votes, the server picks one of a tie at random.
+    /// `None` without votes.
+    fn count_map_votes(&self) -> Option<i64> {
+        let mut counts = vec![0; self.map_choices.len() as usize];
+        for (_, index) in self.map_votes.iter() {
+            if let Some(count) = counts.get_mut(index.to_i64() as usize) {
+                *count += 1;
+            }
+        }
+
+        let most = counts.iter().copied().max().filter(|most| *most > 0)?;
+        let tied: Vec<i64> = (0..counts.len())
+            .filter(|index| counts[*index] == most)
+            .map(|index| index as i64)
+            .collect();
+        tied.choose(&mut rand::thread_rng()).copied()
+    }
+
+    /// Server only: the host picks the map of the next match,
+    /// `index` is an index of `map_choices`.
+    #[export]
+    fn set_map(&mut self, owner: TRef<Node>, index: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() || self.map_rotation || self.map_vote {
+            return;
+        }
+
+        self.select_map(index);
+        self.send_maps(owner);
+    }
+
+    /// Server only: players vote for the map instead of the host.
+    #[export]
+    fn set_map_vote(&mut self, owner: TRef<Node>, map_vote: bool) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() || self.map_rotation {
+            return;
+        }
+
+        self.map_vote = map_vote;
+        self.map_votes = Dictionary::new_shared();
+        self.send_maps(owner);
+    }
+
+    /// Voting for a map in the lobby, `index` is an index of `map_choices`.
+    #[export(rpc = "remote")]
+    fn vote_map(&self, owner: TRef<Node>, index: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            owner.rpc_id(1, "vote_map", &[index.to_variant()]);
+            return;
+        }
+
+        if !self.map_vote || index < 0 || index >= self.map_choices.len() as i64 {
+            return;
+        }
+
+        let sender = match tree.get_rpc_sender_id() {
+            0 => tree.get_network_unique_id(),
+            sender => sender,
+        };
+
+        unsafe {
+            self.map_votes.insert(sender, index);
+        }
+        self.send_maps(owner);
+    }
+
+    fn maps_args(&self) -> [Variant; 5] {
+        [
+            self.map_choices.to_variant(),
+            self.map_choice.to_variant(),
+            self.map_vote.to_variant(),
+            self.map_votes.to_variant(),
+            self.map_rotation.to_variant(),
+        ]
+    }
+
+    /// Server only: lobby maps to everyone.
+    fn send_maps(&self, owner: TRef<Node>) {
+        owner.rpc("update_maps", &self.maps_args());
+
+        // The lobby reads the maps back, not while this instance is borrowed
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.call_deferred("refresh_lobby", &[]);
+        }
+    }
+
+    #[export(rpc = "puppet")]
+    fn update_maps(
+        &mut self,
+        owner: TRef<Node>,
+        map_choices: VariantArray,
+        map_choice: i64,
+        map_vote: bool,
+        map_votes: Dictionary,
+        map_rotation: bool,
+    ) {
+        self.map_choices = map_choices;
+        self.map_choice = map_choice;
+        self.map_vote = map_vote;
+        self.map_votes = map_votes;
+        self.map_rotation = map_rotation;
+
+        let lobby = unsafe { utils::get_lobby(owner.as_ref()) };
+        unsafe {
+            lobby.call_deferred("refresh_lobby", &[]);
+        }
+    }
+
+    /// Server only: what a destroyed rock drops, -1 is nothing,
+    /// otherwise an index of `PowerUpKind::ALL`.
+    #[export]
+    fn roll_powerup(&mut self, _owner: TRef<Node>) -> i64 {
+        let rng = match &mut self.powerup_rng {
+            Some(rng) => rng,
+            None => return -1,
+        };
+
+        self.rules
+            .powerups
+            .roll(rng)
+            .map_or(-1, |kind| kind.to_i64())
+    }
+
+    /// Bombs and power-ups of every player, shown at the bottom of the world.
+    #[export]
+    fn update_hud(&self, owner: TRef<Node>) {
+        let world = match unsafe { utils::get_root(owner.as_ref()) }.get_node("World") {
+            Some(world) => unsafe { world.assume_safe() },
+            None => return,
+        };
+
+        let mut lines = Vec::new();
+        for player in unsafe { world.get_node("Players").unwrap().assume_safe() }
+            .get_children()
+            .iter()
+        {
+            let player = unsafe { player.try_to_object::<Node>().unwrap().assume_safe() };
+            if player.get("eliminated").to_bool() {
+                continue;
+            }
+
+            let status = unsafe { player.callv("get_status", VariantArray::new_shared()) };
+            let player_id = player.name().to_string().parse::<i64>().unwrap_or(0);
+            lines.push(format!(
+                "{}: {}",
+                self.players.get(player_id).to_string(),
+                status.to_string()
+            ));
+        }
+
+        // `Hud` Label
+        let hud = world.get_node("Hud").unwrap();
+        let hud = unsafe { hud.assume_safe() };
+        let hud = hud.cast::<Label>().unwrap();
+        //
+
+        hud.set_text(lines.join("\n"));
+    }
+
+    /// Rules of the current match.
+    #[export]
+    fn get_rules(&self, _owner: TRef<Node>) -> Dictionary {
+        self.rules.to_dictionary()
+    }
+
+    /// Profile screen of the lobby.
+    #[export]
+    fn get_profile(&self, _owner: TRef<Node>) -> Dictionary {
+        self.profile.to_dictionary()
+    }
+
+    #[export]
+    fn set_profile_color(&mut self, _owner: TRef<Node>, color: Color) {
+        self.profile.color = color;
+
+        if let Err(e) = self.profile.save() {
+            godot_error!(
+                "`Gamestate` => GodotError at `Profile::save` function: {}",
+                e
+            );
+        }
+    }
+
+    /// The last used name fills `Connect/Name` next time.
+    fn save_profile_name(&mut self) {
+        if !self.uses_profile() {
+            return;
+        }
+
+        self.profile.name = self.player_name.clone();
+
+        if let Err(e) = self.profile.save() {
+            godot_error!(
+                "`Gamestate` => GodotError at `Profile::save` function: {}",
+                e
+            );
+        }
+    }
+
+    fn start_recording(&mut self, owner: TRef<Node>) {
+        self.stop_recording();
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !self.settings.should_record_replay(tree.is_network_server()) {
+            return;
+        }
+
+        let lives = match self.rules.mode {
+            GameMode::Elimination => self.rules.lives,
+            GameMode::Score => 0,
+        };
+
+        match ReplayRecorder::start(
+            self.match_seed,
+            &self.map_path,
+            self.map_layout.as_ref(),
+            &self.players,
+            lives,
+        ) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => godot_error!(
+                "`Gamestate` => GodotError at `ReplayRecorder::start` function: {}",
+                e
+            ),
+        }
+
+        replay::prune_replays(self.settings.replay_retention);
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish();
+        }
+    }
+
+    /// Preloading `PackedScene` instances (World, Player)
+    fn preload_instances(&mut self) {
+        // World
+        let world_scene = ResourceLoader::godot_singleton()
+            .load(WORLD_MAP, "PackedScene", false)
+            .unwrap();
+        let world_scene = unsafe { world_scene.assume_unique().into_shared() };
+
+        self.preload_world = world_scene.cast::<PackedScene>().unwrap();
+
+        // Player
+        let player_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/Player/Player.tscn", "PackedScene", false)
+            .unwrap();
+        let player_scene = unsafe { player_scene.assume_unique().into_shared() };
+
+        self.preload_player = player_scene.cast::<PackedScene>().unwrap();
+    }
+}
+
+/// Index of a player among the sorted ids, the same on every peer.
+fn spawn_slot(players: &Dictionary, player_id: &Variant) -> usize {
+    let mut ids: Vec<i64> = players.keys().iter().map(|id| id.to_i64()).collect();
+    ids.sort_unstable();
+
+    let player_id = player_id.to_i64();
+    ids.iter().position(|id| *id == player_id).unwrap_or(0)
 }
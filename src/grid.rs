@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// `floor` tile of `res/tileset.tres`, everything but it and the hazard tiles is solid.
+pub const FLOOR_TILE: i64 = 1;
+/// `wall` tile of `res/tileset.tres`.
+pub const WALL_TILE: i64 = 0;
+
+// Hazard tiles of `res/tileset.tres`, floor with an effect, see `hazard`
+pub const ICE_TILE: i64 = 2;
+/// In the order of `DIRECTIONS`
+pub const CONVEYOR_TILES: [i64; 4] = [3, 4, 5, 6];
+pub const TELEPORTER_TILE: i64 = 7;
+
+/// `cell_size` of the `TileMap` of `World.tscn`, for code that works without the world.
+pub const CELL_SIZE: f32 = 48.0;
+
+/// A `TileMap` cell
+pub type Cell = (i32, i32);
+
+pub const DIRECTIONS: [Cell; 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// `TileMap` of the world.
+pub fn get_tilemap<'a>(world: TRef<'a, Node2D>) -> TRef<'a, TileMap> {
+    let tilemap = world.get_node("TileMap").unwrap();
+    let tilemap = unsafe { tilemap.assume_safe() };
+    tilemap.cast::<TileMap>().unwrap()
+}
+
+pub fn to_cell(tilemap: TRef<TileMap>, position: Vector2) -> Cell {
+    let cell = tilemap.world_to_map(position);
+    (cell.x as i32, cell.y as i32)
+}
+
+pub fn cell_center(tilemap: TRef<TileMap>, cell: Cell) -> Vector2 {
+    let cell_size = tilemap.cell_size();
+    tilemap.map_to_world(Vector2::new(cell.0 as f32, cell.1 as f32), false) + cell_size / 2.0
+}
+
+/// Center of the cell under `position`.
+pub fn snap(tilemap: TRef<TileMap>, position: Vector2) -> Vector2 {
+    cell_center(tilemap, to_cell(tilemap, position))
+}
+
+/// Players walk on it and bombs lie on it: the floor or a hazard.
+pub fn is_floor_tile(tile: i64) -> bool {
+    tile == FLOOR_TILE
+        || tile == ICE_TILE
+        || tile == TELEPORTER_TILE
+        || CONVEYOR_TILES.contains(&tile)
+}
+
+/// Floor tile inside the map, bombs can lie or land on it.
+pub fn is_floor(tilemap: TRef<TileMap>, cell: Cell) -> bool {
+    is_floor_tile(tilemap.get_cell(cell.0 as i64, cell.1 as i64))
+}
+
+/// Solid tiles of the map, only the closing arena adds to them during a round.
+pub fn walls(tilemap: TRef<TileMap>) -> HashSet<Cell> {
+    let mut walls = HashSet::new();
+    for cell in tilemap.get_used_cells().iter() {
+        let cell = cell.to_vector2();
+        if !is_floor_tile(tilemap.get_cellv(cell)) {
+            walls.insert((cell.x as i32, cell.y as i32));
+        }
+    }
+    walls
+}
+
+/// Rocks still standing, the ones playing their explosion are gone already.
+pub fn rocks(world: TRef<Node2D>) -> HashSet<Cell> {
+    let tilemap = get_tilemap(world);
+
+    let mut rocks = HashSet::new();
+    for rock in unsafe { world.get_node("Rocks").unwrap().assume_safe() }
+        .get_children()
+        .iter()
+    {
+        let rock = unsafe { rock.try_to_object::<Node2D>().unwrap().assume_safe() };
+        if !rock.get("destroyed").to_bool() {
+            rocks.insert(to_cell(tilemap, rock.position()));
+        }
+    }
+    rocks
+}
+
+/// Cells hit by a bomb at `center`: a cross of `range` tiles in every direction.
+/// Each arm stops before a wall and at the first rock, which is hit.
+/// Every peer gets the same cells from the same map.
+pub fn blast_cells<W, R>(center: Cell, range: i64, is_wall: W, is_rock: R) -> Vec<Cell>
+where
+    W: Fn(Cell) -> bool,
+    R: Fn(Cell) -> bool,
+{
+    let mut cells = vec![center];
+    for (dx, dy) in DIRECTIONS.iter() {
+        for distance in 1..=range as i32 {
+            let cell = (center.0 + dx * distance, center.1 + dy * distance);
+            if is_wall(cell) {
+                break;
+            }
+
+            cells.push(cell);
+            if is_rock(cell) {
+                break;
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut cells: Vec<Cell>) -> Vec<Cell> {
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn blast_is_a_cross() {
+        let cells = blast_cells((5, 5), 2, |_| false, |_| false);
+        assert_eq!(
+            sorted(cells),
+            vec![
+                (3, 5),
+                (4, 5),
+                (5, 3),
+                (5, 4),
+                (5, 5),
+                (5, 6),
+                (5, 7),
+                (6, 5),
+                (7, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn walls_and_rocks_stop_the_blast() {
+        // Wall right of the bomb, rock below it
+        let cells = blast_cells((5, 5), 3, |cell| cell == (6, 5), |cell| cell == (5, 6));
+        assert!(!cells.contains(&(6, 5)));
+        assert!(!cells.contains(&(7, 5)));
+        assert!(cells.contains(&(5, 6)));
+        assert!(!cells.contains(&(5, 7)));
+        assert!(cells.contains(&(2, 5)));
+        assert!(cells.contains(&(5, 2)));
+        assert_eq!(cells.len(), 1 + 3 + 3 + 1);
+    }
+
+    #[test]
+    fn no_range_only_hits_the_center() {
+        assert_eq!(blast_cells((1, 1), 0, |_| false, |_| false), vec![(1, 1)]);
+    }
+}
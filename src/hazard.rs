@@ -0,0 +1,196 @@
+use crate::grid::{self, Cell, CONVEYOR_TILES, ICE_TILE, TELEPORTER_TILE};
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Pixels per second a conveyor adds to the motion of players.
+const CONVEYOR_PUSH: f32 = 60.0;
+/// Pixels per second of a bomb carried by a conveyor.
+pub const CONVEYOR_BOMB_SPEED: f64 = 60.0;
+/// How much of the wanted speed a player on ice gets each second.
+const ICE_GRIP: f32 = 1.5;
+
+/// `TileMap` metadata: the exit of every teleporter, set with the tiles of the map.
+const TELEPORTERS_META: &str = "teleporters";
+
+/// Names of `grid::DIRECTIONS` in map files.
+const DIRECTION_NAMES: [&str; 4] = ["right", "left", "down", "up"];
+
+/// A special floor tile declared by a map.
+///
+/// In the `hazards` array of the map file:
+/// ```json
+/// {"type": "conveyor", "x": 3, "y": 1, "direction": "right"}
+/// {"type": "teleporter", "cells": [[1, 1], [9, 7]]}
+/// {"type": "ice", "x": 5, "y": 5}
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hazard {
+    /// Pushes players and carries bombs towards `direction`, one of `grid::DIRECTIONS`
+    Conveyor { cell: Cell, direction: Cell },
+    /// Players and bombs stepping on one come out of the other
+    Teleporter { cells: [Cell; 2] },
+    /// Players keep their momentum, bombs keep sliding
+    Ice { cell: Cell },
+}
+
+impl Hazard {
+    pub fn cells(&self) -> Vec<Cell> {
+        match self {
+            Hazard::Conveyor { cell, .. } | Hazard::Ice { cell } => vec![*cell],
+            Hazard::Teleporter { cells } => cells.to_vec(),
+        }
+    }
+
+    /// Tile of `res/tileset.tres` painted on its cells.
+    pub fn tile(&self) -> i64 {
+        match self {
+            Hazard::Conveyor { direction, .. } => {
+                let index = grid::DIRECTIONS.iter().position(|d| d == direction);
+                CONVEYOR_TILES[index.unwrap_or(0)]
+            }
+            Hazard::Teleporter { .. } => TELEPORTER_TILE,
+            Hazard::Ice { .. } => ICE_TILE,
+        }
+    }
+
+    pub fn to_dictionary(&self) -> Dictionary {
+        let dict = Dictionary::new();
+        match self {
+            Hazard::Conveyor { cell, direction } => {
+                let index = grid::DIRECTIONS.iter().position(|d| d == direction);
+                dict.insert("type", "conveyor");
+                dict.insert("x", cell.0);
+                dict.insert("y", cell.1);
+                dict.insert("direction", DIRECTION_NAMES[index.unwrap_or(0)]);
+            }
+            Hazard::Teleporter { cells } => {
+                let pads = VariantArray::new();
+                for cell in cells.iter() {
+                    let pad = VariantArray::new();
+                    pad.push(cell.0);
+                    pad.push(cell.1);
+                    pads.push(pad.into_shared());
+                }
+                dict.insert("type", "teleporter");
+                dict.insert("cells", pads.into_shared());
+            }
+            Hazard::Ice { cell } => {
+                dict.insert("type", "ice");
+                dict.insert("x", cell.0);
+                dict.insert("y", cell.1);
+            }
+        }
+        dict.into_shared()
+    }
+
+    pub fn from_dictionary(dict: &Dictionary) -> Result<Self, String> {
+        // Numbers of JSON files are floats
+        let cell = (dict.get("x").to_i64() as i32, dict.get("y").to_i64() as i32);
+
+        match dict.get("type").to_string().as_str() {
+            "conveyor" => {
+                let name = dict.get("direction").to_string();
+                let index = DIRECTION_NAMES
+                    .iter()
+                    .position(|other| *other == name)
+                    .ok_or_else(|| format!("unknown conveyor direction `{}`", name))?;
+                Ok(Hazard::Conveyor {
+                    cell,
+                    direction: grid::DIRECTIONS[index],
+                })
+            }
+            "teleporter" => {
+                let pads = dict.get("cells").to_array();
+                if pads.len() != 2 {
+                    return Err("a teleporter needs 2 cells".to_string());
+                }
+
+                let pad = |index: i32| {
+                    let pad = pads.get(index).to_array();
+                    (pad.get(0).to_i64() as i32, pad.get(1).to_i64() as i32)
+                };
+                Ok(Hazard::Teleporter {
+                    cells: [pad(0), pad(1)],
+                })
+            }
+            "ice" => Ok(Hazard::Ice { cell }),
+            other => Err(format!("unknown hazard `{}`", other)),
+        }
+    }
+}
+
+/// Teleporter pairs of the map, both ways.
+pub fn set_teleporters(tilemap: TRef<TileMap>, hazards: &[Hazard]) {
+    let exits = Dictionary::new();
+    for hazard in hazards.iter() {
+        if let Hazard::Teleporter { cells } = hazard {
+            exits.insert(to_vector(cells[0]).to_variant(), to_vector(cells[1]));
+            exits.insert(to_vector(cells[1]).to_variant(), to_vector(cells[0]));
+        }
+    }
+    tilemap.set_meta(TELEPORTERS_META, exits.into_shared());
+}
+
+/// The other teleporter of the pair, unless it's closed by the arena.
+pub fn teleporter_exit(tilemap: TRef<TileMap>, cell: Cell) -> Option<Cell> {
+    if tile(tilemap, cell) != TELEPORTER_TILE || !tilemap.has_meta(TELEPORTERS_META) {
+        return None;
+    }
+
+    let exit = tilemap
+        .get_meta(TELEPORTERS_META)
+        .to_dictionary()
+        .get(to_vector(cell).to_variant())
+        .try_to_vector2()?;
+    let exit = (exit.x as i32, exit.y as i32);
+
+    if tile(tilemap, exit) == TELEPORTER_TILE {
+        Some(exit)
+    } else {
+        None
+    }
+}
+
+pub fn conveyor_direction(tilemap: TRef<TileMap>, cell: Cell) -> Option<Cell> {
+    let tile = tile(tilemap, cell);
+    CONVEYOR_TILES
+        .iter()
+        .position(|conveyor| *conveyor == tile)
+        .map(|index| grid::DIRECTIONS[index])
+}
+
+pub fn is_ice(tilemap: TRef<TileMap>, cell: Cell) -> bool {
+    tile(tilemap, cell) == ICE_TILE
+}
+
+/// Velocity of a player wanting to move at `wanted`: right away, or slowly on ice.
+pub fn steer(
+    tilemap: TRef<TileMap>,
+    cell: Cell,
+    velocity: Vector2,
+    wanted: Vector2,
+    delta: f64,
+) -> Vector2 {
+    if !is_ice(tilemap, cell) {
+        return wanted;
+    }
+
+    let grip = (ICE_GRIP * delta as f32).min(1.0);
+    velocity + (wanted - velocity) * grip
+}
+
+/// Added to the velocity of a player standing on a conveyor.
+pub fn push(tilemap: TRef<TileMap>, cell: Cell) -> Vector2 {
+    match conveyor_direction(tilemap, cell) {
+        Some((dx, dy)) => Vector2::new(dx as f32, dy as f32) * CONVEYOR_PUSH,
+        None => Vector2::zero(),
+    }
+}
+
+fn tile(tilemap: TRef<TileMap>, cell: Cell) -> i64 {
+    tilemap.get_cell(cell.0 as i64, cell.1 as i64)
+}
+
+fn to_vector(cell: Cell) -> Vector2 {
+    Vector2::new(cell.0 as f32, cell.1 as f32)
+}
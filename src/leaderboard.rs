@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Leaderboard of the server, one section per player key.
+pub const LEADERBOARD_PATH: &str = "user://leaderboard.cfg";
+
+/// Where the lobby exports the leaderboard.
+pub const LEADERBOARD_CSV_PATH: &str = "user://leaderboard.csv";
+
+/// Rating of a new player.
+const INITIAL_RATING: f64 = 1500.0;
+
+/// Max rating change of a one-on-one match, split between the opponents of a player.
+const K_FACTOR: f64 = 32.0;
+
+/// Final standing of a rated player.
+pub struct Standing {
+    pub key: String,
+    pub name: String,
+    pub score: i64,
+    /// Side of the player, teammates aren't opponents
+    pub side: i64,
+    /// Won the match, alone or with the team
+    pub won: bool,
+}
+
+/// Rating of a player identity.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// The last name used with this key
+    pub name: String,
+    pub rating: f64,
+    pub matches: i64,
+    pub wins: i64,
+}
+
+/// Elo ratings of every player identity who has played on this server.
+pub struct Leaderboard {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Leaderboard {
+    /// Loading `user://leaderboard.cfg`, the leaderboard is empty if there is none.
+    pub fn load() -> Self {
+        let mut entries = BTreeMap::new();
+
+        let config = ConfigFile::new();
+        if config.load(LEADERBOARD_PATH).is_ok() {
+            for key in config.get_sections().read().iter() {
+                let key = key.to_string();
+                let entry = Entry {
+                    name: config.get_value(&key, "name", "").to_string(),
+                    rating: config.get_value(&key, "rating", INITIAL_RATING).to_f64(),
+                    matches: config.get_value(&key, "matches", 0).to_i64(),
+                    wins: config.get_value(&key, "wins", 0).to_i64(),
+                };
+                entries.insert(key, entry);
+            }
+        }
+
+        Leaderboard { entries }
+    }
+
+    pub fn save(&self) -> Result<(), GodotError> {
+        let config = ConfigFile::new();
+
+        for (key, entry) in self.entries.iter() {
+            config.set_value(key, "name", entry.name.as_str());
+            config.set_value(key, "rating", entry.rating);
+            config.set_value(key, "matches", entry.matches);
+            config.set_value(key, "wins", entry.wins);
+        }
+
+        config.save(LEADERBOARD_PATH)
+    }
+
+    /// Updating ratings from the final `Score` standings:
+    /// every pair of players of different sides is an Elo match, the higher score wins it.
+    pub fn add_match(&mut self, standings: &[Standing]) {
+        let opponents: Vec<usize> = standings
+            .iter()
+            .map(|s| standings.iter().filter(|o| o.side != s.side).count())
+            .collect();
+        if opponents.iter().all(|count| *count == 0) {
+            return;
+        }
+
+        let ratings: Vec<f64> = standings
+            .iter()
+            .map(|s| {
+                self.entries
+                    .get(&s.key)
+                    .map_or(INITIAL_RATING, |e| e.rating)
+            })
+            .collect();
+
+        let mut changes = vec![0.0; standings.len()];
+        for i in 0..standings.len() {
+            for j in (i + 1)..standings.len() {
+                if standings[i].side == standings[j].side {
+                    continue;
+                }
+
+                let expected = 1.0 / (1.0 + 10f64.powf((ratings[j] - ratings[i]) / 400.0));
+                let actual = match standings[i].score.cmp(&standings[j].score) {
+                    std::cmp::Ordering::Greater => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Less => 0.0,
+                };
+
+                changes[i] += K_FACTOR / opponents[i] as f64 * (actual - expected);
+                changes[j] -= K_FACTOR / opponents[j] as f64 * (actual - expected);
+            }
+        }
+
+        for (standing, change) in standings.iter().zip(changes) {
+            let entry = self
+                .entries
+                .entry(standing.key.clone())
+                .or_insert_with(|| Entry {
+                    name: String::new(),
+                    rating: INITIAL_RATING,
+                    matches: 0,
+                    wins: 0,
+                });
+
+            entry.name = standing.name.clone();
+            entry.rating += change;
+            entry.matches += 1;
+            if standing.won {
+                entry.wins += 1;
+            }
+        }
+    }
+
+    /// Rows sorted by rating, sent to the lobby: keys stay on the server.
+    pub fn rows(&self) -> VariantArray {
+        let mut entries: Vec<&Entry> = self.entries.values().collect();
+        entries.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+
+        let rows = VariantArray::new();
+        for entry in entries {
+            let row = Dictionary::new();
+            row.insert("name", entry.name.as_str());
+            row.insert("rating", entry.rating.round() as i64);
+            row.insert("matches", entry.matches);
+            row.insert("wins", entry.wins);
+            rows.push(row.into_shared());
+        }
+
+        rows.into_shared()
+    }
+}
+
+/// Writing rows of `Leaderboard::rows` to `user://leaderboard.csv`.
+pub fn export_csv(rows: &VariantArray) -> Result<String, GodotError> {
+    let file = File::new();
+    file.open(LEADERBOARD_CSV_PATH, File::WRITE)?;
+
+    file.store_line("rank,name,rating,matches,wins");
+    for (rank, row) in rows.iter().enumerate() {
+        let row = row.to_dictionary();
+        file.store_line(format!(
+            "{},\"{}\",{},{},{}",
+            rank + 1,
+            row.get("name").to_string().replace('"', "\"\""),
+            row.get("rating").to_i64(),
+            row.get("matches").to_i64(),
+            row.get("wins").to_i64()
+        ));
+    }
+    file.close();
+
+    Ok(ProjectSettings::godot_singleton()
+        .globalize_path(LEADERBOARD_CSV_PATH)
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(key: &str, score: i64, side: i64, won: bool) -> Standing {
+        Standing {
+            key: key.to_string(),
+            name: key.to_uppercase(),
+            score,
+            side,
+            won,
+        }
+    }
+
+    fn empty() -> Leaderboard {
+        Leaderboard {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn winner_takes_half_of_k() {
+        let mut leaderboard = empty();
+        leaderboard.add_match(&[standing("a", 3, 0, true), standing("b", 1, 1, false)]);
+
+        let a = &leaderboard.entries["a"];
+        let b = &leaderboard.entries["b"];
+        assert!((a.rating - (INITIAL_RATING + K_FACTOR / 2.0)).abs() < 1e-9);
+        assert!((b.rating - (INITIAL_RATING - K_FACTOR / 2.0)).abs() < 1e-9);
+        assert_eq!((a.matches, a.wins, a.name.as_str()), (1, 1, "A"));
+        assert_eq!((b.matches, b.wins), (1, 0));
+    }
+
+    #[test]
+    fn draws_between_equals_change_nothing() {
+        let mut leaderboard = empty();
+        leaderboard.add_match(&[standing("a", 2, 0, false), standing("b", 2, 1, false)]);
+
+        assert!((leaderboard.entries["a"].rating - INITIAL_RATING).abs() < 1e-9);
+        assert!((leaderboard.entries["b"].rating - INITIAL_RATING).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ratings_are_kept_between_matches() {
+        let mut leaderboard = empty();
+        for _ in 0..5 {
+            leaderboard.add_match(&[
+                standing("a", 3, 0, true),
+                standing("b", 1, 1, false),
+                standing("c", 0, 2, false),
+            ]);
+        }
+
+        let total: f64 = leaderboard.entries.values().map(|e| e.rating).sum();
+        assert!((total - 3.0 * INITIAL_RATING).abs() < 1e-6);
+        assert!(leaderboard.entries["a"].rating > leaderboard.entries["b"].rating);
+        assert!(leaderboard.entries["b"].rating > leaderboard.entries["c"].rating);
+        assert_eq!(leaderboard.entries["a"].matches, 5);
+    }
+
+    #[test]
+    fn teammates_are_not_opponents() {
+        let mut leaderboard = empty();
+        leaderboard.add_match(&[standing("a", 3, 0, true), standing("b", 0, 0, true)]);
+        assert!(leaderboard.entries.is_empty());
+
+        leaderboard.add_match(&[
+            standing("a", 3, 0, true),
+            standing("b", 0, 0, true),
+            standing("c", 1, 1, false),
+        ]);
+        // `b` lost to `c` but not to its teammate `a`
+        assert!(leaderboard.entries["a"].rating > INITIAL_RATING);
+        assert!(leaderboard.entries["b"].rating < INITIAL_RATING);
+        assert!((leaderboard.entries["c"].rating - INITIAL_RATING).abs() < 1e-9);
+    }
+}
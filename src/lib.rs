@@ -4,10 +4,27 @@ mod gamestate;
 mod lobby;
 mod score;
 
+mod arena;
 mod bomb;
+mod bot;
+mod grid;
+mod hazard;
 mod player;
+mod powerup;
 mod rock;
 
+mod leaderboard;
+mod loadtest;
+mod map;
+mod map_editor;
+mod map_validator;
+mod mapgen;
+mod profile;
+mod replay;
+mod replay_viewer;
+mod rules;
+mod settings;
+mod stats;
 mod utils;
 
 fn init(handle: InitHandle) {
@@ -17,6 +34,12 @@ fn init(handle: InitHandle) {
     handle.add_class::<player::Player>();
     handle.add_class::<bomb::Bomb>();
     handle.add_class::<rock::Rock>();
+    handle.add_class::<powerup::PowerUp>();
+    handle.add_class::<replay_viewer::ReplayViewer>();
+    handle.add_class::<map_editor::MapEditor>();
+    handle.add_class::<loadtest::LoadTest>();
+    handle.add_class::<loadtest::LoadTestClient>();
+    handle.add_class::<loadtest::LoadTestSink>();
 }
 
 godot_init!(init);
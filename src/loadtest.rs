@@ -0,0 +1,974 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bomb::BombKind;
+use crate::gamestate::DEFAULT_PORT;
+use crate::grid::CELL_SIZE;
+use crate::map::{self, MapLayout};
+use crate::player::MOTION_SPEED;
+use crate::rules::MatchRules;
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Stats are printed every 5 seconds.
+const REPORT_INTERVAL: f64 = 5.0;
+
+/// Rough size of an RPC packet without its arguments (node path id, method name, ENet header).
+const RPC_OVERHEAD_BYTES: u64 = 12;
+
+/// Fake clients place a bomb every 2-5 seconds.
+const BOMB_INTERVAL: (f64, f64) = (2.0, 5.0);
+
+/// Same as the `stunned` animation of `Player.tscn`.
+const STUN_DURATION: f64 = 1.2;
+
+/// Seconds a mirrored bomb is kept after its fuse, same as the blast of `Bomb`.
+const BLAST_DURATION: f64 = 0.6;
+
+/// RPC traffic of a single fake client.
+#[derive(Clone, Copy, Default)]
+struct Traffic {
+    rpcs_sent: u64,
+    rpcs_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, other: &Traffic) {
+        self.rpcs_sent += other.rpcs_sent;
+        self.rpcs_received += other.rpcs_received;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+    }
+}
+
+/// State of a fake client shared by the client node and its `LoadTestSink`s.
+#[derive(Default)]
+struct ClientState {
+    id: i64,
+    connected: bool,
+    /// Player ids, filled by `register_player`
+    roster: Vec<i64>,
+    /// `create_world` of a new round was received
+    world_requested: bool,
+    world_created: bool,
+    /// Fuse of bombs from the rules of `create_world`
+    fuse_time: f64,
+    /// Map of the round from `create_world`, `None` for the one baked into `World.tscn`
+    layout: Option<MapLayout>,
+    /// Size in tiles and first spawn point of the baked map
+    baked_size: (i32, i32),
+    baked_spawn: Vector2,
+    /// Rocks of the biggest map so far, generated maps can have more than the mirror
+    rock_count: usize,
+    rock_sinks: usize,
+
+    position: Vector2,
+    target: Vector2,
+    stunned_for: f64,
+    /// `exploded(by_who)` was received, `stun(by_who)` should be sent back
+    pending_stun: Option<i64>,
+    /// `arena_closed(position)` was received, `crush(position)` should be sent back
+    pending_crush: Option<Vector2>,
+    bomb_cooldown: f64,
+
+    traffic: Traffic,
+}
+
+impl ClientState {
+    /// Size of the map of the round in tiles.
+    fn map_size(&self) -> (i32, i32) {
+        match &self.layout {
+            Some(layout) => (layout.width, layout.height),
+            None => self.baked_size,
+        }
+    }
+
+    /// Players take turns on the spawn points of map files by id, like `GameState` places them.
+    fn spawn(&self) -> Vector2 {
+        let layout = match &self.layout {
+            Some(layout) if !layout.spawns.is_empty() => layout,
+            _ => return self.baked_spawn,
+        };
+
+        let mut ids = self.roster.clone();
+        ids.sort_unstable();
+        let slot = ids.iter().position(|id| *id == self.id).unwrap_or(0);
+        let cell = layout.spawns[slot % layout.spawns.len()];
+
+        Vector2::new(
+            (cell.0 as f32 + 0.5) * CELL_SIZE,
+            (cell.1 as f32 + 0.5) * CELL_SIZE,
+        )
+    }
+}
+
+type SharedState = Rc<RefCell<ClientState>>;
+
+/// Estimated packet size of an RPC.
+fn rpc_size(method: &str, args: &[Variant]) -> u64 {
+    let marshalls = Marshalls::godot_singleton();
+
+    let args_size: u64 = args
+        .iter()
+        .map(|arg| marshalls.variant_to_base64(arg.clone(), false).len() as u64 * 3 / 4)
+        .sum();
+
+    RPC_OVERHEAD_BYTES + method.len() as u64 + args_size
+}
+
+/// `--loadtest N`: starts N scripted fake clients in this process and reports server load.
+///
+/// Without `--connect` the server is hosted in this process too and the match starts
+/// as soon as every fake client is registered, so the reported tick time is the server's one.
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct LoadTest {
+    clients_count: i64,
+    connect_address: Option<String>,
+
+    clients: Vec<SharedState>,
+    started: bool,
+
+    report_timer: f64,
+    /// Traffic at the time of the last report
+    last_traffic: Traffic,
+}
+
+#[methods]
+impl LoadTest {
+    fn new(_owner: TRef<Node>) -> Self {
+        LoadTest {
+            clients_count: 0,
+            connect_address: None,
+
+            clients: Vec::new(),
+            started: false,
+
+            report_timer: 0.0,
+            last_traffic: Traffic::default(),
+        }
+    }
+
+    /// Creating the load test node, it should be added to the root.
+    pub fn new_instance(clients_count: i64, connect_address: Option<String>) -> Ref<Node> {
+        let loadtest = Instance::<LoadTest, Unique>::new();
+        loadtest
+            .map_mut(|loadtest, _| {
+                loadtest.clients_count = clients_count;
+                loadtest.connect_address = connect_address;
+            })
+            .unwrap();
+
+        let loadtest = loadtest.into_base();
+        loadtest.set_name("LoadTest");
+        loadtest.into_shared()
+    }
+
+    #[export]
+    fn _ready(&mut self, owner: TRef<Node>) {
+        godot_print!(
+            "[loadtest] starting {} fake clients (server: {})",
+            self.clients_count,
+            self.connect_address
+                .as_deref()
+                .unwrap_or("hosted in this process")
+        );
+
+        if self.is_hosting() {
+            let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+            let func_args = VariantArray::new();
+            func_args.push("LoadTest Host");
+            unsafe {
+                gamestate.callv("host_game", func_args.into_shared());
+            }
+        }
+
+        let address = self
+            .connect_address
+            .clone()
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+
+        for index in 0..self.clients_count {
+            let state: SharedState = Rc::new(RefCell::new(ClientState::default()));
+
+            let client = Instance::<LoadTestClient, Unique>::new();
+            client
+                .map_mut(|client, _| {
+                    client.state = state.clone();
+                    client.address = address.clone();
+                })
+                .unwrap();
+
+            let client = client.into_base();
+            client.set_name(format!("Client{}", index));
+            owner.add_child(client, false);
+
+            self.clients.push(state);
+        }
+
+        owner.set_process(true);
+    }
+
+    #[export]
+    fn _process(&mut self, owner: TRef<Node>, delta: f64) {
+        if self.is_hosting() && !self.started {
+            self.start_when_everyone_registered(owner);
+        }
+
+        self.report_timer += delta;
+        if self.report_timer >= REPORT_INTERVAL {
+            self.report();
+            self.report_timer = 0.0;
+        }
+    }
+
+    fn is_hosting(&self) -> bool {
+        self.connect_address.is_none()
+    }
+
+    /// Fake clients and the host itself
+    fn start_when_everyone_registered(&mut self, owner: TRef<Node>) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let registered = gamestate.get("players").to_dictionary().len() as i64;
+
+        if registered == self.clients_count + 1 {
+            godot_print!("[loadtest] everyone is registered, starting the match");
+
+            unsafe {
+                gamestate.callv("start_game", VariantArray::new_shared());
+            }
+            self.started = true;
+        }
+    }
+
+    fn report(&mut self) {
+        let mut traffic = Traffic::default();
+        let mut connected = 0;
+        let mut in_match = 0;
+
+        for client in self.clients.iter() {
+            let client = client.borrow();
+            traffic.add(&client.traffic);
+            if client.connected {
+                connected += 1;
+            }
+            if client.world_created {
+                in_match += 1;
+            }
+        }
+
+        let per_second = |now: u64, before: u64| (now - before) as f64 / self.report_timer;
+
+        let performance = Performance::godot_singleton();
+        let process_ms = performance.get_monitor(Performance::TIME_PROCESS) * 1000.0;
+        let physics_ms = performance.get_monitor(Performance::TIME_PHYSICS_PROCESS) * 1000.0;
+        let fps = performance.get_monitor(Performance::TIME_FPS);
+
+        let sent_rps = per_second(traffic.rpcs_sent, self.last_traffic.rpcs_sent);
+        let received_rps = per_second(traffic.rpcs_received, self.last_traffic.rpcs_received);
+        let up_kib = per_second(traffic.bytes_sent, self.last_traffic.bytes_sent) / 1024.0;
+        let down_kib =
+            per_second(traffic.bytes_received, self.last_traffic.bytes_received) / 1024.0;
+
+        // Monitors of this process, the server is only measured when it's hosted here
+        let tick = if self.connect_address.is_some() {
+            "client tick"
+        } else {
+            "server tick"
+        };
+        godot_print!(
+            "[loadtest] clients: {}/{} connected, {} in match | {}: {:.2} ms (physics {:.2} ms), {} fps",
+            connected,
+            self.clients_count,
+            in_match,
+            tick,
+            process_ms,
+            physics_ms,
+            fps
+        );
+        // What the clients send is what the server receives and the other way around.
+        // Bytes are estimated from the RPC arguments by `rpc_size`, not counted by ENet.
+        godot_print!(
+            "[loadtest] server RPCs: in {:.0}/s, out {:.0}/s | estimated bandwidth: in {:.1} KiB/s, out {:.1} KiB/s",
+            sent_rps,
+            received_rps,
+            up_kib,
+            down_kib
+        );
+
+        self.last_traffic = traffic;
+    }
+}
+
+/// A scripted fake client with its own `MultiplayerAPI`, so many of them fit in one process.
+///
+/// RPC paths are relative to the multiplayer root node,
+/// so the client mirrors the nodes the server talks to with `LoadTestSink`s:
+/// `gamestate`, `World/Players/<id>`, `World/Score` and `World/Rocks/<name>`.
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct LoadTestClient {
+    state: SharedState,
+    address: String,
+    multiplayer: Option<Ref<MultiplayerAPI>>,
+    rng: RefCell<Option<StdRng>>,
+}
+
+#[methods]
+impl LoadTestClient {
+    fn new(_owner: TRef<Node>) -> Self {
+        LoadTestClient {
+            state: Rc::new(RefCell::new(ClientState::default())),
+            address: "127.0.0.1".to_string(),
+            multiplayer: None,
+            rng: RefCell::new(None),
+        }
+    }
+
+    #[export]
+    fn _ready(&mut self, owner: TRef<Node>) {
+        let multiplayer = MultiplayerAPI::new().into_shared();
+        let multiplayer_ref = unsafe { multiplayer.assume_safe() };
+
+        let peer = NetworkedMultiplayerENet::new();
+        if let Err(e) = peer.create_client(self.address.as_str(), DEFAULT_PORT, 0, 0, 0) {
+            godot_error!(
+                "`LoadTestClient` => GodotError at `create_client` function: {}",
+                e
+            );
+            return;
+        }
+
+        multiplayer_ref.set_root_node(owner);
+        multiplayer_ref.set_network_peer(peer);
+        owner.set_custom_multiplayer(multiplayer.clone());
+
+        if let Err(e) = multiplayer_ref.connect(
+            "connected_to_server",
+            owner,
+            "_connected_ok",
+            VariantArray::new_shared(),
+            0,
+        ) {
+            godot_error!(
+                "`LoadTestClient` => GodotError at `connect` function: {}",
+                e
+            );
+        }
+
+        self.multiplayer = Some(multiplayer);
+        *self.rng.borrow_mut() = Some(StdRng::from_entropy());
+
+        self.add_sink(owner, owner, "gamestate", 1);
+
+        owner.set_process(true);
+        owner.set_physics_process(true);
+    }
+
+    #[export]
+    fn _process(&self, owner: TRef<Node>, _delta: f64) {
+        // RPCs and signals are dispatched from here
+        if let Some(multiplayer) = &self.multiplayer {
+            unsafe { multiplayer.assume_safe() }.poll();
+        }
+
+        let (new_round, create_world) = {
+            let state = self.state.borrow();
+            (state.world_requested, !state.world_created)
+        };
+        if new_round {
+            if create_world {
+                self.create_world(owner);
+            }
+
+            // Every round starts from the spawn point
+            let mut state = self.state.borrow_mut();
+            state.world_requested = false;
+            state.position = state.spawn();
+            state.target = state.position;
+        }
+
+        let (rock_sinks, rock_count) = {
+            let state = self.state.borrow();
+            let rock_count = if state.world_created {
+                state.rock_count
+            } else {
+                0
+            };
+            (state.rock_sinks, rock_count)
+        };
+        if rock_count > rock_sinks {
+            // `World/Rocks`
+            let rocks = owner.get_node("World/Rocks").unwrap();
+            let rocks = unsafe { rocks.assume_safe() };
+
+            for index in rock_sinks..rock_count {
+                self.add_sink(owner, rocks, &format!("Rock{}", index), 1);
+            }
+            self.state.borrow_mut().rock_sinks = rock_count;
+        }
+    }
+
+    #[export]
+    fn _physics_process(&self, owner: TRef<Node>, delta: f64) {
+        if !self.state.borrow().world_created {
+            return;
+        }
+
+        let me = self.state.borrow().id;
+        let player = match owner.get_node(format!("World/Players/{}", me)) {
+            Some(player) => unsafe { player.assume_safe() },
+            None => return,
+        };
+
+        let mut rng = self.rng.borrow_mut();
+        let rng = rng.as_mut().unwrap();
+        let mut state = self.state.borrow_mut();
+
+        if let Some(by_who) = state.pending_stun.take() {
+            state.stunned_for = STUN_DURATION;
+            Self::send_relayed(&mut state, player, "stun", &[by_who.to_variant()]);
+        }
+        if let Some(position) = state.pending_crush.take() {
+            state.position = position;
+            state.target = position;
+            state.stunned_for = STUN_DURATION;
+            Self::send_relayed(&mut state, player, "crush", &[position.to_variant()]);
+        }
+
+        let mut anim = "standing";
+        if state.stunned_for > 0.0 {
+            state.stunned_for -= delta;
+            anim = "stunned";
+        } else {
+            // Walking between random points inside the border of the map
+            let diff = state.target - state.position;
+            let step = MOTION_SPEED * delta as f32;
+            if diff.length() <= step {
+                let (width, height) = state.map_size();
+                state.position = state.target;
+                state.target = Vector2::new(
+                    (rng.gen_range(1..(width - 1).max(2)) as f32 + 0.5) * CELL_SIZE,
+                    (rng.gen_range(1..(height - 1).max(2)) as f32 + 0.5) * CELL_SIZE,
+                );
+            } else {
+                state.position += diff.normalize() * step;
+                anim = if diff.x.abs() > diff.y.abs() {
+                    if diff.x < 0.0 {
+                        "walk_left"
+                    } else {
+                        "walk_right"
+                    }
+                } else if diff.y < 0.0 {
+                    "walk_up"
+                } else {
+                    "walk_down"
+                };
+            }
+
+            state.bomb_cooldown -= delta;
+            if state.bomb_cooldown <= 0.0 {
+                state.bomb_cooldown = rng.gen_range(BOMB_INTERVAL.0..BOMB_INTERVAL.1);
+
+                // The server names the bomb and sends `setup_bomb` back
+                let position = state.position;
+                Self::send_to_server(&mut state, player, "request_bomb", &[position.to_variant()]);
+            }
+        }
+
+        let position = state.position;
+        Self::send_relayed(
+            &mut state,
+            player,
+            "update_network",
+            &[position.to_variant(), anim.to_variant()],
+        );
+    }
+
+    /// Connected to the server, registering like `GameState` does.
+    #[export]
+    fn _connected_ok(&self, owner: TRef<Node>) {
+        let multiplayer = unsafe { self.multiplayer.as_ref().unwrap().assume_safe() };
+        let id = multiplayer.get_network_unique_id();
+
+        let mut state = self.state.borrow_mut();
+        state.id = id;
+        state.connected = true;
+        state.roster.push(id);
+
+        let gamestate = owner.get_node("gamestate").unwrap();
+        let gamestate = unsafe { gamestate.assume_safe() };
+
+        let name = format!("LoadBot {}", owner.name());
+        Self::send(
+            &mut state,
+            gamestate,
+            "register_player",
+            &[id.to_variant(), name.to_variant()],
+        );
+    }
+
+    /// Mirroring the world nodes the server sends RPCs to.
+    fn create_world(&self, owner: TRef<Node>) {
+        let world = Node::new();
+        world.set_name("World");
+        let world = unsafe { world.into_shared().assume_safe() };
+        owner.add_child(world, false);
+
+        let players = Node::new();
+        players.set_name("Players");
+        let players = unsafe { players.into_shared().assume_safe() };
+        world.add_child(players, false);
+
+        let rocks = Node::new();
+        rocks.set_name("Rocks");
+        let rocks = unsafe { rocks.into_shared().assume_safe() };
+        world.add_child(rocks, false);
+
+        // Filled by `drop_powerup` of rocks
+        let powerups = Node::new();
+        powerups.set_name("PowerUps");
+        let powerups = unsafe { powerups.into_shared().assume_safe() };
+        world.add_child(powerups, false);
+
+        self.add_sink(owner, world, "Score", 1);
+
+        // Rock names and the baked map come from the world scene, it's never added to the tree here
+        let world_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/World/World.tscn", "PackedScene", false)
+            .unwrap();
+        let world_scene = unsafe { world_scene.assume_safe() };
+        let world_scene = world_scene.cast::<PackedScene>().unwrap();
+        let map = world_scene
+            .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+            .unwrap();
+        let map = unsafe { map.assume_unique() };
+
+        let mut spawn = Vector2::new(72.0, 72.0);
+        let map_rocks = unsafe { map.get_node("Rocks").unwrap().assume_safe() }.get_children();
+        for rock in map_rocks.iter() {
+            let rock = unsafe { rock.try_to_object::<Node>().unwrap().assume_safe() };
+            self.add_sink(owner, rocks, &rock.name().to_string(), 1);
+        }
+        if let Some(spawn_point) = map.get_node("SpawnPoints/0") {
+            let spawn_point = unsafe { spawn_point.assume_safe() };
+            spawn = spawn_point.cast::<Position2D>().unwrap().position();
+        }
+        let tilemap = unsafe { map.get_node("TileMap").unwrap().assume_safe() };
+        let used = tilemap.cast::<TileMap>().unwrap().get_used_rect();
+        let size = (
+            (used.origin.x + used.size.width) as i32,
+            (used.origin.y + used.size.height) as i32,
+        );
+        map.free();
+
+        let mut state = self.state.borrow_mut();
+        for id in state.roster.iter() {
+            self.add_sink(owner, players, &id.to_string(), *id);
+        }
+
+        state.baked_size = size;
+        state.baked_spawn = spawn;
+        state.rock_sinks = map_rocks.len() as usize;
+        state.world_created = true;
+    }
+
+    fn add_sink(&self, owner: TRef<Node>, parent: TRef<Node>, name: &str, network_master: i64) {
+        let sink = Instance::<LoadTestSink, Unique>::new();
+        sink.map_mut(|sink, _| sink.state = Some(self.state.clone()))
+            .unwrap();
+
+        let sink = sink.into_base();
+        sink.set_name(name);
+        sink.set_network_master(network_master, false);
+        if let Some(multiplayer) = owner.custom_multiplayer() {
+            sink.set_custom_multiplayer(multiplayer);
+        }
+
+        parent.add_child(sink, false);
+    }
+
+    fn send(state: &mut ClientState, node: TRef<Node>, method: &str, args: &[Variant]) {
+        state.traffic.rpcs_sent += 1;
+        state.traffic.bytes_sent += rpc_size(method, args);
+
+        node.rpc(method, args);
+    }
+
+    fn send_to_server(state: &mut ClientState, node: TRef<Node>, method: &str, args: &[Variant]) {
+        state.traffic.rpcs_sent += 1;
+        state.traffic.bytes_sent += rpc_size(method, args);
+
+        node.rpc_id(1, method, args);
+    }
+
+    /// Updates of the own player go through the server, like `Player` sends them.
+    fn send_relayed(state: &mut ClientState, node: TRef<Node>, method: &str, args: &[Variant]) {
+        let args = args
+            .iter()
+            .cloned()
+            .collect::<VariantArray<Unique>>()
+            .into_shared();
+        Self::send_to_server(
+            state,
+            node,
+            "relay",
+            &[method.to_variant(), args.to_variant()],
+        );
+    }
+}
+
+/// Receives RPCs for a fake client, counts them and keeps the roster up to date.
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct LoadTestSink {
+    state: Option<SharedState>,
+}
+
+#[methods]
+impl LoadTestSink {
+    fn new(_owner: TRef<Node>) -> Self {
+        LoadTestSink { state: None }
+    }
+
+    fn received(&self, method: &str, args: &[Variant]) {
+        if let Some(state) = &self.state {
+            let mut state = state.borrow_mut();
+            state.traffic.rpcs_received += 1;
+            state.traffic.bytes_received += rpc_size(method, args);
+        }
+    }
+
+    // `gamestate`
+
+    #[export(rpc = "remote")]
+    fn register_player(&self, _owner: TRef<Node>, id: Variant, p_name: Variant) {
+        self.received("register_player", &[id.clone(), p_name]);
+
+        if let Some(state) = &self.state {
+            state.borrow_mut().roster.push(id.to_i64());
+        }
+    }
+
+    #[export(rpc = "remote")]
+    fn unregister_player(&self, _owner: TRef<Node>, id: i64) {
+        self.received("unregister_player", &[id.to_variant()]);
+
+        if let Some(state) = &self.state {
+            state.borrow_mut().roster.retain(|player| *player != id);
+        }
+    }
+
+    /// Map files aren't downloaded, the server is expected to run the same build
+    #[export(rpc = "puppet")]
+    fn check_map(&self, owner: TRef<Node>, hash: String) {
+        self.received("check_map", &[hash.to_variant()]);
+
+        if let Some(state) = &self.state {
+            LoadTestClient::send_to_server(&mut state.borrow_mut(), owner, "map_ready", &[]);
+        }
+    }
+
+    /// Rocks and players keep their names between rounds, the mirror is created once
+    /// and only gets more rocks for bigger generated maps
+    #[export(rpc = "remote")]
+    fn create_world(
+        &self,
+        _owner: TRef<Node>,
+        seed: i64,
+        round: i64,
+        rules: Dictionary,
+        map: Dictionary,
+    ) {
+        self.received(
+            "create_world",
+            &[
+                seed.to_variant(),
+                round.to_variant(),
+                rules.to_variant(),
+                map.to_variant(),
+            ],
+        );
+
+        if let Some(state) = &self.state {
+            let mut state = state.borrow_mut();
+            state.world_requested = true;
+            state.fuse_time = MatchRules::from_dictionary(&rules).fuse_time;
+            let layout = if map.contains("hash") {
+                map::find_map(&map.get("hash").to_string())
+                    .and_then(|path| MapLayout::load(&path).ok())
+            } else if map.contains("tiles") {
+                MapLayout::from_dictionary(&map).ok()
+            } else {
+                None
+            };
+            if let Some(layout) = &layout {
+                state.rock_count = state.rock_count.max(layout.rocks.len());
+            }
+            state.layout = layout;
+        }
+    }
+
+    #[export(rpc = "puppet")]
+    fn round_finished(&self, _owner: TRef<Node>, winner: i64) {
+        self.received("round_finished", &[winner.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn update_teams(&self, _owner: TRef<Node>, teams: i64, team_choices: Dictionary) {
+        self.received(
+            "update_teams",
+            &[teams.to_variant(), team_choices.to_variant()],
+        );
+    }
+
+    #[export(rpc = "puppet")]
+    fn update_maps(
+        &self,
+        _owner: TRef<Node>,
+        map_choices: VariantArray,
+        map_choice: i64,
+        map_vote: bool,
+        map_votes: Dictionary,
+        map_rotation: bool,
+    ) {
+        self.received(
+            "update_maps",
+            &[
+                map_choices.to_variant(),
+                map_choice.to_variant(),
+                map_vote.to_variant(),
+                map_votes.to_variant(),
+                map_rotation.to_variant(),
+            ],
+        );
+    }
+
+    #[export(rpc = "puppet")]
+    fn hurry_up_started(&self, _owner: TRef<Node>) {
+        self.received("hurry_up_started", &[]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn close_arena_cell(&self, _owner: TRef<Node>, x: i32, y: i32) {
+        self.received("close_arena_cell", &[x.to_variant(), y.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn update_timer(&self, _owner: TRef<Node>, seconds_left: i64, sudden_death: bool) {
+        self.received(
+            "update_timer",
+            &[seconds_left.to_variant(), sudden_death.to_variant()],
+        );
+    }
+
+    // `World/Players/<id>`
+
+    #[export(rpc = "remote")]
+    fn update_network(&self, _owner: TRef<Node>, position: Variant, current_anim: Variant) {
+        self.received("update_network", &[position, current_anim]);
+    }
+
+    #[export(rpc = "remote")]
+    #[allow(clippy::too_many_arguments)]
+    fn setup_bomb(
+        &self,
+        owner: TRef<Node>,
+        bomb_name: Variant,
+        bomb_pos: Variant,
+        network_unique_id: Variant,
+        explode_at: Variant,
+        kind: i64,
+        blast_range: Variant,
+    ) {
+        self.received(
+            "setup_bomb",
+            &[
+                bomb_name.clone(),
+                bomb_pos,
+                network_unique_id,
+                explode_at,
+                kind.to_variant(),
+                blast_range,
+            ],
+        );
+
+        // Chain reactions are sent to bombs, they go away after their blast.
+        // Bombs without a fuse stay until they are set off.
+        let fuse_time = self
+            .state
+            .as_ref()
+            .map_or(0.0, |state| state.borrow().fuse_time);
+        let lifetime = if BombKind::from_i64(kind).has_fuse() {
+            Some(fuse_time + BLAST_DURATION)
+        } else {
+            None
+        };
+        if let Some(world) = owner.get_node("../..") {
+            let world = unsafe { world.assume_safe() };
+            self.add_mirror(owner, world, &bomb_name.to_string(), lifetime);
+        }
+    }
+
+    #[export(rpc = "remote")]
+    fn stun(&self, _owner: TRef<Node>, by_who: Variant) {
+        self.received("stun", &[by_who]);
+    }
+
+    #[export(rpc = "remote")]
+    fn add_powerup(&self, _owner: TRef<Node>, kind: i64) {
+        self.received("add_powerup", &[kind.to_variant()]);
+    }
+
+    #[export(rpc = "remote")]
+    fn lose_shield(&self, _owner: TRef<Node>) {
+        self.received("lose_shield", &[]);
+    }
+
+    #[export(rpc = "master")]
+    fn exploded(&self, _owner: TRef<Node>, by_who: Variant) {
+        self.received("exploded", std::slice::from_ref(&by_who));
+
+        if let Some(state) = &self.state {
+            let mut state = state.borrow_mut();
+            if state.stunned_for <= 0.0 {
+                state.pending_stun = Some(by_who.to_i64());
+            }
+        }
+    }
+
+    #[export(rpc = "master")]
+    fn arena_closed(&self, _owner: TRef<Node>, position: Vector2) {
+        self.received("arena_closed", &[position.to_variant()]);
+
+        if let Some(state) = &self.state {
+            state.borrow_mut().pending_crush = Some(position);
+        }
+    }
+
+    #[export(rpc = "remote")]
+    fn crush(&self, _owner: TRef<Node>, position: Vector2) {
+        self.received("crush", &[position.to_variant()]);
+    }
+
+    // `World/Score`
+
+    #[export(rpc = "remote")]
+    fn increase_score(&self, _owner: TRef<Node>, for_who: Variant) {
+        self.received("increase_score", &[for_who]);
+    }
+
+    // `World/Rocks/<name>`
+
+    #[export(rpc = "remote")]
+    fn do_explosion(&self, _owner: TRef<Node>) {
+        self.received("do_explosion", &[]);
+    }
+
+    /// The power-up is mirrored too, it's picked up by name.
+    /// Rock names are reused every round, so is the mirror.
+    #[export(rpc = "puppet")]
+    fn drop_powerup(&self, owner: TRef<Node>, kind: i64) {
+        self.received("drop_powerup", &[kind.to_variant()]);
+
+        if let Some(powerups) = owner.get_node("../../PowerUps") {
+            let powerups = unsafe { powerups.assume_safe() };
+            self.add_mirror(owner, powerups, &owner.name().to_string(), None);
+        }
+    }
+
+    /// A sink for a node created during the round, owned by the server.
+    /// `lifetime`: seconds before the sink is freed, `None` keeps it.
+    fn add_mirror(&self, owner: TRef<Node>, parent: TRef<Node>, name: &str, lifetime: Option<f64>) {
+        if parent.has_node(name) {
+            return;
+        }
+
+        let sink = Instance::<LoadTestSink, Unique>::new();
+        sink.map_mut(|sink, _| sink.state = self.state.clone())
+            .unwrap();
+
+        let sink = sink.into_base();
+        sink.set_name(name);
+        sink.set_network_master(1, false);
+        if let Some(multiplayer) = owner.custom_multiplayer() {
+            sink.set_custom_multiplayer(multiplayer);
+        }
+        let sink = unsafe { sink.into_shared().assume_safe() };
+
+        if let Some(lifetime) = lifetime {
+            let timer = unsafe { Timer::new().into_shared().assume_safe() };
+            timer.set_wait_time(lifetime);
+            timer.set_one_shot(true);
+            timer.set_autostart(true);
+            if let Err(e) =
+                timer.connect("timeout", sink, "queue_free", VariantArray::new_shared(), 0)
+            {
+                godot_error!(
+                    "`LoadTestSink` => GodotError at `timer.connect` function: {}",
+                    e
+                );
+            }
+            sink.add_child(timer, false);
+        }
+
+        parent.add_child(sink, false);
+    }
+
+    // `World/<bomb name>`
+
+    #[export(rpc = "puppet")]
+    fn detonate(&self, owner: TRef<Node>, chain_depth: i64, set_off_by: String) {
+        self.received(
+            "detonate",
+            &[chain_depth.to_variant(), set_off_by.to_variant()],
+        );
+
+        // The bomb explodes, nothing else is sent to it
+        owner.queue_free();
+    }
+
+    #[export(rpc = "puppet")]
+    fn trigger(&self, owner: TRef<Node>) {
+        self.received("trigger", &[]);
+        owner.queue_free();
+    }
+
+    #[export(rpc = "puppet")]
+    fn solidify(&self, _owner: TRef<Node>, passing: VariantArray) {
+        self.received("solidify", &[passing.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn let_through(&self, _owner: TRef<Node>, passing: VariantArray) {
+        self.received("let_through", &[passing.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn pick_up(&self, _owner: TRef<Node>, carrier: String) {
+        self.received("pick_up", &[carrier.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn put_down(&self, _owner: TRef<Node>, position: Vector2) {
+        self.received("put_down", &[position.to_variant()]);
+    }
+
+    #[export(rpc = "puppet")]
+    fn move_to(&self, _owner: TRef<Node>, target: Vector2, speed: f64) {
+        self.received("move_to", &[target.to_variant(), speed.to_variant()]);
+    }
+
+    // `World/PowerUps/<name>`
+
+    #[export(rpc = "puppet")]
+    fn picked_up(&self, _owner: TRef<Node>) {
+        self.received("picked_up", &[]);
+    }
+}
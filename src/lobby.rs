@@ -1,3 +1,8 @@
+use crate::bot::Difficulty;
+use crate::leaderboard;
+use crate::map::MapLayout;
+use crate::replay;
+use crate::rules::{self, GameMode};
 use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
@@ -17,6 +22,12 @@ pub struct Lobby {
     connect_host: Option<Ref<Node>>,
     /// Button
     connect_join: Option<Ref<Node>>,
+    /// Button
+    connect_replays: Option<Ref<Node>>,
+    /// Button
+    connect_profile: Option<Ref<Node>>,
+    /// Button
+    connect_map_editor: Option<Ref<Node>>,
 
     /// AcceptDialog
     error_dialog: Option<Ref<Node>>,
@@ -29,6 +40,61 @@ pub struct Lobby {
 
     /// Button
     players_start: Option<Ref<Node>>,
+    /// OptionButton
+    players_mode: Option<Ref<Node>>,
+    /// OptionButton
+    players_teams: Option<Ref<Node>>,
+    /// OptionButton
+    players_team: Option<Ref<Node>>,
+    /// CheckBox
+    players_friendly_fire: Option<Ref<Node>>,
+    /// OptionButton
+    players_bot_difficulty: Option<Ref<Node>>,
+    /// Button
+    players_add_bot: Option<Ref<Node>>,
+    /// Button
+    players_remove_bot: Option<Ref<Node>>,
+    /// Button
+    players_leaderboard: Option<Ref<Node>>,
+    /// OptionButton
+    players_map: Option<Ref<Node>>,
+    /// TextureRect
+    players_map_preview: Option<Ref<Node>>,
+    /// Label
+    players_map_info: Option<Ref<Node>>,
+    /// CheckBox
+    players_map_vote: Option<Ref<Node>>,
+
+    /// Panel
+    replays: Option<Ref<Node>>,
+    /// ItemList
+    replays_list: Option<Ref<Node>>,
+    /// Button
+    replays_watch: Option<Ref<Node>>,
+    /// Button
+    replays_back: Option<Ref<Node>>,
+
+    /// Panel
+    profile: Option<Ref<Node>>,
+    /// Label
+    profile_name: Option<Ref<Node>>,
+    /// ColorPickerButton
+    profile_color: Option<Ref<Node>>,
+    /// Label
+    profile_stats: Option<Ref<Node>>,
+    /// Button
+    profile_back: Option<Ref<Node>>,
+
+    /// Panel
+    leaderboard: Option<Ref<Node>>,
+    /// ItemList
+    leaderboard_list: Option<Ref<Node>>,
+    /// Button
+    leaderboard_export: Option<Ref<Node>>,
+    /// Button
+    leaderboard_back: Option<Ref<Node>>,
+    /// Rows received from the server, for the CSV export
+    leaderboard_rows: VariantArray,
 }
 
 #[methods]
@@ -42,18 +108,75 @@ impl Lobby {
             connect_address: None,
             connect_host: None,
             connect_join: None,
+            connect_replays: None,
+            connect_profile: None,
+            connect_map_editor: None,
 
             error_dialog: None,
 
             players: None,
             players_list: None,
             players_start: None,
+            players_mode: None,
+            players_teams: None,
+            players_team: None,
+            players_friendly_fire: None,
+            players_bot_difficulty: None,
+            players_add_bot: None,
+            players_remove_bot: None,
+            players_leaderboard: None,
+            players_map: None,
+            players_map_preview: None,
+            players_map_info: None,
+            players_map_vote: None,
+
+            replays: None,
+            replays_list: None,
+            replays_watch: None,
+            replays_back: None,
+
+            profile: None,
+            profile_name: None,
+            profile_color: None,
+            profile_stats: None,
+            profile_back: None,
+
+            leaderboard: None,
+            leaderboard_list: None,
+            leaderboard_export: None,
+            leaderboard_back: None,
+            leaderboard_rows: VariantArray::new_shared(),
         }
     }
 
     #[export]
     fn _ready(&mut self, owner: TRef<Control>) {
         self.update_child_nodes(owner);
+
+        let players_bot_difficulty = self.get_players_bot_difficulty();
+        for difficulty in Difficulty::ALL.iter() {
+            players_bot_difficulty.add_item(difficulty.name(), -1);
+        }
+        players_bot_difficulty.select(1); // Normal
+
+        let players_mode = self.get_players_mode();
+        for mode in GameMode::ALL.iter() {
+            players_mode.add_item(mode.name(), -1);
+        }
+        players_mode.select(0); // Score
+
+        let players_teams = self.get_players_teams();
+        players_teams.add_item("No teams", -1);
+        for teams in 2..=rules::MAX_TEAMS {
+            players_teams.add_item(format!("{} teams", teams), -1);
+        }
+        players_teams.select(0);
+
+        // The last used name, from the profile
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        self.get_connect_name()
+            .set_text(gamestate.get("player_name").to_godot_string());
+
         if let Err(e) = self.connect_signals(owner) {
             godot_error!("`Lobby` => GodotError at `connect_signals` function: {}", e);
         }
@@ -66,16 +189,62 @@ impl Lobby {
         self.connect_address = owner.get_node("Connect/IPAddress");
         self.connect_host = owner.get_node("Connect/Host");
         self.connect_join = owner.get_node("Connect/Join");
+        self.connect_replays = owner.get_node("Connect/Replays");
+        self.connect_profile = owner.get_node("Connect/Profile");
+        self.connect_map_editor = owner.get_node("Connect/MapEditor");
         self.error_dialog = owner.get_node("ErrorDialog");
         self.players = owner.get_node("Players");
         self.players_list = owner.get_node("Players/List");
         self.players_start = owner.get_node("Players/Start");
+        self.players_mode = owner.get_node("Players/Mode");
+        self.players_teams = owner.get_node("Players/Teams");
+        self.players_team = owner.get_node("Players/Team");
+        self.players_friendly_fire = owner.get_node("Players/FriendlyFire");
+        self.players_bot_difficulty = owner.get_node("Players/BotDifficulty");
+        self.players_add_bot = owner.get_node("Players/AddBot");
+        self.players_remove_bot = owner.get_node("Players/RemoveBot");
+        self.players_leaderboard = owner.get_node("Players/Leaderboard");
+        self.players_map = owner.get_node("Players/Map");
+        self.players_map_preview = owner.get_node("Players/MapPreview");
+        self.players_map_info = owner.get_node("Players/MapInfo");
+        self.players_map_vote = owner.get_node("Players/MapVote");
+        self.replays = owner.get_node("Replays");
+        self.replays_list = owner.get_node("Replays/List");
+        self.replays_watch = owner.get_node("Replays/Watch");
+        self.replays_back = owner.get_node("Replays/Back");
+        self.profile = owner.get_node("Profile");
+        self.profile_name = owner.get_node("Profile/Name");
+        self.profile_color = owner.get_node("Profile/Color");
+        self.profile_stats = owner.get_node("Profile/Stats");
+        self.profile_back = owner.get_node("Profile/Back");
+        self.leaderboard = owner.get_node("Leaderboard");
+        self.leaderboard_list = owner.get_node("Leaderboard/List");
+        self.leaderboard_export = owner.get_node("Leaderboard/Export");
+        self.leaderboard_back = owner.get_node("Leaderboard/Back");
     }
 
     fn connect_signals(&self, owner: TRef<Control>) -> Result<(), GodotError> {
         let players_start = self.get_players_start();
         let connect_host = self.get_connect_host();
         let connect_join = self.get_connect_join();
+        let connect_replays = self.get_connect_replays();
+        let players_add_bot = self.get_players_add_bot();
+        let players_mode = self.get_players_mode();
+        let players_teams = self.get_players_teams();
+        let players_team = self.get_players_team();
+        let players_friendly_fire = self.get_players_friendly_fire();
+        let players_remove_bot = self.get_players_remove_bot();
+        let replays_watch = self.get_replays_watch();
+        let replays_back = self.get_replays_back();
+        let connect_profile = self.get_connect_profile();
+        let connect_map_editor = self.get_connect_map_editor();
+        let profile_color = self.get_profile_color();
+        let profile_back = self.get_profile_back();
+        let players_leaderboard = self.get_players_leaderboard();
+        let players_map = self.get_players_map();
+        let players_map_vote = self.get_players_map_vote();
+        let leaderboard_export = self.get_leaderboard_export();
+        let leaderboard_back = self.get_leaderboard_back();
 
         players_start.connect(
             "pressed",
@@ -101,6 +270,150 @@ impl Lobby {
             0,
         )?;
 
+        players_mode.connect(
+            "item_selected",
+            owner,
+            "_on_mode_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_teams.connect(
+            "item_selected",
+            owner,
+            "_on_teams_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_team.connect(
+            "item_selected",
+            owner,
+            "_on_team_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_friendly_fire.connect(
+            "toggled",
+            owner,
+            "_on_friendly_fire_toggled",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_map.connect(
+            "item_selected",
+            owner,
+            "_on_map_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_map_vote.connect(
+            "toggled",
+            owner,
+            "_on_map_vote_toggled",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_add_bot.connect(
+            "pressed",
+            owner,
+            "_on_add_bot_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_remove_bot.connect(
+            "pressed",
+            owner,
+            "_on_remove_bot_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        connect_replays.connect(
+            "pressed",
+            owner,
+            "_on_replays_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        replays_watch.connect(
+            "pressed",
+            owner,
+            "_on_watch_replay_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        replays_back.connect(
+            "pressed",
+            owner,
+            "_on_replays_back_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        connect_profile.connect(
+            "pressed",
+            owner,
+            "_on_profile_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        connect_map_editor.connect(
+            "pressed",
+            owner,
+            "_on_map_editor_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        profile_color.connect(
+            "color_changed",
+            owner,
+            "_on_profile_color_changed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        profile_back.connect(
+            "pressed",
+            owner,
+            "_on_profile_back_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        players_leaderboard.connect(
+            "pressed",
+            owner,
+            "_on_leaderboard_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        leaderboard_export.connect(
+            "pressed",
+            owner,
+            "_on_leaderboard_export_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        leaderboard_back.connect(
+            "pressed",
+            owner,
+            "_on_leaderboard_back_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
         Ok(())
     }
 
@@ -190,14 +503,24 @@ impl Lobby {
         let connect = self.get_connect();
         let players = self.get_players();
         let players_list = self.get_players_list();
+        let replays = self.get_replays();
+        let profile = self.get_profile();
+        let leaderboard = self.get_leaderboard();
         let connect_host = self.get_connect_host();
         let connect_join = self.get_connect_join();
-        let world = unsafe { utils::get_world(owner.as_ref()) };
 
-        world.queue_free();
+        // A client can fail before its first world is created
+        let root = unsafe { utils::get_root(owner.as_ref()) };
+        if let Some(world) = root.get_node("World") {
+            unsafe { world.assume_safe() }.queue_free();
+        }
+
         owner.show();
         connect.show();
         players.hide();
+        replays.hide();
+        profile.hide();
+        leaderboard.hide();
         players_list.clear();
         connect_host.set_disabled(false);
         connect_join.set_disabled(false);
@@ -226,6 +549,8 @@ impl Lobby {
         let tree = unsafe { utils::get_tree(owner.as_ref()) };
         let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
         let gamestate_players = gamestate.get("players").to_dictionary();
+        let teams = gamestate.get("teams").to_i64();
+        let team_choices = gamestate.get("team_choices").to_dictionary();
 
         godot_print!("gamestate_players: {:?}", gamestate_players);
 
@@ -237,23 +562,133 @@ impl Lobby {
 
         for (p_id, p_name) in gamestate_players.iter() {
             godot_print!("p: {}", p_name.to_string());
+            let team = team_choices.get(p_id.clone()).to_i64();
             players_list.add_item(
                 p_name.try_to_string().unwrap_or_else(|| "???".to_string())
                     + (if p_id.to_i64() == tree.get_network_unique_id() {
                         " (You)"
                     } else {
                         ""
+                    })
+                    + &(if teams > 0 && team > 0 && team <= teams {
+                        format!(" [{}]", rules::team_name(team))
+                    } else {
+                        String::new()
                     }),
                 Null::null(),
                 true,
             );
         }
 
+        // Teams, "Auto" lets the server balance them
+        let players_teams = self.get_players_teams();
+        players_teams.select(if teams > 0 { teams - 1 } else { 0 });
+
+        let players_team = self.get_players_team();
+        players_team.clear();
+        players_team.add_item("Auto", -1);
+        for team in 1..=teams {
+            players_team.add_item(rules::team_name(team), -1);
+        }
+        let my_team = team_choices.get(tree.get_network_unique_id()).to_i64();
+        players_team.select(if my_team <= teams { my_team } else { 0 });
+        players_team.set_visible(teams > 0);
+
         players_start.set_disabled(!tree.is_network_server());
+        self.get_players_mode()
+            .set_disabled(!tree.is_network_server());
+        players_teams.set_disabled(!tree.is_network_server());
+        self.get_players_friendly_fire()
+            .set_disabled(!tree.is_network_server());
+        self.get_players_bot_difficulty()
+            .set_disabled(!tree.is_network_server());
+        self.get_players_add_bot()
+            .set_disabled(!tree.is_network_server());
+        self.get_players_remove_bot()
+            .set_disabled(!tree.is_network_server());
+
+        // Maps, the votes are counted next to their names
+        let map_choices = gamestate.get("map_choices").to_array();
+        let map_choice = gamestate.get("map_choice").to_i64();
+        let map_vote = gamestate.get("map_vote").to_bool();
+        let map_votes = gamestate.get("map_votes").to_dictionary();
+        let map_rotation = gamestate.get("map_rotation").to_bool();
+
+        let mut votes = vec![0; map_choices.len() as usize];
+        for (_, index) in map_votes.iter() {
+            if let Some(count) = votes.get_mut(index.to_i64() as usize) {
+                *count += 1;
+            }
+        }
+
+        let players_map = self.get_players_map();
+        players_map.clear();
+        for (index, choice) in map_choices.iter().enumerate() {
+            let name = choice.to_dictionary().get("name").to_string();
+            if map_vote && votes[index] > 0 {
+                players_map.add_item(format!("{} ({})", name, votes[index]), -1);
+            } else {
+                players_map.add_item(name, -1);
+            }
+        }
+
+        // My own vote, until the server counts them
+        let my_vote = map_votes.get(tree.get_network_unique_id());
+        let selected = if map_vote && !my_vote.is_nil() {
+            my_vote.to_i64()
+        } else {
+            map_choice
+        };
+        if selected >= 0 && selected < players_map.get_item_count() {
+            players_map.select(selected);
+            self.show_map(
+                Some(map_choices.get(selected as i32).to_dictionary()),
+                gamestate_players.len() as i64,
+            );
+        } else {
+            self.show_map(None, gamestate_players.len() as i64);
+        }
+        players_map.set_disabled(map_rotation || !(map_vote || tree.is_network_server()));
+
+        let players_map_vote = self.get_players_map_vote();
+        players_map_vote.set_pressed(map_vote);
+        players_map_vote.set_disabled(map_rotation || !tree.is_network_server());
 
         godot_print!("Lobby was refreshed!");
     }
 
+    /// Thumbnail and player-count hint of a map of the gamestate's `map_choices`.
+    fn show_map(&self, choice: Option<Dictionary>, players: i64) {
+        let players_map_preview = self.get_players_map_preview();
+        let players_map_info = self.get_players_map_info();
+
+        let choice = match choice {
+            Some(choice) => choice,
+            None => {
+                players_map_preview.set_texture(Null::null());
+                players_map_info.set_text("");
+                return;
+            }
+        };
+
+        if choice.get("path").to_string().is_empty() {
+            players_map_preview.set_texture(Null::null());
+            players_map_info.set_text("A new map every round, with a spawn point for everyone.");
+            return;
+        }
+
+        match MapLayout::from_dictionary(&choice) {
+            Ok(layout) => {
+                players_map_preview.set_texture(layout.thumbnail().upcast::<Texture>());
+                players_map_info.set_text(map_hint(&layout, players));
+            }
+            Err(e) => {
+                players_map_preview.set_texture(Null::null());
+                players_map_info.set_text(format!("Can't read the map: {}", e));
+            }
+        }
+    }
+
     #[export]
     fn change_to_players_lobby(&self, _owner: TRef<Control>) {
         let connect = self.get_connect();
@@ -271,6 +706,307 @@ impl Lobby {
         }
     }
 
+    /// `index` is an index of `GameMode::ALL`
+    #[export]
+    fn _on_mode_selected(&self, owner: TRef<Control>, index: i64) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(index);
+            gamestate.callv("set_game_mode", func_args);
+        }
+    }
+
+    /// `index` 0 is "No teams", then 2, 3, 4... teams
+    #[export]
+    fn _on_teams_selected(&self, owner: TRef<Control>, index: i64) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(if index == 0 { 0 } else { index + 1 });
+            gamestate.callv("set_teams", func_args);
+        }
+    }
+
+    /// `index` 0 is "Auto", then the team itself
+    #[export]
+    fn _on_team_selected(&self, owner: TRef<Control>, index: i64) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(index);
+            gamestate.callv("choose_team", func_args);
+        }
+    }
+
+    #[export]
+    fn _on_friendly_fire_toggled(&self, owner: TRef<Control>, pressed: bool) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(pressed);
+            gamestate.callv("set_friendly_fire", func_args);
+        }
+    }
+
+    /// `index` is an index of the gamestate's `map_choices`,
+    /// a vote when players vote for the map
+    #[export]
+    fn _on_map_selected(&self, owner: TRef<Control>, index: i64) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let method = if gamestate.get("map_vote").to_bool() {
+            "vote_map"
+        } else {
+            "set_map"
+        };
+
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(index);
+            gamestate.callv(method, func_args);
+        }
+    }
+
+    #[export]
+    fn _on_map_vote_toggled(&self, owner: TRef<Control>, pressed: bool) {
+        // `refresh_lobby` toggles it on clients too
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(pressed);
+            gamestate.callv("set_map_vote", func_args);
+        }
+    }
+
+    #[export]
+    fn _on_add_bot_pressed(&self, owner: TRef<Control>) {
+        let difficulty = self.get_players_bot_difficulty().selected();
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(difficulty);
+            gamestate.callv("add_bot", func_args);
+        }
+
+        self.refresh_lobby(owner);
+    }
+
+    #[export]
+    fn _on_remove_bot_pressed(&self, owner: TRef<Control>) {
+        unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("remove_bot", VariantArray::new_shared());
+        }
+
+        self.refresh_lobby(owner);
+    }
+
+    #[export]
+    fn _on_replays_pressed(&self, _owner: TRef<Control>) {
+        let connect = self.get_connect();
+        let replays = self.get_replays();
+        let replays_list = self.get_replays_list();
+
+        replays_list.clear();
+
+        // The newest first
+        let os = OS::godot_singleton();
+        for path in replay::list_replays().iter().rev() {
+            let file_name = path.rsplit('/').next().unwrap_or(path);
+            let started_at: i64 = file_name
+                .split('_')
+                .next()
+                .and_then(|t| t.parse().ok())
+                .unwrap_or(0);
+
+            let date = os.get_datetime_from_unix_time(started_at);
+            let label = match replay::load_header(path) {
+                Ok(header) => format!(
+                    "{}-{:02}-{:02} {:02}:{:02} ({} players)",
+                    date.get("year").to_i64(),
+                    date.get("month").to_i64(),
+                    date.get("day").to_i64(),
+                    date.get("hour").to_i64(),
+                    date.get("minute").to_i64(),
+                    header.roster.len()
+                ),
+                Err(_) => format!("{} (broken)", file_name),
+            };
+
+            replays_list.add_item(label, Null::null(), true);
+            replays_list.set_item_metadata(replays_list.get_item_count() - 1, path.as_str());
+        }
+
+        connect.hide();
+        replays.show();
+    }
+
+    #[export]
+    fn _on_watch_replay_pressed(&self, owner: TRef<Control>) {
+        let replays = self.get_replays();
+        let replays_list = self.get_replays_list();
+
+        let selected = replays_list.get_selected_items();
+        if selected.is_empty() {
+            return;
+        }
+
+        let path = replays_list.get_item_metadata(selected.get(0) as i64);
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        let watching = unsafe {
+            func_args.push(path);
+            gamestate.callv("watch_replay", func_args).to_bool()
+        };
+
+        if !watching {
+            let error_dialog = self.get_error_dialog();
+            error_dialog.set_text("Can't load the replay.");
+            error_dialog.popup_centered_minsize(Vector2::new(0.0, 0.0));
+            return;
+        }
+
+        replays.hide();
+    }
+
+    #[export]
+    fn _on_replays_back_pressed(&self, _owner: TRef<Control>) {
+        let connect = self.get_connect();
+        let replays = self.get_replays();
+
+        replays.hide();
+        connect.show();
+    }
+
+    #[export]
+    fn _on_map_editor_pressed(&self, owner: TRef<Control>) {
+        self.get_connect().hide();
+
+        unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("open_map_editor", VariantArray::new_shared());
+        }
+    }
+
+    #[export]
+    fn _on_profile_pressed(&self, owner: TRef<Control>) {
+        let connect = self.get_connect();
+        let profile = self.get_profile();
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let player_profile = unsafe {
+            gamestate
+                .callv("get_profile", VariantArray::new_shared())
+                .to_dictionary()
+        };
+
+        self.get_profile_name()
+            .set_text(player_profile.get("name").to_godot_string());
+        self.get_profile_color()
+            .set_pick_color(player_profile.get("color").to_color());
+        self.get_profile_stats().set_text(format!(
+            "Matches played: {}\nWins: {}\nRocks destroyed: {}\nStuns dealt: {}\nStuns received: {}",
+            player_profile.get("matches_played").to_i64(),
+            player_profile.get("wins").to_i64(),
+            player_profile.get("rocks_destroyed").to_i64(),
+            player_profile.get("stuns_dealt").to_i64(),
+            player_profile.get("stuns_received").to_i64(),
+        ));
+
+        connect.hide();
+        profile.show();
+    }
+
+    #[export]
+    fn _on_profile_color_changed(&self, owner: TRef<Control>, color: Color) {
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(color);
+            gamestate.callv("set_profile_color", func_args);
+        }
+    }
+
+    #[export]
+    fn _on_profile_back_pressed(&self, _owner: TRef<Control>) {
+        let connect = self.get_connect();
+        let profile = self.get_profile();
+
+        profile.hide();
+        connect.show();
+    }
+
+    /// The leaderboard is kept by the server, it's shown when received.
+    #[export]
+    fn _on_leaderboard_pressed(&self, owner: TRef<Control>) {
+        unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("request_leaderboard", VariantArray::new_shared());
+        }
+    }
+
+    #[export]
+    fn show_leaderboard(&self, _owner: TRef<Control>, rows: VariantArray) {
+        let players = self.get_players();
+        let leaderboard = self.get_leaderboard();
+        let leaderboard_list = self.get_leaderboard_list();
+
+        leaderboard_list.clear();
+        unsafe {
+            self.leaderboard_rows.clear();
+        }
+
+        for (rank, row) in rows.iter().enumerate() {
+            let row_dict = row.to_dictionary();
+            leaderboard_list.add_item(
+                format!(
+                    "{}. {} - {} ({} wins / {} matches)",
+                    rank + 1,
+                    row_dict.get("name").to_string(),
+                    row_dict.get("rating").to_i64(),
+                    row_dict.get("wins").to_i64(),
+                    row_dict.get("matches").to_i64()
+                ),
+                Null::null(),
+                false,
+            );
+
+            unsafe {
+                self.leaderboard_rows.push(row);
+            }
+        }
+
+        players.hide();
+        leaderboard.show();
+    }
+
+    #[export]
+    fn _on_leaderboard_export_pressed(&self, _owner: TRef<Control>) {
+        let error_dialog = self.get_error_dialog();
+
+        match leaderboard::export_csv(&self.leaderboard_rows) {
+            Ok(path) => error_dialog.set_text(format!("Leaderboard exported to\n{}", path)),
+            Err(e) => error_dialog.set_text(format!("Can't export the leaderboard: {}", e)),
+        }
+        error_dialog.popup_centered_minsize(Vector2::new(0.0, 0.0));
+    }
+
+    #[export]
+    fn _on_leaderboard_back_pressed(&self, _owner: TRef<Control>) {
+        let players = self.get_players();
+        let leaderboard = self.get_leaderboard();
+
+        leaderboard.hide();
+        players.show();
+    }
+
     #[export]
     fn _on_find_public_ip_pressed(&self, _owner: TRef<Control>) {
         godot_print!("https://icanhazip.com/");
@@ -314,6 +1050,24 @@ impl Lobby {
         connect_join.cast::<Button>().unwrap()
     }
 
+    fn get_connect_replays(&self) -> TRef<Button> {
+        let connect_replays = self.connect_replays.unwrap();
+        let connect_replays = unsafe { connect_replays.assume_safe() };
+        connect_replays.cast::<Button>().unwrap()
+    }
+
+    fn get_connect_profile(&self) -> TRef<Button> {
+        let connect_profile = self.connect_profile.unwrap();
+        let connect_profile = unsafe { connect_profile.assume_safe() };
+        connect_profile.cast::<Button>().unwrap()
+    }
+
+    fn get_connect_map_editor(&self) -> TRef<Button> {
+        let connect_map_editor = self.connect_map_editor.unwrap();
+        let connect_map_editor = unsafe { connect_map_editor.assume_safe() };
+        connect_map_editor.cast::<Button>().unwrap()
+    }
+
     fn get_error_dialog(&self) -> TRef<AcceptDialog> {
         let error_dialog = self.error_dialog.unwrap();
         let error_dialog = unsafe { error_dialog.assume_safe() };
@@ -337,4 +1091,173 @@ impl Lobby {
         let players_start = unsafe { players_start.assume_safe() };
         players_start.cast::<Button>().unwrap()
     }
+
+    fn get_players_mode(&self) -> TRef<OptionButton> {
+        let players_mode = self.players_mode.unwrap();
+        let players_mode = unsafe { players_mode.assume_safe() };
+        players_mode.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_players_teams(&self) -> TRef<OptionButton> {
+        let players_teams = self.players_teams.unwrap();
+        let players_teams = unsafe { players_teams.assume_safe() };
+        players_teams.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_players_team(&self) -> TRef<OptionButton> {
+        let players_team = self.players_team.unwrap();
+        let players_team = unsafe { players_team.assume_safe() };
+        players_team.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_players_friendly_fire(&self) -> TRef<CheckBox> {
+        let players_friendly_fire = self.players_friendly_fire.unwrap();
+        let players_friendly_fire = unsafe { players_friendly_fire.assume_safe() };
+        players_friendly_fire.cast::<CheckBox>().unwrap()
+    }
+
+    fn get_players_bot_difficulty(&self) -> TRef<OptionButton> {
+        let players_bot_difficulty = self.players_bot_difficulty.unwrap();
+        let players_bot_difficulty = unsafe { players_bot_difficulty.assume_safe() };
+        players_bot_difficulty.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_players_add_bot(&self) -> TRef<Button> {
+        let players_add_bot = self.players_add_bot.unwrap();
+        let players_add_bot = unsafe { players_add_bot.assume_safe() };
+        players_add_bot.cast::<Button>().unwrap()
+    }
+
+    fn get_players_remove_bot(&self) -> TRef<Button> {
+        let players_remove_bot = self.players_remove_bot.unwrap();
+        let players_remove_bot = unsafe { players_remove_bot.assume_safe() };
+        players_remove_bot.cast::<Button>().unwrap()
+    }
+
+    fn get_players_leaderboard(&self) -> TRef<Button> {
+        let players_leaderboard = self.players_leaderboard.unwrap();
+        let players_leaderboard = unsafe { players_leaderboard.assume_safe() };
+        players_leaderboard.cast::<Button>().unwrap()
+    }
+
+    fn get_players_map(&self) -> TRef<OptionButton> {
+        let players_map = self.players_map.unwrap();
+        let players_map = unsafe { players_map.assume_safe() };
+        players_map.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_players_map_preview(&self) -> TRef<TextureRect> {
+        let players_map_preview = self.players_map_preview.unwrap();
+        let players_map_preview = unsafe { players_map_preview.assume_safe() };
+        players_map_preview.cast::<TextureRect>().unwrap()
+    }
+
+    fn get_players_map_info(&self) -> TRef<Label> {
+        let players_map_info = self.players_map_info.unwrap();
+        let players_map_info = unsafe { players_map_info.assume_safe() };
+        players_map_info.cast::<Label>().unwrap()
+    }
+
+    fn get_players_map_vote(&self) -> TRef<CheckBox> {
+        let players_map_vote = self.players_map_vote.unwrap();
+        let players_map_vote = unsafe { players_map_vote.assume_safe() };
+        players_map_vote.cast::<CheckBox>().unwrap()
+    }
+
+    fn get_replays(&self) -> TRef<Panel> {
+        let replays = self.replays.unwrap();
+        let replays = unsafe { replays.assume_safe() };
+        replays.cast::<Panel>().unwrap()
+    }
+
+    fn get_replays_list(&self) -> TRef<ItemList> {
+        let replays_list = self.replays_list.unwrap();
+        let replays_list = unsafe { replays_list.assume_safe() };
+        replays_list.cast::<ItemList>().unwrap()
+    }
+
+    fn get_replays_watch(&self) -> TRef<Button> {
+        let replays_watch = self.replays_watch.unwrap();
+        let replays_watch = unsafe { replays_watch.assume_safe() };
+        replays_watch.cast::<Button>().unwrap()
+    }
+
+    fn get_replays_back(&self) -> TRef<Button> {
+        let replays_back = self.replays_back.unwrap();
+        let replays_back = unsafe { replays_back.assume_safe() };
+        replays_back.cast::<Button>().unwrap()
+    }
+
+    fn get_profile(&self) -> TRef<Panel> {
+        let profile = self.profile.unwrap();
+        let profile = unsafe { profile.assume_safe() };
+        profile.cast::<Panel>().unwrap()
+    }
+
+    fn get_profile_name(&self) -> TRef<Label> {
+        let profile_name = self.profile_name.unwrap();
+        let profile_name = unsafe { profile_name.assume_safe() };
+        profile_name.cast::<Label>().unwrap()
+    }
+
+    fn get_profile_color(&self) -> TRef<ColorPickerButton> {
+        let profile_color = self.profile_color.unwrap();
+        let profile_color = unsafe { profile_color.assume_safe() };
+        profile_color.cast::<ColorPickerButton>().unwrap()
+    }
+
+    fn get_profile_stats(&self) -> TRef<Label> {
+        let profile_stats = self.profile_stats.unwrap();
+        let profile_stats = unsafe { profile_stats.assume_safe() };
+        profile_stats.cast::<Label>().unwrap()
+    }
+
+    fn get_profile_back(&self) -> TRef<Button> {
+        let profile_back = self.profile_back.unwrap();
+        let profile_back = unsafe { profile_back.assume_safe() };
+        profile_back.cast::<Button>().unwrap()
+    }
+
+    fn get_leaderboard(&self) -> TRef<Panel> {
+        let leaderboard = self.leaderboard.unwrap();
+        let leaderboard = unsafe { leaderboard.assume_safe() };
+        leaderboard.cast::<Panel>().unwrap()
+    }
+
+    fn get_leaderboard_list(&self) -> TRef<ItemList> {
+        let leaderboard_list = self.leaderboard_list.unwrap();
+        let leaderboard_list = unsafe { leaderboard_list.assume_safe() };
+        leaderboard_list.cast::<ItemList>().unwrap()
+    }
+
+    fn get_leaderboard_export(&self) -> TRef<Button> {
+        let leaderboard_export = self.leaderboard_export.unwrap();
+        let leaderboard_export = unsafe { leaderboard_export.assume_safe() };
+        leaderboard_export.cast::<Button>().unwrap()
+    }
+
+    fn get_leaderboard_back(&self) -> TRef<Button> {
+        let leaderboard_back = self.leaderboard_back.unwrap();
+        let leaderboard_back = unsafe { leaderboard_back.assume_safe() };
+        leaderboard_back.cast::<Button>().unwrap()
+    }
+}
+
+/// Who made the map and how many players it's for, compared to the lobby.
+fn map_hint(layout: &MapLayout, players: i64) -> String {
+    let spawns = layout.spawns.len() as i64;
+    let mut hint = if layout.author.is_empty() {
+        layout.name.clone()
+    } else {
+        format!("{} by {}", layout.name, layout.author)
+    };
+    hint += &format!("\nFor {} players, {} spawn points", layout.players, spawns);
+
+    if players > spawns {
+        hint += "\nToo many players, some share a spawn point";
+    } else if players < layout.players {
+        hint += &format!("\n{} more players recommended", layout.players - players);
+    }
+
+    hint
 }
@@ -0,0 +1,489 @@
+use crate::grid::{self, Cell, FLOOR_TILE, WALL_TILE};
+use crate::hazard::{self, Hazard};
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Maps shipped with the game.
+pub const RES_MAP_DIR: &str = "res://maps";
+/// Maps made by players, saved by the map editor.
+pub const USER_MAP_DIR: &str = "user://maps";
+/// Maps received from servers, named by their hash.
+const DOWNLOADED_MAP_DIR: &str = "user://maps/downloaded";
+
+/// Map file extension.
+pub const MAP_EXTENSION: &str = "json";
+
+/// Map of a match when none is chosen.
+pub const DEFAULT_MAP: &str = "res://maps/classic.json";
+
+/// Map rotation entry of a generated map.
+pub const PROCEDURAL_MAP: &str = "procedural";
+
+/// Bumped every time the map file layout changes.
+/// Version 2: `hazards` are conveyors, teleporters and ice.
+pub const MAP_FORMAT_VERSION: i64 = 2;
+
+/// Walls around the map, so the rest of the screen isn't empty.
+const BORDER_PADDING: i32 = 10;
+
+/// Characters of the `tiles` rows, spawn points are their index in base 36.
+const WALL_CHAR: char = '#';
+const FLOOR_CHAR: char = '.';
+const ROCK_CHAR: char = '+';
+const SPAWN_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tile {
+    Wall,
+    Floor,
+}
+
+/// A map: a grid of tiles, rocks, spawn points and hazards, placed with its top left tile at cell `(0, 0)`.
+///
+/// The file is a JSON document:
+/// ```json
+/// {
+///   "format": "multiplayer_bomber_map", "version": 2,
+///   "name": "Classic", "author": "...", "players": 12,
+///   "tiles": ["#####", "#0+.#", "#.#.#", "#.+1#", "#####"],
+///   "hazards": [{"type": "ice", "x": 1, "y": 2}]
+/// }
+/// ```
+/// `#` is a wall, `.` a floor, `+` a rock and `0`-`9`, `A`-`Z` are spawn points by index.
+/// Hazards are special floor tiles, see `Hazard`.
+#[derive(Clone, Debug)]
+pub struct MapLayout {
+    pub name: String,
+    pub author: String,
+    /// Recommended number of players
+    pub players: i64,
+
+    pub width: i32,
+    pub height: i32,
+    /// Row by row
+    tiles: Vec<Tile>,
+    /// Always on floor tiles
+    pub rocks: Vec<Cell>,
+    /// Sorted by index, always on floor tiles
+    pub spawns: Vec<Cell>,
+    /// Only painted on floor tiles
+    pub hazards: Vec<Hazard>,
+}
+
+impl MapLayout {
+    /// Only walls.
+    pub fn new(width: i32, height: i32) -> Self {
+        MapLayout {
+            name: String::new(),
+            author: String::new(),
+            players: 0,
+
+            width,
+            height,
+            tiles: vec![Tile::Wall; (width * height) as usize],
+            rocks: Vec::new(),
+            spawns: Vec::new(),
+            hazards: Vec::new(),
+        }
+    }
+
+    /// Everything outside the grid is a wall.
+    pub fn tile(&self, cell: Cell) -> Tile {
+        match self.index(cell) {
+            Some(index) => self.tiles[index],
+            None => Tile::Wall,
+        }
+    }
+
+    pub fn set_tile(&mut self, cell: Cell, tile: Tile) {
+        if let Some(index) = self.index(cell) {
+            self.tiles[index] = tile;
+        }
+    }
+
+    /// The other teleporter of the pair on `cell`, if any.
+    pub fn teleporter_exit(&self, cell: Cell) -> Option<Cell> {
+        self.hazards.iter().find_map(|hazard| match hazard {
+            Hazard::Teleporter { cells } if cells[0] == cell => Some(cells[1]),
+            Hazard::Teleporter { cells } if cells[1] == cell => Some(cells[0]),
+            _ => None,
+        })
+    }
+
+    fn index(&self, cell: Cell) -> Option<usize> {
+        if cell.0 < 0 || cell.1 < 0 || cell.0 >= self.width || cell.1 >= self.height {
+            return None;
+        }
+
+        Some((cell.1 * self.width + cell.0) as usize)
+    }
+
+    /// A pixel for every tile, for previews in the lobby.
+    pub fn thumbnail(&self) -> Ref<ImageTexture, Shared> {
+        let image = Image::new();
+        image.create(
+            self.width as i64,
+            self.height as i64,
+            false,
+            Image::FORMAT_RGB8,
+        );
+
+        image.lock();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = (x, y);
+                let color = if self.tile(cell) == Tile::Wall {
+                    Color::rgb(0.25, 0.25, 0.3)
+                } else if self.spawns.contains(&cell) {
+                    Color::rgb(1.0, 0.9, 0.3)
+                } else if self.rocks.contains(&cell) {
+                    Color::rgb(0.6, 0.45, 0.3)
+                } else if self
+                    .hazards
+                    .iter()
+                    .any(|hazard| hazard.cells().contains(&cell))
+                {
+                    Color::rgb(0.55, 0.75, 0.95)
+                } else {
+                    Color::rgb(0.45, 0.65, 0.35)
+                };
+                image.set_pixel(x as i64, y as i64, color);
+            }
+        }
+        image.unlock();
+
+        // No filter, tiles stay sharp when stretched
+        let texture = ImageTexture::new();
+        texture.create_from_image(image, 0);
+        texture.into_shared()
+    }
+
+    /// Replacing the tiles, rocks and spawn points of `world` (an instance of `World.tscn`).
+    pub fn apply(&self, world: TRef<Node2D>) {
+        let tilemap = grid::get_tilemap(world);
+        tilemap.clear();
+
+        for y in -BORDER_PADDING..self.height + BORDER_PADDING {
+            for x in -BORDER_PADDING..self.width + BORDER_PADDING {
+                let tile = match self.tile((x, y)) {
+                    Tile::Wall => WALL_TILE,
+                    Tile::Floor => FLOOR_TILE,
+                };
+                tilemap.set_cell(
+                    x as i64,
+                    y as i64,
+                    tile,
+                    false,
+                    false,
+                    false,
+                    Vector2::zero(),
+                );
+            }
+        }
+
+        // Hazards
+        for hazard in self.hazards.iter() {
+            for cell in hazard.cells() {
+                if self.tile(cell) == Tile::Floor {
+                    tilemap.set_cell(
+                        cell.0 as i64,
+                        cell.1 as i64,
+                        hazard.tile(),
+                        false,
+                        false,
+                        false,
+                        Vector2::zero(),
+                    );
+                }
+            }
+        }
+        hazard::set_teleporters(tilemap, &self.hazards);
+
+        // Rocks
+        let rocks = unsafe { world.get_node("Rocks").unwrap().assume_safe() };
+        clear_children(rocks);
+
+        let rock_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/Rock/Rock.tscn", "PackedScene", false)
+            .unwrap();
+        let rock_scene = unsafe { rock_scene.assume_safe() };
+        let rock_scene = rock_scene.cast::<PackedScene>().unwrap();
+
+        for (index, cell) in self.rocks.iter().enumerate() {
+            let rock = rock_scene
+                .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+                .unwrap();
+            let rock = unsafe { rock.assume_safe() };
+            let rock = rock.cast::<Node2D>().unwrap();
+
+            rock.set_name(format!("Rock{}", index));
+            rock.set_position(grid::cell_center(tilemap, *cell));
+            rocks.add_child(rock, false);
+        }
+
+        // Spawn points, named by index like the hand-made ones
+        let spawn_points = unsafe { world.get_node("SpawnPoints").unwrap().assume_safe() };
+        clear_children(spawn_points);
+
+        for (index, cell) in self.spawns.iter().enumerate() {
+            let spawn_point = Position2D::new();
+            spawn_point.set_name(index.to_string());
+            spawn_point.set_position(grid::cell_center(tilemap, *cell));
+            spawn_points.add_child(spawn_point, false);
+        }
+    }
+
+    pub fn to_dictionary(&self) -> Dictionary {
+        let mut rows: Vec<Vec<char>> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| match self.tile((x, y)) {
+                        Tile::Wall => WALL_CHAR,
+                        Tile::Floor => FLOOR_CHAR,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (x, y) in self.rocks.iter() {
+            rows[*y as usize][*x as usize] = ROCK_CHAR;
+        }
+        for (index, (x, y)) in self.spawns.iter().enumerate() {
+            rows[*y as usize][*x as usize] = SPAWN_CHARS.chars().nth(index).unwrap_or(FLOOR_CHAR);
+        }
+
+        let tiles = VariantArray::new();
+        for row in rows {
+            tiles.push(row.into_iter().collect::<String>());
+        }
+
+        let hazards = VariantArray::new();
+        for hazard in self.hazards.iter() {
+            hazards.push(hazard.to_dictionary());
+        }
+
+        let dict = Dictionary::new();
+        dict.insert("format", "multiplayer_bomber_map");
+        dict.insert("version", MAP_FORMAT_VERSION);
+        dict.insert("name", self.name.as_str());
+        dict.insert("author", self.author.as_str());
+        dict.insert("players", self.players);
+        dict.insert("tiles", tiles.into_shared());
+        dict.insert("hazards", hazards.into_shared());
+
+        dict.into_shared()
+    }
+
+    pub fn from_dictionary(dict: &Dictionary) -> Result<Self, String> {
+        if dict.get("format").to_string() != "multiplayer_bomber_map" {
+            return Err("not a map file".to_string());
+        }
+
+        let version = dict.get("version").to_i64();
+        if version > MAP_FORMAT_VERSION {
+            return Err(format!(
+                "map version {} is newer than supported {}",
+                version, MAP_FORMAT_VERSION
+            ));
+        }
+
+        let rows: Vec<String> = dict
+            .get("tiles")
+            .to_array()
+            .iter()
+            .map(|row| row.to_string())
+            .collect();
+        let width = rows.first().map(|row| row.chars().count()).unwrap_or(0);
+        if width == 0 {
+            return Err("no tiles".to_string());
+        }
+
+        let mut layout = MapLayout::new(width as i32, rows.len() as i32);
+        layout.name = dict.get("name").to_string();
+        layout.author = dict.get("author").to_string();
+        layout.players = dict.get("players").to_i64();
+        // Version 1 files only have an empty list
+        for (index, hazard) in dict.get("hazards").to_array().iter().enumerate() {
+            let hazard = Hazard::from_dictionary(&hazard.to_dictionary())
+                .map_err(|e| format!("hazard {}: {}", index, e))?;
+            layout.hazards.push(hazard);
+        }
+
+        let mut spawns = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(format!("row {} isn't {} tiles wide", y, width));
+            }
+
+            for (x, c) in row.chars().enumerate() {
+                let cell = (x as i32, y as i32);
+                if c == WALL_CHAR {
+                    continue;
+                }
+                layout.set_tile(cell, Tile::Floor);
+
+                if c == ROCK_CHAR {
+                    layout.rocks.push(cell);
+                } else if let Some(index) = SPAWN_CHARS.find(c) {
+                    spawns.push((index, cell));
+                } else if c != FLOOR_CHAR {
+                    return Err(format!("unknown tile `{}` at {}, {}", c, x, y));
+                }
+            }
+        }
+
+        spawns.sort_by_key(|(index, _)| *index);
+        for pair in spawns.windows(2) {
+            if pair[0].0 == pair[1].0 {
+                return Err(format!("spawn point {} is set twice", pair[0].0));
+            }
+        }
+        layout.spawns = spawns.into_iter().map(|(_, cell)| cell).collect();
+
+        Ok(layout)
+    }
+
+    pub fn to_json(&self) -> String {
+        JSON::godot_singleton()
+            .print(self.to_dictionary(), "  ", false)
+            .to_string()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parsed = JSON::godot_singleton()
+            .parse(text)
+            .ok_or_else(|| "JSON parser failure".to_string())?;
+        let parsed = unsafe { parsed.assume_safe() };
+
+        let result = parsed.result();
+        if result.get_type() != VariantType::Dictionary {
+            return Err(format!(
+                "invalid JSON at line {}: {}",
+                parsed.error_line(),
+                parsed.error_string()
+            ));
+        }
+
+        Self::from_dictionary(&result.to_dictionary())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        Self::parse(&read_map_file(path)?).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), GodotError> {
+        let dir = Directory::new();
+        if !dir.dir_exists(USER_MAP_DIR) {
+            dir.make_dir_recursive(USER_MAP_DIR)?;
+        }
+
+        let file = File::new();
+        file.open(path, File::WRITE)?;
+        file.store_line(self.to_json());
+        file.close();
+
+        Ok(())
+    }
+}
+
+/// Removed right away, the new children take their names.
+fn clear_children(parent: TRef<Node>) {
+    for child in parent.get_children().iter() {
+        let child = child.try_to_object::<Node>().unwrap();
+        parent.remove_child(child);
+        unsafe { child.assume_safe() }.queue_free();
+    }
+}
+
+pub fn read_map_file(path: &str) -> Result<String, String> {
+    let file = File::new();
+    file.open(path, File::READ)
+        .map_err(|e| format!("can't open {}: {}", path, e))?;
+
+    let text = file.get_as_text().to_string();
+    file.close();
+
+    Ok(text)
+}
+
+/// FNV-1a of the file contents, the same on every platform.
+/// Peers compare maps by it, so a renamed file is still the same map.
+pub fn hash_map_text(text: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Paths of all map files: shipped, made by players and downloaded.
+pub fn list_maps() -> Vec<String> {
+    let mut maps = Vec::new();
+    for dir_path in [RES_MAP_DIR, USER_MAP_DIR, DOWNLOADED_MAP_DIR].iter() {
+        maps.extend(list_map_dir(dir_path));
+    }
+
+    maps
+}
+
+fn list_map_dir(dir_path: &str) -> Vec<String> {
+    let mut maps = Vec::new();
+
+    let dir = Directory::new();
+    if dir.open(dir_path).is_err() {
+        return maps;
+    }
+
+    if let Err(e) = dir.list_dir_begin(true, true) {
+        godot_error!("`Map` => GodotError at `list_dir_begin` function: {}", e);
+        return maps;
+    }
+
+    loop {
+        let file_name = dir.get_next().to_string();
+        if file_name.is_empty() {
+            break;
+        }
+
+        if !dir.current_is_dir() && file_name.ends_with(&format!(".{}", MAP_EXTENSION)) {
+            maps.push(format!("{}/{}", dir_path, file_name));
+        }
+    }
+    dir.list_dir_end();
+
+    maps.sort();
+    maps
+}
+
+/// A local map file with the given hash, if any.
+pub fn find_map(hash: &str) -> Option<String> {
+    list_maps().into_iter().find(|path| {
+        read_map_file(path)
+            .map(|text| hash_map_text(&text) == hash)
+            .unwrap_or(false)
+    })
+}
+
+/// Saving a map received from the server, it isn't downloaded again.
+pub fn save_downloaded_map(text: &str) -> Result<String, GodotError> {
+    let dir = Directory::new();
+    if !dir.dir_exists(DOWNLOADED_MAP_DIR) {
+        dir.make_dir_recursive(DOWNLOADED_MAP_DIR)?;
+    }
+
+    let path = format!(
+        "{}/{}.{}",
+        DOWNLOADED_MAP_DIR,
+        hash_map_text(text),
+        MAP_EXTENSION
+    );
+
+    let file = File::new();
+    file.open(path.as_str(), File::WRITE)?;
+    file.store_string(text);
+    file.close();
+
+    Ok(path)
+}
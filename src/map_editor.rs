@@ -0,0 +1,566 @@
+use crate::grid::{self, Cell};
+use crate::map::{self, MapLayout, Tile};
+use crate::map_validator;
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// New maps fill the screen, like `res://maps/classic.json`.
+const NEW_MAP_WIDTH: i32 = 21;
+const NEW_MAP_HEIGHT: i32 = 13;
+
+/// Spawn points are written as `0`-`9`, `A`-`Z` in map files.
+const MAX_SPAWNS: usize = 36;
+
+/// Zoomed out, so the map fits next to the editor panel.
+const CAMERA_ZOOM: f32 = 1.5;
+
+/// Labels of the world scene, unused while editing.
+const WORLD_LABELS: [&str; 6] = ["Score", "Round", "Timer", "TeamScore", "Hud", "Spectator"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tool {
+    Floor,
+    Wall,
+    Rock,
+    Spawn,
+}
+
+impl Tool {
+    const ALL: [Tool; 4] = [Tool::Floor, Tool::Wall, Tool::Rock, Tool::Spawn];
+
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Floor => "Floor",
+            Tool::Wall => "Wall",
+            Tool::Rock => "Rock",
+            Tool::Spawn => "Spawn point",
+        }
+    }
+}
+
+/// Editing a map file in the world scene, opened from the lobby.
+///
+/// Left click paints with the selected tool, right click removes rocks and spawn points.
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct MapEditor {
+    layout: MapLayout,
+    tool: Tool,
+    /// Left or right mouse button held down
+    painting: Option<i64>,
+    /// Painted last, dragging over it again changes nothing
+    last_cell: Option<Cell>,
+
+    /// OptionButton
+    tool_option: Option<Ref<Node>>,
+    /// LineEdit
+    map_name: Option<Ref<Node>>,
+    /// LineEdit
+    author: Option<Ref<Node>>,
+    /// SpinBox
+    players: Option<Ref<Node>>,
+    /// LineEdit
+    file_name: Option<Ref<Node>>,
+    /// OptionButton
+    maps: Option<Ref<Node>>,
+    /// Button
+    open: Option<Ref<Node>>,
+    /// Button
+    new_map: Option<Ref<Node>>,
+    /// Button
+    check: Option<Ref<Node>>,
+    /// Button
+    save: Option<Ref<Node>>,
+    /// Button
+    test_play: Option<Ref<Node>>,
+    /// Button
+    exit: Option<Ref<Node>>,
+    /// Label
+    status: Option<Ref<Node>>,
+}
+
+#[methods]
+#[allow(deprecated)]
+impl MapEditor {
+    fn new(_owner: TRef<Node>) -> Self {
+        MapEditor {
+            layout: Self::blank_map(),
+            tool: Tool::Wall,
+            painting: None,
+            last_cell: None,
+
+            tool_option: None,
+            map_name: None,
+            author: None,
+            players: None,
+            file_name: None,
+            maps: None,
+            open: None,
+            new_map: None,
+            check: None,
+            save: None,
+            test_play: None,
+            exit: None,
+            status: None,
+        }
+    }
+
+    #[export]
+    fn _ready(&mut self, owner: TRef<Node>) {
+        self.update_child_nodes(owner);
+
+        let tool_option = self.get_tool_option();
+        for tool in Tool::ALL.iter() {
+            tool_option.add_item(tool.name(), -1);
+        }
+        tool_option.select(Tool::ALL.iter().position(|t| *t == self.tool).unwrap() as i64);
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        for label in WORLD_LABELS.iter() {
+            if let Some(label) = world.get_node(*label) {
+                unsafe { label.assume_safe() }
+                    .cast::<CanvasItem>()
+                    .unwrap()
+                    .hide();
+            }
+        }
+
+        // Camera
+        let camera = world.get_node("Camera2D").unwrap();
+        let camera = unsafe { camera.assume_safe() };
+        let camera = camera.cast::<Camera2D>().unwrap();
+        camera.set_zoom(Vector2::new(CAMERA_ZOOM, CAMERA_ZOOM));
+        //
+
+        if let Err(e) = self.connect_signals(owner) {
+            godot_error!(
+                "`MapEditor` => GodotError at `connect_signals` function: {}",
+                e
+            );
+        }
+
+        self.refresh_maps();
+        self.open_map(owner, map::DEFAULT_MAP);
+
+        owner.set_process_unhandled_input(true);
+    }
+
+    #[export]
+    fn _unhandled_input(&mut self, owner: TRef<Node>, event: Ref<InputEvent>) {
+        let event = unsafe { event.assume_safe() };
+
+        if let Some(event) = event.cast::<InputEventMouseButton>() {
+            let button = event.button_index();
+            if button != GlobalConstants::BUTTON_LEFT && button != GlobalConstants::BUTTON_RIGHT {
+                return;
+            }
+
+            if event.is_pressed() {
+                self.painting = Some(button);
+                self.last_cell = None;
+                self.paint_under_mouse(owner);
+            } else if self.painting == Some(button) {
+                self.painting = None;
+            }
+        } else if event.cast::<InputEventMouseMotion>().is_some() && self.painting.is_some() {
+            self.paint_under_mouse(owner);
+        }
+    }
+
+    fn paint_under_mouse(&mut self, owner: TRef<Node>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+        let cell = grid::to_cell(tilemap, world.get_global_mouse_position());
+
+        let outside =
+            cell.0 < 0 || cell.1 < 0 || cell.0 >= self.layout.width || cell.1 >= self.layout.height;
+        if outside || self.last_cell == Some(cell) {
+            return;
+        }
+        self.last_cell = Some(cell);
+
+        let changed = if self.painting == Some(GlobalConstants::BUTTON_RIGHT) {
+            self.erase(cell)
+        } else {
+            self.paint(cell)
+        };
+
+        if changed {
+            self.refresh_world(owner);
+        }
+    }
+
+    /// Painting `cell` with the selected tool, `true` if anything changed.
+    fn paint(&mut self, cell: Cell) -> bool {
+        let layout = &mut self.layout;
+        let was_floor = layout.tile(cell) == Tile::Floor;
+
+        match self.tool {
+            Tool::Floor => {
+                layout.set_tile(cell, Tile::Floor);
+                !was_floor
+            }
+            Tool::Wall => {
+                let erased = self.erase(cell);
+                self.layout.set_tile(cell, Tile::Wall);
+                was_floor || erased
+            }
+            Tool::Rock => {
+                if layout.rocks.contains(&cell) {
+                    return false;
+                }
+
+                layout.spawns.retain(|spawn| *spawn != cell);
+                layout.set_tile(cell, Tile::Floor);
+                layout.rocks.push(cell);
+                true
+            }
+            Tool::Spawn => {
+                if layout.spawns.contains(&cell) || layout.spawns.len() >= MAX_SPAWNS {
+                    return false;
+                }
+
+                layout.rocks.retain(|rock| *rock != cell);
+                layout.set_tile(cell, Tile::Floor);
+                layout.spawns.push(cell);
+                true
+            }
+        }
+    }
+
+    /// Removing the rock or spawn point of `cell`, `true` if there was one.
+    fn erase(&mut self, cell: Cell) -> bool {
+        let layout = &mut self.layout;
+        let count = layout.rocks.len() + layout.spawns.len();
+
+        layout.rocks.retain(|rock| *rock != cell);
+        layout.spawns.retain(|spawn| *spawn != cell);
+
+        layout.rocks.len() + layout.spawns.len() != count
+    }
+
+    /// Rebuilding the world from the map, spawn points are shown by their index.
+    fn refresh_world(&self, owner: TRef<Node>) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        self.layout.apply(world);
+
+        // `SpawnPoints`
+        let spawn_points = world.get_node("SpawnPoints").unwrap();
+        let spawn_points = unsafe { spawn_points.assume_safe() };
+        //
+
+        for spawn_point in spawn_points.get_children().iter() {
+            let spawn_point = spawn_point.try_to_object::<Node>().unwrap();
+            let spawn_point = unsafe { spawn_point.assume_safe() };
+
+            let marker = Label::new();
+            marker.set_text(spawn_point.name());
+            marker.set_position(Vector2::new(-8.0, -8.0), false);
+            spawn_point.add_child(marker, false);
+        }
+    }
+
+    fn open_map(&mut self, owner: TRef<Node>, path: &str) {
+        match MapLayout::load(path) {
+            Ok(layout) => {
+                self.layout = layout;
+
+                let file_name = path.rsplit('/').next().unwrap_or(path);
+                let file_name = file_name.trim_end_matches(&format!(".{}", map::MAP_EXTENSION));
+                self.get_file_name().set_text(file_name);
+                self.set_status(&format!("Opened {}", path));
+            }
+            Err(e) => {
+                self.layout = Self::blank_map();
+                self.get_file_name().set_text("");
+                self.set_status(&format!("Can't open the map: {}", e));
+            }
+        }
+
+        self.get_map_name().set_text(self.layout.name.as_str());
+        self.get_author().set_text(self.layout.author.as_str());
+        self.get_players()
+            .set_value(self.layout.players.max(2) as f64);
+
+        self.refresh_world(owner);
+    }
+
+    /// Floor inside a wall border.
+    fn blank_map() -> MapLayout {
+        let mut layout = MapLayout::new(NEW_MAP_WIDTH, NEW_MAP_HEIGHT);
+        for y in 1..NEW_MAP_HEIGHT - 1 {
+            for x in 1..NEW_MAP_WIDTH - 1 {
+                layout.set_tile((x, y), Tile::Floor);
+            }
+        }
+        layout.players = 2;
+
+        layout
+    }
+
+    /// Taking the metadata from the panel.
+    fn update_metadata(&mut self) {
+        self.layout.name = self.get_map_name().text().to_string();
+        self.layout.author = self.get_author().text().to_string();
+        self.layout.players = self.get_players().value() as i64;
+    }
+
+    /// Only letters, digits, `-` and `_`, so it can't leave `user://maps/`.
+    fn file_path(&self) -> Option<String> {
+        let file_name: String = self
+            .get_file_name()
+            .text()
+            .to_string()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+
+        if file_name.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "{}/{}.{}",
+            map::USER_MAP_DIR,
+            file_name,
+            map::MAP_EXTENSION
+        ))
+    }
+
+    /// Saving into `user://maps/`, the path of the file on success.
+    fn save_map(&mut self) -> Option<String> {
+        self.update_metadata();
+
+        let path = match self.file_path() {
+            Some(path) => path,
+            None => {
+                self.set_status("Enter a file name first.");
+                return None;
+            }
+        };
+
+        if let Err(e) = self.layout.save(&path) {
+            godot_error!("`MapEditor` => GodotError at `save` function: {}", e);
+            self.set_status(&format!("Can't save {}: {}", path, e));
+            return None;
+        }
+
+        self.refresh_maps();
+        self.set_status(&format!("Saved {}", path));
+        Some(path)
+    }
+
+    fn refresh_maps(&self) {
+        let maps = self.get_maps();
+        maps.clear();
+
+        for path in map::list_maps().iter() {
+            maps.add_item(path.as_str(), -1);
+            maps.set_item_metadata(maps.get_item_count() - 1, path.as_str());
+        }
+    }
+
+    fn set_status(&self, status: &str) {
+        self.get_status().set_text(status);
+    }
+
+    #[export]
+    fn _on_tool_selected(&mut self, _owner: TRef<Node>, index: i64) {
+        self.tool = Tool::ALL[index as usize];
+    }
+
+    #[export]
+    fn _on_open_pressed(&mut self, owner: TRef<Node>) {
+        let maps = self.get_maps();
+        if maps.selected() < 0 {
+            return;
+        }
+
+        let path = maps.get_item_metadata(maps.selected()).to_string();
+        self.open_map(owner, &path);
+    }
+
+    #[export]
+    fn _on_new_pressed(&mut self, owner: TRef<Node>) {
+        self.layout = Self::blank_map();
+        self.get_map_name().set_text("");
+        self.get_author().set_text("");
+        self.get_players().set_value(self.layout.players as f64);
+        self.get_file_name().set_text("");
+        self.set_status("New map");
+
+        self.refresh_world(owner);
+    }
+
+    #[export]
+    fn _on_check_pressed(&mut self, _owner: TRef<Node>) {
+        self.update_metadata();
+
+        let problems = map_validator::validate(&self.layout, false);
+        if problems.is_empty() {
+            self.set_status("The map is playable.");
+        } else {
+            self.set_status(&map_validator::describe(&problems));
+        }
+    }
+
+    #[export]
+    fn _on_save_pressed(&mut self, _owner: TRef<Node>) {
+        self.save_map();
+    }
+
+    /// Saving the map and hosting a match on it against bots.
+    #[export]
+    fn _on_test_play_pressed(&mut self, owner: TRef<Node>) {
+        self.update_metadata();
+
+        let problems = map_validator::validate(&self.layout, false);
+        if !problems.is_empty() {
+            self.set_status(&map_validator::describe(&problems));
+            return;
+        }
+
+        let path = match self.save_map() {
+            Some(path) => path,
+            None => return,
+        };
+
+        // One bot for every other player, the world is replaced
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        unsafe {
+            gamestate.call_deferred(
+                "test_map",
+                &[path.to_variant(), (self.layout.players - 1).to_variant()],
+            );
+        }
+    }
+
+    #[export]
+    fn _on_exit_pressed(&self, owner: TRef<Node>) {
+        unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("end_game", VariantArray::new_shared());
+        }
+    }
+
+    fn update_child_nodes(&mut self, owner: TRef<Node>) {
+        self.tool_option = owner.get_node("HUD/Panel/Tool");
+        self.map_name = owner.get_node("HUD/Panel/Name");
+        self.author = owner.get_node("HUD/Panel/Author");
+        self.players = owner.get_node("HUD/Panel/Players");
+        self.file_name = owner.get_node("HUD/Panel/FileName");
+        self.maps = owner.get_node("HUD/Panel/Maps");
+        self.open = owner.get_node("HUD/Panel/Open");
+        self.new_map = owner.get_node("HUD/Panel/New");
+        self.check = owner.get_node("HUD/Panel/Check");
+        self.save = owner.get_node("HUD/Panel/Save");
+        self.test_play = owner.get_node("HUD/Panel/TestPlay");
+        self.exit = owner.get_node("HUD/Panel/Exit");
+        self.status = owner.get_node("HUD/Panel/Status");
+    }
+
+    fn connect_signals(&self, owner: TRef<Node>) -> Result<(), GodotError> {
+        self.get_tool_option().connect(
+            "item_selected",
+            owner,
+            "_on_tool_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        let buttons = [
+            (self.get_open(), "_on_open_pressed"),
+            (self.get_new_map(), "_on_new_pressed"),
+            (self.get_check(), "_on_check_pressed"),
+            (self.get_save(), "_on_save_pressed"),
+            (self.get_test_play(), "_on_test_play_pressed"),
+            (self.get_exit(), "_on_exit_pressed"),
+        ];
+        for (button, method) in buttons.iter() {
+            button.connect("pressed", owner, *method, VariantArray::new_shared(), 0)?;
+        }
+
+        Ok(())
+    }
+
+    // get child nodes
+
+    fn get_tool_option(&self) -> TRef<OptionButton> {
+        let tool_option = self.tool_option.unwrap();
+        let tool_option = unsafe { tool_option.assume_safe() };
+        tool_option.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_map_name(&self) -> TRef<LineEdit> {
+        let map_name = self.map_name.unwrap();
+        let map_name = unsafe { map_name.assume_safe() };
+        map_name.cast::<LineEdit>().unwrap()
+    }
+
+    fn get_author(&self) -> TRef<LineEdit> {
+        let author = self.author.unwrap();
+        let author = unsafe { author.assume_safe() };
+        author.cast::<LineEdit>().unwrap()
+    }
+
+    fn get_players(&self) -> TRef<SpinBox> {
+        let players = self.players.unwrap();
+        let players = unsafe { players.assume_safe() };
+        players.cast::<SpinBox>().unwrap()
+    }
+
+    fn get_file_name(&self) -> TRef<LineEdit> {
+        let file_name = self.file_name.unwrap();
+        let file_name = unsafe { file_name.assume_safe() };
+        file_name.cast::<LineEdit>().unwrap()
+    }
+
+    fn get_maps(&self) -> TRef<OptionButton> {
+        let maps = self.maps.unwrap();
+        let maps = unsafe { maps.assume_safe() };
+        maps.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_open(&self) -> TRef<Button> {
+        let open = self.open.unwrap();
+        let open = unsafe { open.assume_safe() };
+        open.cast::<Button>().unwrap()
+    }
+
+    fn get_new_map(&self) -> TRef<Button> {
+        let new_map = self.new_map.unwrap();
+        let new_map = unsafe { new_map.assume_safe() };
+        new_map.cast::<Button>().unwrap()
+    }
+
+    fn get_check(&self) -> TRef<Button> {
+        let check = self.check.unwrap();
+        let check = unsafe { check.assume_safe() };
+        check.cast::<Button>().unwrap()
+    }
+
+    fn get_save(&self) -> TRef<Button> {
+        let save = self.save.unwrap();
+        let save = unsafe { save.assume_safe() };
+        save.cast::<Button>().unwrap()
+    }
+
+    fn get_test_play(&self) -> TRef<Button> {
+        let test_play = self.test_play.unwrap();
+        let test_play = unsafe { test_play.assume_safe() };
+        test_play.cast::<Button>().unwrap()
+    }
+
+    fn get_exit(&self) -> TRef<Button> {
+        let exit = self.exit.unwrap();
+        let exit = unsafe { exit.assume_safe() };
+        exit.cast::<Button>().unwrap()
+    }
+
+    fn get_status(&self) -> TRef<Label> {
+        let status = self.status.unwrap();
+        let status = unsafe { status.assume_safe() };
+        status.cast::<Label>().unwrap()
+    }
+}
@@ -0,0 +1,294 @@
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+use crate::gamestate::MAX_PEERS;
+use crate::grid::{self, Cell, CELL_SIZE};
+use crate::map::{MapLayout, Tile};
+use crate::player::{BASE_BLAST_RANGE, MOTION_SPEED};
+use crate::settings::DEFAULT_FUSE_TIME;
+
+/// Exit code of `--validate-map` for a map with errors.
+const EXIT_INVALID: i64 = 1;
+
+/// Something wrong with a map, it can't be played.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// Name of the failed check, stays the same between versions
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(check: &'static str, message: String) -> Self {
+        Problem { check, message }
+    }
+
+    fn to_dictionary(&self) -> Dictionary {
+        let dict = Dictionary::new();
+        dict.insert("check", self.check);
+        dict.insert("message", self.message.as_str());
+        dict.into_shared()
+    }
+}
+
+/// Every error of the map, empty for a playable map.
+/// `allow_shared_spawns`: players of a full server may take turns on fewer spawn points.
+pub fn validate(layout: &MapLayout, allow_shared_spawns: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    check_spawn_count(layout, allow_shared_spawns, &mut problems);
+    check_rocks(layout, &mut problems);
+    check_hazards(layout, &mut problems);
+    check_reachable(layout, &mut problems);
+    check_escape(layout, &mut problems);
+
+    problems
+}
+
+/// A spawn point for every player of a full server,
+/// shared spawns only need one for every recommended player.
+fn check_spawn_count(layout: &MapLayout, allow_shared_spawns: bool, problems: &mut Vec<Problem>) {
+    let spawns = layout.spawns.len() as i64;
+    if !allow_shared_spawns && spawns < MAX_PEERS {
+        problems.push(Problem::new(
+            "spawn_count",
+            format!("{} spawn points for up to {} players", spawns, MAX_PEERS),
+        ));
+    } else if spawns < layout.players {
+        problems.push(Problem::new(
+            "spawn_count",
+            format!(
+                "{} spawn points for {} recommended players",
+                spawns, layout.players
+            ),
+        ));
+    }
+}
+
+fn check_rocks(layout: &MapLayout, problems: &mut Vec<Problem>) {
+    for rock in layout.rocks.iter() {
+        if layout.tile(*rock) != Tile::Floor {
+            problems.push(Problem::new(
+                "rock_on_wall",
+                format!("rock at {},{} is on a wall", rock.0, rock.1),
+            ));
+        }
+        if let Some(index) = layout.spawns.iter().position(|spawn| spawn == rock) {
+            problems.push(Problem::new(
+                "rock_on_spawn",
+                format!("rock at {},{} is on spawn point {}", rock.0, rock.1, index),
+            ));
+        }
+    }
+}
+
+fn check_hazards(layout: &MapLayout, problems: &mut Vec<Problem>) {
+    let mut taken: Vec<Cell> = Vec::new();
+    for (index, hazard) in layout.hazards.iter().enumerate() {
+        for cell in hazard.cells() {
+            if layout.tile(cell) != Tile::Floor {
+                problems.push(Problem::new(
+                    "hazard_on_wall",
+                    format!("hazard {} at {},{} is on a wall", index, cell.0, cell.1),
+                ));
+            }
+            if taken.contains(&cell) {
+                problems.push(Problem::new(
+                    "hazard_overlap",
+                    format!(
+                        "hazard {} at {},{} is on another hazard",
+                        index, cell.0, cell.1
+                    ),
+                ));
+            }
+            taken.push(cell);
+        }
+    }
+}
+
+/// Rocks can be blown up, only walls stand in the way.
+fn check_reachable(layout: &MapLayout, problems: &mut Vec<Problem>) {
+    let first = match layout.spawns.first() {
+        Some(first) => *first,
+        None => return,
+    };
+
+    let reached = flood_fill(layout, first, usize::MAX, |_| true);
+    for (index, spawn) in layout.spawns.iter().enumerate() {
+        if !reached.iter().any(|(cell, _)| cell == spawn) {
+            problems.push(Problem::new(
+                "spawn_reachable",
+                format!("spawn point {} can't be reached from spawn point 0", index),
+            ));
+        }
+    }
+}
+
+/// A player placing a bomb right away must be able to walk out of its blast before the
+/// default fuse runs out, without power-ups and with the rocks still standing.
+fn check_escape(layout: &MapLayout, problems: &mut Vec<Problem>) {
+    let max_steps = (DEFAULT_FUSE_TIME as f32 * MOTION_SPEED / CELL_SIZE) as usize;
+    let is_rock = |cell: Cell| layout.rocks.contains(&cell);
+
+    for (index, spawn) in layout.spawns.iter().enumerate() {
+        let blast = grid::blast_cells(
+            *spawn,
+            BASE_BLAST_RANGE,
+            |cell| layout.tile(cell) != Tile::Floor,
+            is_rock,
+        );
+        let reached = flood_fill(layout, *spawn, max_steps, |cell| !is_rock(cell));
+
+        if reached.iter().all(|(cell, _)| blast.contains(cell)) {
+            problems.push(Problem::new(
+                "spawn_escape",
+                format!(
+                    "spawn point {} has no safe tile within {} steps from its own bomb",
+                    index, max_steps
+                ),
+            ));
+        }
+    }
+}
+
+/// Floor tiles reached from `start` within `max_steps`, with their distance.
+/// Stepping on a teleporter leads to the other one of the pair.
+fn flood_fill<P>(
+    layout: &MapLayout,
+    start: Cell,
+    max_steps: usize,
+    passable: P,
+) -> Vec<(Cell, usize)>
+where
+    P: Fn(Cell) -> bool,
+{
+    let mut reached = vec![(start, 0)];
+    let mut next = 0;
+    while next < reached.len() {
+        let (cell, steps) = reached[next];
+        next += 1;
+        if steps == max_steps {
+            continue;
+        }
+
+        for direction in grid::DIRECTIONS.iter() {
+            let neighbour = (cell.0 + direction.0, cell.1 + direction.1);
+            if layout.tile(neighbour) != Tile::Floor || !passable(neighbour) {
+                continue;
+            }
+
+            let exit = layout.teleporter_exit(neighbour);
+            for step in [Some(neighbour), exit].iter().flatten() {
+                if layout.tile(*step) == Tile::Floor
+                    && passable(*step)
+                    && !reached.iter().any(|(cell, _)| cell == step)
+                {
+                    reached.push((*step, steps + 1));
+                }
+            }
+        }
+    }
+    reached
+}
+
+/// Report of `--validate-map`, a JSON object with the errors of the map file.
+pub fn report(path: &str, allow_shared_spawns: bool) -> Dictionary {
+    let problems = match MapLayout::load(path) {
+        Ok(layout) => validate(&layout, allow_shared_spawns),
+        Err(e) => vec![Problem::new("format", e)],
+    };
+
+    let errors = VariantArray::new();
+    for problem in problems.iter() {
+        errors.push(problem.to_dictionary());
+    }
+
+    let dict = Dictionary::new();
+    dict.insert("map", path);
+    dict.insert("valid", problems.is_empty());
+    dict.insert("errors", errors.into_shared());
+    dict.into_shared()
+}
+
+/// Prints the report of the map file and quits, with a non-zero exit code on errors.
+pub fn run(tree: TRef<SceneTree>, path: &str, allow_shared_spawns: bool) {
+    let result = report(path, allow_shared_spawns);
+    let valid = result.get("valid").to_bool();
+
+    godot_print!("{}", JSON::godot_singleton().print(result, "", false));
+    tree.quit(if valid { 0 } else { EXIT_INVALID });
+}
+
+/// Map errors as a list, for the editor.
+pub fn describe(problems: &[Problem]) -> String {
+    let lines: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+    format!("Problems:\n- {}", lines.join("\n- "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hazard::Hazard;
+
+    /// Two rooms of a corridor split by a wall: `#0..#..1#`
+    fn split_rooms() -> MapLayout {
+        let mut layout = MapLayout::new(9, 3);
+        for x in [1, 2, 3, 5, 6, 7].iter() {
+            layout.set_tile((*x, 1), Tile::Floor);
+        }
+        layout.players = 2;
+        layout.spawns = vec![(1, 1), (7, 1)];
+        layout
+    }
+
+    fn checks(problems: &[Problem]) -> Vec<&'static str> {
+        problems.iter().map(|problem| problem.check).collect()
+    }
+
+    #[test]
+    fn walls_split_spawns() {
+        let problems = validate(&split_rooms(), true);
+        assert!(checks(&problems).contains(&"spawn_reachable"));
+    }
+
+    #[test]
+    fn teleporters_join_rooms() {
+        let mut layout = split_rooms();
+        layout.hazards.push(Hazard::Teleporter {
+            cells: [(3, 1), (5, 1)],
+        });
+
+        let reached = flood_fill(&layout, (1, 1), usize::MAX, |_| true);
+        assert!(reached.contains(&((3, 1), 2)));
+        assert!(reached.contains(&((5, 1), 2)));
+        assert!(reached.contains(&((7, 1), 4)));
+
+        assert!(checks(&validate(&layout, true)).is_empty());
+    }
+
+    #[test]
+    fn rocks_block_teleporters() {
+        let mut layout = split_rooms();
+        layout.hazards.push(Hazard::Teleporter {
+            cells: [(3, 1), (5, 1)],
+        });
+        layout.rocks.push((6, 1));
+
+        let reached = flood_fill(&layout, (1, 1), usize::MAX, |cell| {
+            !layout.rocks.contains(&cell)
+        });
+        assert!(reached.contains(&((5, 1), 2)));
+        assert!(!reached.iter().any(|(cell, _)| *cell == (7, 1)));
+    }
+
+    #[test]
+    fn full_server_needs_a_spawn_per_player() {
+        let layout = split_rooms();
+        assert!(checks(&validate(&layout, false)).contains(&"spawn_count"));
+        assert!(!checks(&validate(&layout, true)).contains(&"spawn_count"));
+
+        let mut layout = split_rooms();
+        layout.players = 4;
+        assert!(checks(&validate(&layout, true)).contains(&"spawn_count"));
+    }
+}
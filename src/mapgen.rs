@@ -0,0 +1,202 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::grid::Cell;
+use crate::map::{MapLayout, Tile};
+
+/// Generated maps fit the screen of the hand-made one.
+const MIN_SIZE: i64 = 7;
+const MAX_WIDTH: i64 = 19;
+const MAX_HEIGHT: i64 = 11;
+
+/// Steps around every spawn kept free of rocks.
+const SPAWN_CLEARANCE: i32 = 2;
+
+/// Parameters of the map generator, chosen by the server.
+#[derive(Clone, Copy, Debug)]
+pub struct MapParams {
+    /// In tiles, without the border
+    pub width: i64,
+    pub height: i64,
+    /// Chance of a free tile getting a rock, from 0 to 1
+    pub rock_density: f64,
+}
+
+impl Default for MapParams {
+    fn default() -> Self {
+        MapParams {
+            width: MAX_WIDTH,
+            height: MAX_HEIGHT,
+            rock_density: 0.6,
+        }
+    }
+}
+
+impl MapParams {
+    /// Sizes are odd, so the pillar grid ends with a corridor on every side.
+    fn clamped(self) -> Self {
+        let odd = |size: i64, max: i64| {
+            let size = size.max(MIN_SIZE).min(max);
+            if size % 2 == 0 {
+                size - 1
+            } else {
+                size
+            }
+        };
+
+        MapParams {
+            width: odd(self.width, MAX_WIDTH),
+            height: odd(self.height, MAX_HEIGHT),
+            rock_density: self.rock_density.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// A new map: floor tiles are `1..=width` x `1..=height` inside a wall border,
+/// with an indestructible pillar on every tile with both coordinates even.
+/// Sent by the server with `create_world`, every peer builds the same world from it.
+pub fn generate(seed: i64, params: MapParams, players: usize) -> MapLayout {
+    let params = params.clamped();
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+
+    let (width, height) = (params.width as i32, params.height as i32);
+    let floor: Vec<Cell> = (1..=height)
+        .flat_map(|y| (1..=width).map(move |x| (x, y)))
+        .filter(|cell| !is_pillar(*cell))
+        .collect();
+
+    let mut layout = MapLayout::new(width + 2, height + 2);
+    layout.name = format!("Generated {}", seed);
+    layout.players = players as i64;
+    for cell in floor.iter() {
+        layout.set_tile(*cell, Tile::Floor);
+    }
+
+    layout.spawns = pick_spawns(&floor, players.max(1), width, height, &mut rng);
+
+    let spawns = &layout.spawns;
+    layout.rocks = floor
+        .iter()
+        .copied()
+        .filter(|cell| {
+            spawns
+                .iter()
+                .all(|spawn| distance(*spawn, *cell) > SPAWN_CLEARANCE)
+        })
+        .filter(|_| rng.gen_bool(params.rock_density))
+        .collect();
+
+    layout
+}
+
+fn is_pillar(cell: Cell) -> bool {
+    cell.0 % 2 == 0 && cell.1 % 2 == 0
+}
+
+/// Steps between two tiles.
+fn distance(a: Cell, b: Cell) -> i32 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+/// A random corner first, then the floor tile farthest from every spawn so far.
+fn pick_spawns(
+    floor: &[Cell],
+    count: usize,
+    width: i32,
+    height: i32,
+    rng: &mut StdRng,
+) -> Vec<Cell> {
+    let corners = [(1, 1), (width, 1), (1, height), (width, height)];
+    let mut spawns = vec![*corners.choose(rng).unwrap()];
+
+    while spawns.len() < count.min(floor.len()) {
+        let farthest = floor
+            .iter()
+            .copied()
+            .filter(|cell| !spawns.contains(cell))
+            .max_by_key(|cell| {
+                let nearest = spawns.iter().map(|spawn| distance(*spawn, *cell)).min();
+                // Ties are broken by the seed
+                (nearest, rng.gen::<u32>())
+            })
+            .unwrap();
+        spawns.push(farthest);
+    }
+
+    spawns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(layout: &MapLayout) -> Vec<Tile> {
+        (0..layout.height)
+            .flat_map(|y| (0..layout.width).map(move |x| (x, y)))
+            .map(|cell| layout.tile(cell))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        let a = generate(42, MapParams::default(), 8);
+        let b = generate(42, MapParams::default(), 8);
+
+        assert_eq!(tiles(&a), tiles(&b));
+        assert_eq!(a.rocks, b.rocks);
+        assert_eq!(a.spawns, b.spawns);
+    }
+
+    #[test]
+    fn sizes_are_odd_and_clamped() {
+        let params = MapParams {
+            width: 8,
+            height: 30,
+            rock_density: 2.0,
+        }
+        .clamped();
+        assert_eq!((params.width, params.height), (7, 11));
+        assert_eq!(params.rock_density, 1.0);
+
+        let params = MapParams {
+            width: 0,
+            height: -4,
+            rock_density: 0.5,
+        }
+        .clamped();
+        assert_eq!((params.width, params.height), (MIN_SIZE, MIN_SIZE));
+
+        let layout = generate(
+            7,
+            MapParams {
+                width: 12,
+                height: 12,
+                rock_density: 0.5,
+            },
+            4,
+        );
+        assert_eq!((layout.width, layout.height), (11 + 2, 11 + 2));
+    }
+
+    #[test]
+    fn spawns_are_clear_of_rocks() {
+        for seed in 0..20 {
+            let layout = generate(seed, MapParams::default(), 12);
+            assert_eq!(layout.spawns.len(), 12);
+
+            for spawn in layout.spawns.iter() {
+                assert_eq!(layout.tile(*spawn), Tile::Floor);
+                for rock in layout.rocks.iter() {
+                    assert!(
+                        distance(*spawn, *rock) > SPAWN_CLEARANCE,
+                        "seed {}: rock {:?} next to spawn {:?}",
+                        seed,
+                        rock,
+                        spawn
+                    );
+                }
+            }
+        }
+    }
+}
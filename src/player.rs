@@ -1,10 +1,43 @@
 use std::f64::consts;
 
+use crate::bomb::BombKind;
+use crate::bot::{BotBrain, Difficulty};
+use crate::grid::{self, Cell};
+use crate::hazard;
+use crate::powerup::PowerUpKind;
+use crate::replay;
+use crate::rules;
 use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
 
-const MOTION_SPEED: f32 = 90.0;
+pub const MOTION_SPEED: f32 = 90.0;
+
+/// Radius of the `shape` of `Player.tscn`.
+pub const PLAYER_RADIUS: f32 = 20.0;
+
+/// Elimination mode: seconds without losing a life after a hit (the stun lasts 1.2 s).
+const INVULNERABILITY: f64 = 2.5;
+
+/// Blinking of an invulnerable player, in blinks per second.
+const BLINK_RATE: f64 = 8.0;
+
+/// Bombs at a time without power-ups.
+const BASE_BOMBS: i64 = 1;
+/// Blast range in tiles without power-ups.
+pub const BASE_BLAST_RANGE: i64 = 2;
+/// Limits of stacked power-ups.
+const MAX_BOMBS: i64 = 8;
+const MAX_BLAST_RANGE: i64 = 8;
+const MAX_SPEED_LEVEL: i64 = 3;
+/// Extra speed of every speed power-up, as a fraction of `MOTION_SPEED`.
+const SPEED_STEP: f32 = 0.2;
+
+/// Seconds between kick requests, the kicked bomb starts moving when the server says so.
+const KICK_COOLDOWN: f64 = 0.3;
+
+/// Updates the master of a player sends to its puppets, clients send them through the server.
+const RELAYED: [&str; 4] = ["update_network", "stun", "crush", "lose_shield"];
 
 #[derive(NativeClass)]
 #[inherit(KinematicBody2D)]
@@ -16,9 +49,50 @@ pub struct Player {
     /// Current animation
     current_anim: String,
     prev_bombing: bool,
+    prev_throwing: bool,
+    prev_detonating: bool,
+    /// Master only: the bomb picked up with the throw key, thrown when it's released
+    held_bomb: Option<String>,
     bomb_index: u16,
+    /// Direction of the last move, bombs are thrown this way
+    facing: Cell,
+    kick_cooldown: f64,
     #[property]
     stunned: bool,
+    /// Kept between frames, players slide on ice
+    velocity: Vector2,
+    /// Teleporters only work when they are stepped on
+    last_cell: Option<Cell>,
+
+    /// Only on the server, for players controlled by a bot
+    bot: Option<BotBrain>,
+
+    /// Elimination mode only
+    lives: Option<i64>,
+    invulnerable_for: f64,
+    /// No lives left, the player is out of the round
+    #[property]
+    eliminated: bool,
+
+    /// Team mode only, 0 means no team
+    #[property]
+    team: i64,
+    friendly_fire: bool,
+
+    // Power-ups, granted by the server on every peer
+    #[property]
+    bomb_capacity: i64,
+    #[property]
+    blast_range: i64,
+    #[property]
+    speed_level: i64,
+    #[property]
+    can_kick: bool,
+    #[property]
+    shield: bool,
+    /// Index of `BombKind::ALL`, from the last bomb power-up picked up
+    #[property]
+    bomb_kind: i64,
 }
 
 #[methods]
@@ -28,8 +102,30 @@ impl Player {
             preload_bomb: PackedScene::new().into_shared(),
             current_anim: "".to_string(),
             prev_bombing: false,
+            prev_throwing: false,
+            prev_detonating: false,
+            held_bomb: None,
             bomb_index: 0,
+            facing: (0, 1),
+            kick_cooldown: 0.0,
             stunned: false,
+            velocity: Vector2::zero(),
+            last_cell: None,
+            bot: None,
+
+            lives: None,
+            invulnerable_for: 0.0,
+            eliminated: false,
+
+            team: 0,
+            friendly_fire: true,
+
+            bomb_capacity: BASE_BOMBS,
+            blast_range: BASE_BLAST_RANGE,
+            speed_level: 0,
+            can_kick: false,
+            shield: false,
+            bomb_kind: BombKind::Normal.to_i64(),
         }
     }
 
@@ -39,55 +135,103 @@ impl Player {
     }
 
     #[export]
-    fn _physics_process(&mut self, owner: TRef<KinematicBody2D>, _delta: f64) {
-        if owner.is_network_master() {
-            let mut motion = Vector2::zero();
+    fn _physics_process(&mut self, owner: TRef<KinematicBody2D>, delta: f64) {
+        // Watching a replay, the player is moved by `ReplayViewer`
+        if !unsafe { utils::get_tree(owner.as_ref()).has_network_peer() } {
+            return;
+        }
 
-            let input = Input::godot_singleton();
-            if Input::is_action_pressed(input, "ui_left") {
-                motion.x -= 1.0;
-            }
-            if Input::is_action_pressed(input, "ui_right") {
-                motion.x += 1.0;
-            }
-            if Input::is_action_pressed(input, "ui_up") {
-                motion.y -= 1.0;
-            }
-            if Input::is_action_pressed(input, "ui_down") {
-                motion.y += 1.0;
-            }
+        if self.invulnerable_for > 0.0 {
+            self.invulnerable_for -= delta;
+
+            let visible = self.invulnerable_for <= 0.0
+                || (self.invulnerable_for * BLINK_RATE).floor() as i64 % 2 == 0;
+            owner.set_modulate(Color::rgba(1.0, 1.0, 1.0, if visible { 1.0 } else { 0.3 }));
+        }
 
-            let mut bombing = Input::is_action_pressed(input, "ui_select");
+        if self.eliminated {
+            return;
+        }
+
+        if owner.is_network_master() {
+            let (mut motion, mut bombing, mut throwing, mut detonating) = match &mut self.bot {
+                Some(bot) => {
+                    let (motion, bombing) = bot.update(owner, self.stunned, delta);
+                    (motion, bombing, false, false)
+                }
+                None => Self::read_input(),
+            };
 
             if self.stunned {
                 bombing = false;
+                throwing = false;
+                detonating = false;
                 motion = Vector2::zero();
             }
 
             if bombing && !self.prev_bombing {
-                let bomb_name = format!("{}{}", owner.name(), self.bomb_index);
-                let bomb_pos = owner.position();
-                let network_unique_id =
-                    unsafe { utils::get_tree(owner.as_ref()).get_network_unique_id() };
-
-                self.setup_bomb(
-                    owner,
-                    bomb_name.to_variant(),
-                    bomb_pos.to_variant(),
-                    network_unique_id.to_variant(),
-                );
-                owner.rpc(
-                    "setup_bomb",
-                    &[
-                        bomb_name.to_variant(),
-                        bomb_pos.to_variant(),
-                        network_unique_id.to_variant(),
-                    ],
-                );
+                // Bombs lie in the middle of a tile
+                let world = unsafe { utils::get_world(owner.as_ref()) };
+                let bomb_pos = grid::snap(grid::get_tilemap(world), owner.position());
+
+                // Checked here too, so a full bomb bag doesn't cost a request
+                if self.can_place_bomb(owner, bomb_pos) {
+                    if unsafe { utils::get_tree(owner.as_ref()) }.is_network_server() {
+                        self.request_bomb(owner, bomb_pos);
+                    } else {
+                        owner.rpc_id(1, "request_bomb", &[bomb_pos.to_variant()]);
+                    }
+                }
             }
 
             self.prev_bombing = bombing;
 
+            // Only straight moves kick bombs and change where they are thrown
+            let direction = if motion.x != 0.0 && motion.y == 0.0 {
+                Some((motion.x.signum() as i32, 0))
+            } else if motion.y != 0.0 && motion.x == 0.0 {
+                Some((0, motion.y.signum() as i32))
+            } else {
+                None
+            };
+            if let Some(direction) = direction {
+                self.facing = direction;
+            }
+
+            self.kick_cooldown -= delta;
+            if let Some(direction) = direction {
+                if self.can_kick && self.kick_cooldown <= 0.0 {
+                    if let Some(bomb_name) = self.bomb_ahead(owner, direction) {
+                        self.kick_cooldown = KICK_COOLDOWN;
+                        self.send_bomb_request(owner, "request_kick", bomb_name, direction);
+                    }
+                }
+            }
+
+            // The bomb under the player is picked up with the key, and thrown when it's released
+            if throwing && !self.prev_throwing {
+                let world = unsafe { utils::get_world(owner.as_ref()) };
+                let cell = grid::to_cell(grid::get_tilemap(world), owner.position());
+                if let Some(bomb_name) = self.still_bomb_at(owner, cell) {
+                    self.held_bomb = Some(bomb_name.clone());
+                    self.send_bomb_request(owner, "request_pick_up", bomb_name, self.facing);
+                }
+            } else if !throwing && self.prev_throwing {
+                if let Some(bomb_name) = self.held_bomb.take() {
+                    self.send_bomb_request(owner, "request_throw", bomb_name, self.facing);
+                }
+            }
+            self.prev_throwing = throwing;
+
+            if detonating && !self.prev_detonating {
+                if unsafe { utils::get_tree(owner.as_ref()) }.is_network_server() {
+                    self.request_detonate(owner);
+                } else {
+                    owner.rpc_id(1, "request_detonate", &[]);
+                }
+            }
+            self.prev_detonating = detonating;
+
             let mut new_anim = String::from("standing");
             if motion.y < 0.0 {
                 new_anim = String::from("walk_up");
@@ -112,62 +256,417 @@ impl Player {
                 }
             }
 
-            owner.move_and_slide(
-                motion * MOTION_SPEED,
+            // Hazards are applied by the master of the player, like the rest of the motion
+            let world = unsafe { utils::get_world(owner.as_ref()) };
+            let tilemap = grid::get_tilemap(world);
+            let cell = grid::to_cell(tilemap, owner.position());
+
+            let speed = MOTION_SPEED * (1.0 + SPEED_STEP * self.speed_level as f32);
+            self.velocity = hazard::steer(tilemap, cell, self.velocity, motion * speed, delta);
+            let moved = owner.move_and_slide(
+                self.velocity + hazard::push(tilemap, cell),
                 Vector2::new(0.0, 1.0),
                 false,
                 4,
                 consts::FRAC_PI_4,
                 true,
             );
+
+            // Running into a wall stops the slide on ice
+            if moved.x == 0.0 {
+                self.velocity.x = 0.0;
+            }
+            if moved.y == 0.0 {
+                self.velocity.y = 0.0;
+            }
+
+            self.teleport(owner, world);
         }
 
         if owner.is_network_master() {
-            owner.rpc(
-                "update_network",
-                &[
-                    owner.position().to_variant(),
-                    self.current_anim.to_variant(),
-                ],
-            );
+            let args = [
+                owner.position().to_variant(),
+                self.current_anim.to_variant(),
+            ];
+
+            self.send_to_puppets(owner, "update_network", &args);
+            replay::record_event(owner.as_ref(), "update_network", &args);
+        }
+    }
+
+    /// Stepping on a teleporter, the player comes out of the other one,
+    /// unless a rock stands there.
+    fn teleport(&mut self, owner: TRef<KinematicBody2D>, world: TRef<Node2D>) {
+        let tilemap = grid::get_tilemap(world);
+        let cell = grid::to_cell(tilemap, owner.position());
+        if self.last_cell == Some(cell) {
+            return;
+        }
+        self.last_cell = Some(cell);
+
+        if let Some(exit) = hazard::teleporter_exit(tilemap, cell) {
+            if !grid::rocks(world).contains(&exit) {
+                owner.set_position(grid::cell_center(tilemap, exit));
+                self.last_cell = Some(exit);
+            }
         }
     }
 
     // Updating position of the player
-    #[export(rpc = "puppet")]
+    #[export(rpc = "remote")]
     fn update_network(
         &self,
         owner: TRef<KinematicBody2D>,
         position: Variant,
         current_anim: Variant,
     ) {
+        if !self.is_trusted_sender(owner) {
+            return;
+        }
+
         owner.set_position(position.to_vector2());
         unsafe {
             self.get_animation(owner)
                 .play(current_anim.to_string(), -1.0, 1.0, false);
         }
+
+        replay::record_event(owner.as_ref(), "update_network", &[position, current_anim]);
+    }
+
+    /// Server: the master of the player wants a bomb at `bomb_pos`,
+    /// the server names it and decides when it explodes.
+    #[export(rpc = "remote")]
+    fn request_bomb(&mut self, owner: TRef<KinematicBody2D>, bomb_pos: Vector2) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let sender = tree.get_rpc_sender_id();
+        if sender != 0 && sender != owner.get_network_master() {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let bomb_pos = grid::snap(grid::get_tilemap(world), bomb_pos);
+        if self.stunned || self.eliminated || !self.can_place_bomb(owner, bomb_pos) {
+            return;
+        }
+
+        let bomb_name = format!("{}{}", owner.name(), self.bomb_index);
+        self.bomb_index = self.bomb_index.wrapping_add(1);
+        // Player id is the node name, bots don't have their own network id
+        let network_unique_id = self.player_id(owner);
+
+        // Bots don't use the detonator
+        let kind = match BombKind::from_i64(self.bomb_kind) {
+            BombKind::Remote if self.bot.is_some() => BombKind::Normal,
+            kind => kind,
+        };
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let explode_at =
+            unsafe { gamestate.callv("bomb_explode_at", VariantArray::new_shared()) }.to_i64();
+
+        owner.rpc(
+            "setup_bomb",
+            &[
+                bomb_name.to_variant(),
+                bomb_pos.to_variant(),
+                network_unique_id.to_variant(),
+                explode_at.to_variant(),
+                kind.to_i64().to_variant(),
+                self.blast_range.to_variant(),
+            ],
+        );
+        self.setup_bomb(
+            owner,
+            bomb_name.to_variant(),
+            bomb_pos,
+            network_unique_id,
+            explode_at,
+            kind.to_i64(),
+            self.blast_range,
+        );
+    }
+
+    /// Server: the master of the player sets off all its remote bombs, oldest first.
+    #[export(rpc = "remote")]
+    fn request_detonate(&self, owner: TRef<KinematicBody2D>) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return;
+        }
+
+        let sender = tree.get_rpc_sender_id();
+        if sender != 0 && sender != owner.get_network_master() {
+            return;
+        }
+
+        if self.stunned || self.eliminated {
+            return;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let player_id = self.player_id(owner);
+
+        // Bombs are the only `Area2D` children of `World`
+        let mut bombs: Vec<TRef<Area2D>> = world
+            .get_children()
+            .iter()
+            .filter_map(|child| {
+                unsafe { child.try_to_object::<Node>()?.assume_safe() }.cast::<Area2D>()
+            })
+            .filter(|bomb| {
+                !bomb.get("exploded").to_bool()
+                    && bomb.get("from_player_id").to_i64() == player_id
+                    && BombKind::from_i64(bomb.get("kind").to_i64()) == BombKind::Remote
+            })
+            .collect();
+        bombs.sort_by_key(|bomb| bomb.name().to_string());
+
+        for bomb in bombs {
+            bomb.rpc("trigger", &[]);
+            unsafe {
+                bomb.call_deferred("trigger", &[]);
+            }
+        }
+    }
+
+    /// Server: the master of the player walked into `bomb_name` with the kick power-up.
+    #[export(rpc = "remote")]
+    fn request_kick(&self, owner: TRef<KinematicBody2D>, bomb_name: String, dx: i64, dy: i64) {
+        if !self.can_kick {
+            return;
+        }
+
+        // The bomb is next to the player, or already under its front
+        if let Some((bomb, bomb_cell, cell)) = self.requested_bomb(owner, &bomb_name, dx, dy) {
+            let carried = !bomb.get("carried_by").to_string().is_empty();
+            if !carried
+                && (bomb_cell == (cell.0 + dx as i32, cell.1 + dy as i32) || bomb_cell == cell)
+            {
+                unsafe {
+                    bomb.call_deferred("kick", &[dx.to_variant(), dy.to_variant()]);
+                }
+            }
+        }
     }
 
-    /// Remote function (You need to call the function like a `remotesync` mode.
-    ///
-    /// Example:
-    /// ```
-    /// setup_bomb(...);
-    /// rpc("setup_bomb", ...);
-    /// ```
-    ///
-    /// Create bomb
+    /// Server: the master of the player picks up `bomb_name` it's standing on,
+    /// a player holds one bomb at a time.
+    #[export(rpc = "remote")]
+    fn request_pick_up(&self, owner: TRef<KinematicBody2D>, bomb_name: String, dx: i64, dy: i64) {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let carrier = owner.name().to_string();
+        if self.held_bomb_of(world, &carrier).is_some() {
+            return;
+        }
+
+        if let Some((bomb, bomb_cell, cell)) = self.requested_bomb(owner, &bomb_name, dx, dy) {
+            if bomb_cell == cell && bomb.get("carried_by").to_string().is_empty() {
+                bomb.rpc("pick_up", &[carrier.to_variant()]);
+                unsafe {
+                    bomb.call_deferred("pick_up", &[carrier.to_variant()]);
+                }
+            }
+        }
+    }
+
+    /// Server: the master of the player throws `bomb_name` it's holding.
+    #[export(rpc = "remote")]
+    fn request_throw(&self, owner: TRef<KinematicBody2D>, bomb_name: String, dx: i64, dy: i64) {
+        if let Some((bomb, _, _)) = self.requested_bomb(owner, &bomb_name, dx, dy) {
+            if bomb.get("carried_by").to_string() == owner.name().to_string() {
+                unsafe {
+                    bomb.call_deferred("throw", &[dx.to_variant(), dy.to_variant()]);
+                }
+            }
+        }
+    }
+
+    /// Name of the live bomb `carrier` (a player name) is holding.
+    fn held_bomb_of(&self, world: TRef<Node2D>, carrier: &str) -> Option<String> {
+        // Bombs are the only `Area2D` children of `World`
+        world
+            .get_children()
+            .iter()
+            .filter_map(|child| {
+                unsafe { child.try_to_object::<Node>()?.assume_safe() }.cast::<Area2D>()
+            })
+            .find(|bomb| {
+                !bomb.get("exploded").to_bool() && bomb.get("carried_by").to_string() == carrier
+            })
+            .map(|bomb| bomb.name().to_string())
+    }
+
+    /// Server only: the still bomb of a kick, pick-up or throw request from the master of the player,
+    /// with its cell and the cell of the player. `(dx, dy)` must be a straight direction.
+    /// Moving the bomb is deferred, it reads players back.
+    fn requested_bomb<'a>(
+        &self,
+        owner: TRef<'a, KinematicBody2D>,
+        bomb_name: &str,
+        dx: i64,
+        dy: i64,
+    ) -> Option<(TRef<'a, Area2D>, Cell, Cell)> {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.is_network_server() {
+            return None;
+        }
+
+        let sender = tree.get_rpc_sender_id();
+        if sender != 0 && sender != owner.get_network_master() {
+            return None;
+        }
+
+        if self.stunned || self.eliminated || dx.abs() + dy.abs() != 1 {
+            return None;
+        }
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let bomb = unsafe { world.get_node(bomb_name)?.assume_safe() }.cast::<Area2D>()?;
+        if bomb.get("exploded").to_bool() || bomb.get("moving").to_bool() {
+            return None;
+        }
+
+        let tilemap = grid::get_tilemap(world);
+        Some((
+            bomb,
+            grid::to_cell(tilemap, bomb.position()),
+            grid::to_cell(tilemap, owner.position()),
+        ))
+    }
+
+    /// Kick, pick-up and throw requests are checked by the server, the host sends them to itself.
+    fn send_bomb_request(
+        &self,
+        owner: TRef<KinematicBody2D>,
+        method: &str,
+        bomb_name: String,
+        direction: Cell,
+    ) {
+        let (dx, dy) = (direction.0 as i64, direction.1 as i64);
+        if unsafe { utils::get_tree(owner.as_ref()) }.is_network_server() {
+            match method {
+                "request_kick" => self.request_kick(owner, bomb_name, dx, dy),
+                "request_pick_up" => self.request_pick_up(owner, bomb_name, dx, dy),
+                _ => self.request_throw(owner, bomb_name, dx, dy),
+            }
+        } else {
+            owner.rpc_id(
+                1,
+                method,
+                &[bomb_name.to_variant(), dx.to_variant(), dy.to_variant()],
+            );
+        }
+    }
+
+    /// Name of the still bomb the front of the player touches, walking towards `direction`.
+    fn bomb_ahead(&self, owner: TRef<KinematicBody2D>, direction: Cell) -> Option<String> {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        let half_tile = tilemap.cell_size().x / 2.0;
+        let front =
+            owner.position() + Vector2::new(direction.0 as f32, direction.1 as f32) * half_tile;
+
+        let cell = grid::to_cell(tilemap, owner.position());
+        let ahead = grid::to_cell(tilemap, front);
+        if ahead == cell {
+            return None;
+        }
+
+        self.still_bomb_at(owner, ahead)
+    }
+
+    /// Name of a bomb lying in `cell`, neither moving, carried nor exploded.
+    fn still_bomb_at(&self, owner: TRef<KinematicBody2D>, cell: Cell) -> Option<String> {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+
+        // Bombs are the only `Area2D` children of `World`
+        world
+            .get_children()
+            .iter()
+            .filter_map(|child| {
+                unsafe { child.try_to_object::<Node>()?.assume_safe() }.cast::<Area2D>()
+            })
+            .find(|bomb| {
+                !bomb.get("exploded").to_bool()
+                    && !bomb.get("moving").to_bool()
+                    && bomb.get("carried_by").to_string().is_empty()
+                    && grid::to_cell(tilemap, bomb.position()) == cell
+            })
+            .map(|bomb| bomb.name().to_string())
+    }
+
+    /// Server (Sync): create bomb
     /// `bomb_name`: String
     /// `bomb_pos`: Vector2
     /// `network_unique_id`: i64
+    /// `explode_at`: server time of the explosion in msec
+    /// `kind`: index of `BombKind::ALL`
+    /// `blast_range`: range of the player when the bomb was placed
     #[export(rpc = "remote")]
+    #[allow(clippy::too_many_arguments)]
     fn setup_bomb(
         &self,
         owner: TRef<KinematicBody2D>,
         bomb_name: Variant,
-        bomb_pos: Variant,
-        network_unique_id: Variant,
+        bomb_pos: Vector2,
+        network_unique_id: i64,
+        explode_at: i64,
+        kind: i64,
+        blast_range: i64,
+    ) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.get_rpc_sender_id() > 1 {
+            return;
+        }
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        let func_args = VariantArray::new();
+        func_args.push(explode_at);
+        let fuse_msec = unsafe { gamestate.callv("fuse_left_msec", func_args.into_shared()) };
+
+        self.place_bomb(
+            owner,
+            bomb_name,
+            bomb_pos,
+            network_unique_id,
+            fuse_msec.to_i64(),
+            kind,
+            blast_range,
+        );
+    }
+
+    /// Bomb of this player exploding in `fuse_msec`, also called by `ReplayViewer`.
+    #[export]
+    #[allow(clippy::too_many_arguments)]
+    fn place_bomb(
+        &self,
+        owner: TRef<KinematicBody2D>,
+        bomb_name: Variant,
+        bomb_pos: Vector2,
+        network_unique_id: i64,
+        fuse_msec: i64,
+        kind: i64,
+        blast_range: i64,
     ) {
+        replay::record_event(
+            owner.as_ref(),
+            "setup_bomb",
+            &[
+                bomb_name.clone(),
+                bomb_pos.to_variant(),
+                network_unique_id.to_variant(),
+                fuse_msec.to_variant(),
+                kind.to_variant(),
+                blast_range.to_variant(),
+            ],
+        );
+
         let bomb_packed_scene = unsafe { self.preload_bomb.assume_safe() };
 
         // Bomb
@@ -178,28 +677,388 @@ impl Player {
 
         // Bomb properties
         bomb.set_name(bomb_name.to_godot_string()); // Ensure unique name for the bomb
-        bomb.set_position(bomb_pos.to_vector2());
+        bomb.set_position(bomb_pos);
         bomb.set("from_player_id", network_unique_id);
+        bomb.set("blast_range", blast_range);
+        bomb.set("fuse_left", fuse_msec as f64 / 1000.0);
+        bomb.set("kind", kind);
         //
 
         // No need to set network master to bomb, by default will be owned by the server
         let world = unsafe { utils::get_world(owner.as_ref()) };
         world.add_child(bomb, false);
+
+        self.refresh_hud(owner);
+    }
+
+    /// `by_who`: id of the player whose bomb stunned this one
+    #[export(rpc = "remote")]
+    fn stun(&mut self, owner: TRef<KinematicBody2D>, by_who: Variant) {
+        if !self.is_trusted_sender(owner) {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "stun", &[by_who]);
+
+        self.stunned = true;
+
+        // Every peer counts lives, `stun` is received by all of them
+        if let Some(lives) = self.lives {
+            self.set_lives(owner, lives - 1);
+            self.invulnerable_for = INVULNERABILITY;
+
+            if lives - 1 <= 0 {
+                self.eliminate(owner);
+            }
+        }
+    }
+
+    /// Server: caught by the closing arena, received by the master of the player.
+    #[export(rpc = "master")]
+    fn arena_closed(&mut self, owner: TRef<KinematicBody2D>, position: Vector2) {
+        if self.eliminated {
+            return;
+        }
+
+        self.send_to_puppets(owner, "crush", &[position.to_variant()]);
+        self.crush(owner, position);
+    }
+
+    /// Moved out of the new wall to `position` and stunned,
+    /// in elimination mode the player is out of the round.
+    #[export(rpc = "remote")]
+    fn crush(&mut self, owner: TRef<KinematicBody2D>, position: Vector2) {
+        if !self.is_trusted_sender(owner) {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "crush", &[position.to_variant()]);
+
+        owner.set_position(position);
+        self.stunned = true;
+
+        if self.lives.is_some() {
+            self.set_lives(owner, 0);
+            self.eliminate(owner);
+        }
+    }
+
+    /// Elimination mode: lives left, shown under the nickname.
+    #[export]
+    fn set_lives(&mut self, owner: TRef<KinematicBody2D>, lives: i64) {
+        self.lives = Some(lives.max(0));
+
+        // `lives` Label
+        let lives_label = owner.get_node("lives").unwrap();
+        let lives_label = unsafe { lives_label.assume_safe() };
+        let lives_label = lives_label.cast::<Label>().unwrap();
+        //
+
+        lives_label.set_text(format!("Lives: {}", lives.max(0)));
+        lives_label.show();
+    }
+
+    /// Lives back to `lives` when `ReplayViewer` is seeking, out of the round without any.
+    #[export]
+    fn restore_lives(&mut self, owner: TRef<KinematicBody2D>, lives: i64) {
+        self.set_lives(owner, lives);
+        self.eliminated = lives <= 0;
+        owner.set_visible(!self.eliminated);
+    }
+
+    /// Lives left, 0 when playing without lives.
+    #[export]
+    fn get_lives(&self, _owner: TRef<KinematicBody2D>) -> i64 {
+        self.lives.unwrap_or(0)
+    }
+
+    /// The player is removed from the round: hidden, out of reach of blasts,
+    /// and its peer is a spectator until the next round.
+    fn eliminate(&mut self, owner: TRef<KinematicBody2D>) {
+        self.eliminated = true;
+
+        owner.hide();
+        owner.set_collision_layer(0);
+        owner.set_collision_mask(0);
+
+        // Replays only hide the player
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() {
+            return;
+        }
+
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        unsafe {
+            gamestate.call_deferred("player_eliminated", &[self.player_id(owner).to_variant()]);
+        }
+    }
+
+    /// One bomb per tile, and no more live bombs at a time than `bomb_capacity`.
+    fn can_place_bomb(&self, owner: TRef<KinematicBody2D>, bomb_pos: Vector2) -> bool {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let player_id = self.player_id(owner);
+
+        let mut live_bombs = 0;
+        // Bombs are the only `Area2D` children of `World`
+        for child in world.get_children().iter() {
+            let child = unsafe { child.try_to_object::<Node>().unwrap().assume_safe() };
+            if let Some(bomb) = child.cast::<Area2D>() {
+                // Exploded bombs are only showing their blast
+                if bomb.get("exploded").to_bool() {
+                    continue;
+                }
+
+                if bomb.position() == bomb_pos {
+                    return false;
+                }
+                if bomb.get("from_player_id").to_i64() == player_id {
+                    live_bombs += 1;
+                }
+            }
+        }
+
+        live_bombs < self.bomb_capacity
+    }
+
+    /// Bombs of this player which haven't exploded yet.
+    fn live_bombs(&self, owner: TRef<KinematicBody2D>) -> i64 {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let player_id = self.player_id(owner);
+
+        world
+            .get_children()
+            .iter()
+            .filter_map(|child| {
+                unsafe { child.try_to_object::<Node>()?.assume_safe() }.cast::<Area2D>()
+            })
+            .filter(|bomb| {
+                !bomb.get("exploded").to_bool() && bomb.get("from_player_id").to_i64() == player_id
+            })
+            .count() as i64
+    }
+
+    /// Server (Sync): a picked up power-up, `kind` is an index of `PowerUpKind::ALL`.
+    #[export(rpc = "remote")]
+    fn add_powerup(&mut self, owner: TRef<KinematicBody2D>, kind: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.get_rpc_sender_id() > 1 {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "add_powerup", &[kind.to_variant()]);
+
+        match PowerUpKind::from_i64(kind) {
+            Some(PowerUpKind::ExtraBomb) => {
+                self.bomb_capacity = (self.bomb_capacity + 1).min(MAX_BOMBS)
+            }
+            Some(PowerUpKind::BlastRange) => {
+                self.blast_range = (self.blast_range + 1).min(MAX_BLAST_RANGE)
+            }
+            Some(PowerUpKind::Speed) => {
+                self.speed_level = (self.speed_level + 1).min(MAX_SPEED_LEVEL)
+            }
+            Some(PowerUpKind::Kick) => self.can_kick = true,
+            Some(PowerUpKind::Shield) => self.shield = true,
+            Some(PowerUpKind::Remote) => self.bomb_kind = BombKind::Remote.to_i64(),
+            Some(PowerUpKind::Piercing) => self.bomb_kind = BombKind::Piercing.to_i64(),
+            Some(PowerUpKind::Mine) => self.bomb_kind = BombKind::Mine.to_i64(),
+            None => return,
+        }
+
+        self.refresh_hud(owner);
     }
 
-    #[export(rpc = "puppet")]
-    fn stun(&mut self, _owner: TRef<KinematicBody2D>) {
-        self.stunned = true
+    /// Power-ups back to the `kinds` picked up in the round when `ReplayViewer` is seeking.
+    #[export]
+    fn set_powerups(&mut self, owner: TRef<KinematicBody2D>, kinds: VariantArray) {
+        self.bomb_capacity = BASE_BOMBS;
+        self.blast_range = BASE_BLAST_RANGE;
+        self.speed_level = 0;
+        self.can_kick = false;
+        self.shield = false;
+        self.bomb_kind = BombKind::Normal.to_i64();
+
+        for kind in kinds.iter() {
+            self.add_powerup(owner, kind.to_i64());
+        }
+        self.refresh_hud(owner);
+    }
+
+    /// The shield took a hit instead of the player.
+    #[export(rpc = "remote")]
+    fn lose_shield(&mut self, owner: TRef<KinematicBody2D>) {
+        if !self.is_trusted_sender(owner) {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "lose_shield", &[]);
+
+        self.shield = false;
+        self.invulnerable_for = INVULNERABILITY;
+        self.refresh_hud(owner);
+    }
+
+    /// Live bombs out of `bomb_capacity` and other power-ups, for the HUD.
+    #[export]
+    fn get_status(&self, owner: TRef<KinematicBody2D>) -> String {
+        let mut status = vec![format!(
+            "bombs {}/{}",
+            self.live_bombs(owner),
+            self.bomb_capacity
+        )];
+        if self.blast_range > BASE_BLAST_RANGE {
+            status.push(format!("range {}", self.blast_range));
+        }
+        if self.speed_level > 0 {
+            status.push(format!("speed +{}", self.speed_level));
+        }
+        if self.can_kick {
+            status.push(String::from("kick"));
+        }
+        if self.shield {
+            status.push(String::from("shield"));
+        }
+        let bomb_kind = BombKind::from_i64(self.bomb_kind);
+        if bomb_kind != BombKind::Normal {
+            status.push(String::from(bomb_kind.name()));
+        }
+
+        status.join(", ")
+    }
+
+    fn refresh_hud(&self, owner: TRef<KinematicBody2D>) {
+        // Not right away, `GameState` reads this player back
+        let gamestate = unsafe { utils::get_gamestate_singleton(owner.as_ref()) };
+        unsafe {
+            gamestate.call_deferred("update_hud", &[]);
+        }
+    }
+
+    /// Server only: the player is controlled by a bot from now on.
+    /// `difficulty` is an index of `Difficulty::ALL`.
+    #[export]
+    fn make_bot(&mut self, _owner: TRef<KinematicBody2D>, difficulty: i64) {
+        self.bot = Some(BotBrain::new(Difficulty::from_i64(difficulty)));
+    }
+
+    /// Updates of the master of the player always come from the server,
+    /// it relays the ones of clients, so bots can't be told apart from humans.
+    fn is_trusted_sender(&self, owner: TRef<KinematicBody2D>) -> bool {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+
+        let sender = tree.get_rpc_sender_id();
+        if sender == 0 {
+            // Called locally, or relayed by this server
+            return true;
+        }
+
+        !owner.is_network_master() && sender == 1
+    }
+
+    /// Master: `method` is called on the puppets of the player, through the server from clients.
+    fn send_to_puppets(&self, owner: TRef<KinematicBody2D>, method: &str, args: &[Variant]) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.is_network_server() {
+            owner.rpc(method, args);
+        } else {
+            let args = args
+                .iter()
+                .cloned()
+                .collect::<VariantArray<Unique>>()
+                .into_shared();
+            owner.rpc_id(1, "relay", &[method.to_variant(), args.to_variant()]);
+        }
+    }
+
+    /// Server: an update of the master of the player, passed on to everyone else.
+    #[export(rpc = "remote")]
+    fn relay(&self, owner: TRef<KinematicBody2D>, method: String, args: VariantArray) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        let sender = tree.get_rpc_sender_id();
+        if !tree.is_network_server()
+            || sender != owner.get_network_master()
+            || !RELAYED.contains(&method.as_str())
+        {
+            return;
+        }
+
+        let args: Vec<Variant> = args.iter().collect();
+        for peer in tree.get_network_connected_peers().read().iter() {
+            let peer = *peer as i64;
+            if peer != sender {
+                owner.rpc_id(peer, method.as_str(), &args);
+            }
+        }
+
+        // The server is a puppet too, the player is still borrowed here
+        unsafe {
+            owner.call_deferred(method, &args);
+        }
+    }
+
+    fn read_input() -> (Vector2, bool, bool, bool) {
+        let mut motion = Vector2::zero();
+
+        let input = Input::godot_singleton();
+        if Input::is_action_pressed(input, "ui_left") {
+            motion.x -= 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_right") {
+            motion.x += 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_up") {
+            motion.y -= 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_down") {
+            motion.y += 1.0;
+        }
+
+        (
+            motion,
+            Input::is_action_pressed(input, "ui_select"),
+            Input::is_action_pressed(input, "throw_bomb"),
+            Input::is_action_pressed(input, "detonate_bombs"),
+        )
+    }
+
+    fn player_id(&self, owner: TRef<KinematicBody2D>) -> i64 {
+        owner.name().to_string().parse().unwrap_or(0)
+    }
+
+    /// Blasts of teammates are harmless without friendly fire, your own bombs still hit you.
+    fn is_teammate_hit(&self, owner: TRef<KinematicBody2D>, by_who: &Variant) -> bool {
+        let by_who = by_who.to_i64();
+        if self.friendly_fire || self.team == 0 || by_who == self.player_id(owner) {
+            return false;
+        }
+
+        owner
+            .get_parent()
+            .and_then(|players| unsafe { players.assume_safe() }.get_node(by_who.to_string()))
+            .map(|player| unsafe { player.assume_safe() }.get("team").to_i64() == self.team)
+            .unwrap_or(false)
     }
 
     #[export(rpc = "master")]
-    fn exploded(&mut self, owner: TRef<KinematicBody2D>, _by_who: Variant) {
-        if self.stunned {
+    fn exploded(&mut self, owner: TRef<KinematicBody2D>, by_who: Variant) {
+        if self.stunned || self.invulnerable_for > 0.0 || self.eliminated {
+            return;
+        }
+
+        if self.is_teammate_hit(owner, &by_who) {
             return;
         }
 
-        owner.rpc("stun", &[]); // Stun puppets
-        self.stun(owner); // Stun master - could use sync to do both at once
+        replay::record_event(owner.as_ref(), "exploded", std::slice::from_ref(&by_who));
+
+        if self.shield {
+            self.send_to_puppets(owner, "lose_shield", &[]);
+            self.lose_shield(owner);
+            return;
+        }
+
+        self.send_to_puppets(owner, "stun", std::slice::from_ref(&by_who)); // Stun puppets
+        self.stun(owner, by_who); // Stun master - could use sync to do both at once
     }
 
     #[export]
@@ -213,6 +1072,36 @@ impl Player {
         nickname.set_text(player_name.to_godot_string());
     }
 
+    /// Preferred color of the local profile
+    #[export]
+    fn set_player_color(&self, owner: TRef<KinematicBody2D>, color: Color) {
+        // `sprite` Sprite
+        let sprite = owner.get_node("sprite").unwrap();
+        let sprite = unsafe { sprite.assume_safe() };
+        let sprite = sprite.cast::<Sprite>().unwrap();
+        //
+
+        sprite.set_modulate(color);
+    }
+
+    /// Team mode: players are tinted and named in the color of their team.
+    #[export]
+    fn set_team(&mut self, owner: TRef<KinematicBody2D>, team: i64, friendly_fire: bool) {
+        self.team = team;
+        self.friendly_fire = friendly_fire;
+
+        let color = rules::team_color(team);
+        self.set_player_color(owner, color);
+
+        // `nickname` Label
+        let nickname = owner.get_node("nickname").unwrap();
+        let nickname = unsafe { nickname.assume_safe() };
+        let nickname = nickname.cast::<Label>().unwrap();
+        //
+
+        nickname.add_color_override("font_color", color);
+    }
+
     fn preload_instances(&mut self) {
         let bomb_scene = ResourceLoader::godot_singleton()
             .load("res://scenes/Bomb/Bomb.tscn", "PackedScene", false)
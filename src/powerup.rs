@@ -0,0 +1,318 @@
+use rand::Rng;
+
+use crate::replay;
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Power-ups dropped by rocks, sent as an index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerUpKind {
+    /// One more bomb at a time
+    ExtraBomb,
+    /// Blast one tile longer
+    BlastRange,
+    /// Faster walking
+    Speed,
+    /// Kicking bombs
+    Kick,
+    /// The next hit is absorbed
+    Shield,
+    /// Bombs set off with the detonator key
+    Remote,
+    /// Blasts going through rocks
+    Piercing,
+    /// Bombs set off by players walking over them
+    Mine,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 8] = [
+        PowerUpKind::ExtraBomb,
+        PowerUpKind::BlastRange,
+        PowerUpKind::Speed,
+        PowerUpKind::Kick,
+        PowerUpKind::Shield,
+        PowerUpKind::Remote,
+        PowerUpKind::Piercing,
+        PowerUpKind::Mine,
+    ];
+
+    pub fn from_i64(value: i64) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn to_i64(self) -> i64 {
+        Self::ALL.iter().position(|kind| *kind == self).unwrap() as i64
+    }
+
+    /// Key of `[powerups]` in the settings file and of `--powerup`.
+    pub fn key(self) -> &'static str {
+        match self {
+            PowerUpKind::ExtraBomb => "extra_bomb",
+            PowerUpKind::BlastRange => "blast_range",
+            PowerUpKind::Speed => "speed",
+            PowerUpKind::Kick => "kick",
+            PowerUpKind::Shield => "shield",
+            PowerUpKind::Remote => "remote",
+            PowerUpKind::Piercing => "piercing",
+            PowerUpKind::Mine => "mine",
+        }
+    }
+
+    pub fn parse(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.key() == key)
+    }
+
+    /// Letter on the pickup.
+    fn letter(self) -> &'static str {
+        match self {
+            PowerUpKind::ExtraBomb => "B",
+            PowerUpKind::BlastRange => "R",
+            PowerUpKind::Speed => "S",
+            PowerUpKind::Kick => "K",
+            PowerUpKind::Shield => "D",
+            PowerUpKind::Remote => "T",
+            PowerUpKind::Piercing => "P",
+            PowerUpKind::Mine => "M",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            PowerUpKind::ExtraBomb => Color::rgb(0.9, 0.3, 0.3),
+            PowerUpKind::BlastRange => Color::rgb(1.0, 0.6, 0.2),
+            PowerUpKind::Speed => Color::rgb(0.3, 0.8, 1.0),
+            PowerUpKind::Kick => Color::rgb(0.6, 0.9, 0.3),
+            PowerUpKind::Shield => Color::rgb(0.8, 0.5, 1.0),
+            PowerUpKind::Remote => Color::rgb(0.5, 0.7, 1.0),
+            PowerUpKind::Piercing => Color::rgb(1.0, 0.4, 0.4),
+            PowerUpKind::Mine => Color::rgb(0.6, 0.6, 0.6),
+        }
+    }
+
+    /// Default chance of a destroyed rock dropping this power-up.
+    fn default_chance(self) -> f64 {
+        match self {
+            PowerUpKind::ExtraBomb => 0.08,
+            PowerUpKind::BlastRange => 0.08,
+            PowerUpKind::Speed => 0.06,
+            PowerUpKind::Kick => 0.04,
+            PowerUpKind::Shield => 0.04,
+            PowerUpKind::Remote => 0.03,
+            PowerUpKind::Piercing => 0.03,
+            PowerUpKind::Mine => 0.03,
+        }
+    }
+}
+
+/// Chances of a destroyed rock dropping each power-up, at most one per rock.
+#[derive(Clone, Debug)]
+pub struct PowerUpTable {
+    chances: [f64; PowerUpKind::ALL.len()],
+}
+
+impl Default for PowerUpTable {
+    fn default() -> Self {
+        let mut chances = [0.0; PowerUpKind::ALL.len()];
+        for kind in PowerUpKind::ALL.iter() {
+            chances[kind.to_i64() as usize] = kind.default_chance();
+        }
+
+        PowerUpTable { chances }
+    }
+}
+
+impl PowerUpTable {
+    /// No power-ups at all.
+    pub fn none() -> Self {
+        PowerUpTable {
+            chances: [0.0; PowerUpKind::ALL.len()],
+        }
+    }
+
+    pub fn chance(&self, kind: PowerUpKind) -> f64 {
+        self.chances[kind.to_i64() as usize]
+    }
+
+    /// `chance` is clamped to 0..=1.
+    pub fn set_chance(&mut self, kind: PowerUpKind, chance: f64) {
+        self.chances[kind.to_i64() as usize] = chance.clamp(0.0, 1.0);
+    }
+
+    /// One roll per destroyed rock, chances are stacked one after another,
+    /// past their sum nothing is dropped.
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<PowerUpKind> {
+        let roll = rng.gen::<f64>();
+
+        let mut total = 0.0;
+        for kind in PowerUpKind::ALL.iter() {
+            total += self.chance(*kind);
+            if roll < total {
+                return Some(*kind);
+            }
+        }
+
+        None
+    }
+
+    /// In key:chance format.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let dict = Dictionary::new();
+        for kind in PowerUpKind::ALL.iter() {
+            dict.insert(kind.key(), self.chance(*kind));
+        }
+
+        dict.into_shared()
+    }
+
+    /// Missing keys keep their default chance.
+    pub fn from_dictionary(dict: &Dictionary) -> Self {
+        let mut table = PowerUpTable::default();
+        for kind in PowerUpKind::ALL.iter() {
+            if let Some(chance) = dict.get(kind.key()).try_to_f64() {
+                table.set_chance(*kind, chance);
+            }
+        }
+
+        table
+    }
+}
+
+/// A power-up lying in `World/PowerUps`, picked up by the first player walking over it.
+/// Only the server decides who picks it up.
+#[derive(NativeClass)]
+#[inherit(Area2D)]
+pub struct PowerUp {
+    /// Index of `PowerUpKind::ALL`
+    #[property]
+    kind: i64,
+    taken: bool,
+}
+
+#[methods]
+#[allow(deprecated)]
+impl PowerUp {
+    fn new(_owner: TRef<Area2D>) -> Self {
+        PowerUp {
+            kind: 0,
+            taken: false,
+        }
+    }
+
+    #[export]
+    fn _ready(&mut self, owner: TRef<Area2D>) {
+        if let Some(kind) = PowerUpKind::from_i64(self.kind) {
+            // `Background` ColorRect
+            let background = owner.get_node("Background").unwrap();
+            let background = unsafe { background.assume_safe() };
+            let background = background.cast::<ColorRect>().unwrap();
+            //
+
+            background.set_frame_color(kind.color());
+
+            // `Letter` Label
+            let letter = owner.get_node("Letter").unwrap();
+            let letter = unsafe { letter.assume_safe() };
+            let letter = letter.cast::<Label>().unwrap();
+            //
+
+            letter.set_text(kind.letter());
+        }
+
+        let status = owner.connect(
+            "body_entered",
+            owner,
+            "_on_body_entered",
+            VariantArray::new_shared(),
+            0,
+        );
+
+        if let Err(e) = status {
+            godot_error!("`PowerUp` => GodotError at `connect` function: {}", e);
+        }
+    }
+
+    #[export]
+    fn _on_body_entered(&mut self, owner: TRef<Area2D>, body: Variant) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if self.taken || !tree.has_network_peer() || !tree.is_network_server() {
+            return;
+        }
+
+        // Body
+        let body = body.try_to_object::<Node>().unwrap();
+        let body = unsafe { body.assume_safe() };
+        //
+
+        if !body.has_method("add_powerup") || body.get("eliminated").to_bool() {
+            return;
+        }
+
+        self.taken = true;
+
+        unsafe {
+            body.call_deferred("add_powerup", &[self.kind.to_variant()]);
+        }
+        body.rpc("add_powerup", &[self.kind.to_variant()]);
+
+        owner.rpc("picked_up", &[]);
+        self.picked_up(owner);
+    }
+
+    /// Server (Sync)
+    #[export(rpc = "puppet")]
+    fn picked_up(&self, owner: TRef<Area2D>) {
+        replay::record_event(owner.as_ref(), "picked_up", &[]);
+
+        owner.queue_free();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const ROLLS: usize = 20_000;
+
+    #[test]
+    fn none_never_drops() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let table = PowerUpTable::none();
+        assert!((0..ROLLS).all(|_| table.roll(&mut rng).is_none()));
+    }
+
+    #[test]
+    fn certain_chance_always_drops() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut table = PowerUpTable::none();
+        table.set_chance(PowerUpKind::Kick, 3.0);
+        assert_eq!(table.chance(PowerUpKind::Kick), 1.0);
+        assert!((0..ROLLS).all(|_| table.roll(&mut rng) == Some(PowerUpKind::Kick)));
+    }
+
+    #[test]
+    fn drops_follow_the_chances() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let table = PowerUpTable::default();
+
+        let mut drops = [0; PowerUpKind::ALL.len()];
+        let mut nothing = 0;
+        for _ in 0..ROLLS {
+            match table.roll(&mut rng) {
+                Some(kind) => drops[kind.to_i64() as usize] += 1,
+                None => nothing += 1,
+            }
+        }
+
+        let mut total = 0.0;
+        for kind in PowerUpKind::ALL.iter() {
+            let rate = drops[kind.to_i64() as usize] as f64 / ROLLS as f64;
+            assert!((rate - table.chance(*kind)).abs() < 0.01, "{:?}", kind);
+            total += table.chance(*kind);
+        }
+        assert!((nothing as f64 / ROLLS as f64 - (1.0 - total)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,139 @@
+use rand::Rng;
+
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+use crate::stats::PlayerStats;
+
+/// Local player profile.
+pub const PROFILE_PATH: &str = "user://profile.cfg";
+
+/// Name of a new profile, the same as the default `Connect/Name`.
+const DEFAULT_NAME: &str = "The Warrior";
+
+/// Player profile of this machine: last used name, preferred color and lifetime stats.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// Identity of the player on leaderboards, survives name changes
+    pub key: String,
+    pub name: String,
+    pub color: Color,
+
+    pub matches_played: i64,
+    pub wins: i64,
+    pub rocks_destroyed: i64,
+    pub stuns_dealt: i64,
+    pub stuns_received: i64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            key: String::new(),
+            name: DEFAULT_NAME.to_string(),
+            color: Color::rgb(1.0, 1.0, 1.0),
+
+            matches_played: 0,
+            wins: 0,
+            rocks_destroyed: 0,
+            stuns_dealt: 0,
+            stuns_received: 0,
+        }
+    }
+}
+
+impl Profile {
+    /// Loading `user://profile.cfg`, a new profile is used if there is none.
+    pub fn load() -> Self {
+        let mut profile = Profile::default();
+        profile.load_file();
+
+        if profile.key.is_empty() {
+            profile.key = generate_key();
+            if let Err(e) = profile.save() {
+                godot_error!("`Profile` => GodotError at `save` function: {}", e);
+            }
+        }
+
+        profile
+    }
+
+    fn load_file(&mut self) {
+        let config = ConfigFile::new();
+        if config.load(PROFILE_PATH).is_err() {
+            return;
+        }
+
+        self.key = config.get_value("player", "key", "").to_string();
+        self.name = config
+            .get_value("player", "name", self.name.as_str())
+            .to_string();
+        self.color = config.get_value("player", "color", self.color).to_color();
+
+        self.matches_played = config
+            .get_value("stats", "matches_played", self.matches_played)
+            .to_i64();
+        self.wins = config.get_value("stats", "wins", self.wins).to_i64();
+        self.rocks_destroyed = config
+            .get_value("stats", "rocks_destroyed", self.rocks_destroyed)
+            .to_i64();
+        self.stuns_dealt = config
+            .get_value("stats", "stuns_dealt", self.stuns_dealt)
+            .to_i64();
+        self.stuns_received = config
+            .get_value("stats", "stuns_received", self.stuns_received)
+            .to_i64();
+    }
+
+    pub fn save(&self) -> Result<(), GodotError> {
+        let config = ConfigFile::new();
+
+        config.set_value("player", "key", self.key.as_str());
+        config.set_value("player", "name", self.name.as_str());
+        config.set_value("player", "color", self.color);
+
+        config.set_value("stats", "matches_played", self.matches_played);
+        config.set_value("stats", "wins", self.wins);
+        config.set_value("stats", "rocks_destroyed", self.rocks_destroyed);
+        config.set_value("stats", "stuns_dealt", self.stuns_dealt);
+        config.set_value("stats", "stuns_received", self.stuns_received);
+
+        config.save(PROFILE_PATH)
+    }
+
+    /// Adding a finished (or interrupted) match to the lifetime stats.
+    pub fn add_match(&mut self, stats: PlayerStats, won: bool) {
+        self.matches_played += 1;
+        if won {
+            self.wins += 1;
+        }
+
+        self.rocks_destroyed += stats.rocks_destroyed;
+        self.stuns_dealt += stats.stuns_dealt;
+        self.stuns_received += stats.stuns_received;
+    }
+
+    /// For the profile screen of the lobby.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let dict = Dictionary::new();
+
+        dict.insert("name", self.name.as_str());
+        dict.insert("color", self.color);
+
+        dict.insert("matches_played", self.matches_played);
+        dict.insert("wins", self.wins);
+        dict.insert("rocks_destroyed", self.rocks_destroyed);
+        dict.insert("stuns_dealt", self.stuns_dealt);
+        dict.insert("stuns_received", self.stuns_received);
+
+        dict.into_shared()
+    }
+}
+
+/// 128 random bits as hex, generated once per profile.
+fn generate_key() -> String {
+    let mut rng = rand::thread_rng();
+    (0..16)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
@@ -0,0 +1,346 @@
+use crate::map::MapLayout;
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Directory where replay files are stored.
+pub const REPLAY_DIR: &str = "user://replays";
+
+/// Replay file extension.
+pub const REPLAY_EXTENSION: &str = "replay";
+
+/// Bumped every time the replay file layout changes.
+pub const REPLAY_FORMAT_VERSION: i64 = 1;
+
+/// Writes a match into a replay file.
+///
+/// The file is a JSON document per line:
+/// the first line is the header (version, seed, map, layout, roster, lives),
+/// every next line is an event `{ "t": msec, "node": path, "event": name, "args": [...] }`.
+/// `node` is the path of the node relative to `World`, `.` for the rounds of the match:
+/// `round_started(round, seed, layout)` and `round_finished(winner, text)`.
+pub struct ReplayRecorder {
+    file: Ref<File>,
+    path: String,
+    started_at_msec: i64,
+}
+
+impl ReplayRecorder {
+    /// Creating a new replay file and writing its header.
+    /// `lives`: lives of every player at the start of a round, 0 without lives.
+    pub fn start(
+        seed: i64,
+        map: &str,
+        layout: Option<&MapLayout>,
+        roster: &Dictionary,
+        lives: i64,
+    ) -> Result<Self, GodotError> {
+        let dir = Directory::new();
+        if !dir.dir_exists(REPLAY_DIR) {
+            dir.make_dir_recursive(REPLAY_DIR)?;
+        }
+
+        let os = OS::godot_singleton();
+        let started_at = os.get_unix_time();
+        let path = format!(
+            "{}/{:012}_{}.{}",
+            REPLAY_DIR, started_at, seed, REPLAY_EXTENSION
+        );
+
+        let file = File::new();
+        file.open(path.as_str(), File::WRITE)?;
+
+        // Header
+        let header = Dictionary::new();
+        header.insert("format", "multiplayer_bomber_replay");
+        header.insert("version", REPLAY_FORMAT_VERSION);
+        header.insert("started_at", started_at);
+        header.insert("seed", seed);
+        header.insert("map", map);
+        if let Some(layout) = layout {
+            header.insert("layout", layout.to_dictionary());
+        }
+        header.insert("roster", roster_to_json(roster));
+        header.insert("lives", lives);
+        //
+
+        file.store_line(JSON::godot_singleton().print(header.into_shared(), "", false));
+
+        godot_print!("recording replay to {}", path);
+
+        Ok(ReplayRecorder {
+            file: file.into_shared(),
+            path,
+            started_at_msec: os.get_ticks_msec(),
+        })
+    }
+
+    /// Appending a single gameplay event or state update.
+    pub fn record(&self, node: &str, event: &str, args: &VariantArray) {
+        let json_args = VariantArray::new();
+        for arg in args.iter() {
+            json_args.push(to_json_value(&arg));
+        }
+
+        let line = Dictionary::new();
+        line.insert(
+            "t",
+            OS::godot_singleton().get_ticks_msec() - self.started_at_msec,
+        );
+        line.insert("node", node);
+        line.insert("event", event);
+        line.insert("args", json_args.into_shared());
+
+        let file = unsafe { self.file.assume_safe() };
+        file.store_line(JSON::godot_singleton().print(line.into_shared(), "", false));
+    }
+
+    /// Closing the replay file.
+    pub fn finish(self) {
+        let file = unsafe { self.file.assume_safe() };
+        file.close();
+
+        godot_print!("replay saved: {}", self.path);
+    }
+}
+
+/// Replay file header.
+pub struct ReplayHeader {
+    pub version: i64,
+    pub seed: i64,
+    pub map: String,
+    /// The whole map of the first round, `None` for the one baked into the world scene
+    pub layout: Option<MapLayout>,
+    /// `(id, name)` pairs
+    pub roster: Vec<(i64, String)>,
+    /// Lives of every player at the start of a round, 0 without lives
+    pub lives: i64,
+}
+
+/// A single recorded event.
+pub struct ReplayEvent {
+    /// Milliseconds since the start of the match
+    pub t: i64,
+    /// Node path relative to `World`
+    pub node: String,
+    pub event: String,
+    pub args: VariantArray,
+}
+
+/// Fully loaded replay file.
+pub struct Replay {
+    pub header: ReplayHeader,
+    /// Sorted by time
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    /// Duration of the replay in milliseconds.
+    pub fn duration(&self) -> i64 {
+        self.events.last().map(|e| e.t).unwrap_or(0)
+    }
+}
+
+/// Loading only the header of a replay file.
+pub fn load_header(path: &str) -> Result<ReplayHeader, String> {
+    let file = File::new();
+    file.open(path, File::READ)
+        .map_err(|e| format!("can't open {}: {}", path, e))?;
+
+    let header = parse_header(&file.get_line().to_string());
+    file.close();
+
+    header
+}
+
+/// Loading a replay file with all its events.
+pub fn load_replay(path: &str) -> Result<Replay, String> {
+    let file = File::new();
+    file.open(path, File::READ)
+        .map_err(|e| format!("can't open {}: {}", path, e))?;
+
+    let header = parse_header(&file.get_line().to_string())?;
+
+    let mut events = Vec::new();
+    while !file.eof_reached() {
+        let line = file.get_line().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        let event = parse_json_object(&line)?;
+        events.push(ReplayEvent {
+            t: event.get("t").to_i64(),
+            node: event.get("node").to_string(),
+            event: event.get("event").to_string(),
+            args: event.get("args").to_array(),
+        });
+    }
+    file.close();
+
+    // Events are written in order, but it's cheap to be sure.
+    events.sort_by_key(|e| e.t);
+
+    Ok(Replay { header, events })
+}
+
+/// `Vector2` stored as `[x, y]` by the recorder.
+pub fn from_json_vector2(value: &Variant) -> Vector2 {
+    let array = value.to_array();
+    Vector2::new(array.get(0).to_f64() as f32, array.get(1).to_f64() as f32)
+}
+
+fn parse_header(line: &str) -> Result<ReplayHeader, String> {
+    let header = parse_json_object(line)?;
+
+    if header.get("format").to_string() != "multiplayer_bomber_replay" {
+        return Err("not a replay file".to_string());
+    }
+
+    let version = header.get("version").to_i64();
+    if version > REPLAY_FORMAT_VERSION {
+        return Err(format!(
+            "replay version {} is newer than supported {}",
+            version, REPLAY_FORMAT_VERSION
+        ));
+    }
+
+    let mut roster: Vec<(i64, String)> = header
+        .get("roster")
+        .to_dictionary()
+        .iter()
+        .map(|(id, name)| (id.to_string().parse().unwrap_or(0), name.to_string()))
+        .collect();
+    roster.sort_by_key(|(id, _)| *id);
+
+    let layout = header.get("layout").to_dictionary();
+    let layout = if layout.is_empty() {
+        None
+    } else {
+        Some(MapLayout::from_dictionary(&layout).map_err(|e| format!("map: {}", e))?)
+    };
+
+    Ok(ReplayHeader {
+        version,
+        seed: header.get("seed").to_i64(),
+        map: header.get("map").to_string(),
+        layout,
+        roster,
+        lives: header.get("lives").to_i64(),
+    })
+}
+
+fn parse_json_object(line: &str) -> Result<Dictionary, String> {
+    let parsed = JSON::godot_singleton()
+        .parse(line)
+        .ok_or_else(|| "JSON parser failure".to_string())?;
+    let parsed = unsafe { parsed.assume_safe() };
+
+    let result = parsed.result();
+    if result.get_type() != VariantType::Dictionary {
+        return Err(format!(
+            "invalid JSON at line {}: {}",
+            parsed.error_line(),
+            parsed.error_string()
+        ));
+    }
+
+    Ok(result.to_dictionary())
+}
+
+/// Removing the oldest replays, so only `keep` of them are left.
+pub fn prune_replays(keep: i64) {
+    let mut replays = list_replays();
+
+    let keep = keep.max(0) as usize;
+    if replays.len() <= keep {
+        return;
+    }
+
+    // File names start with a zero padded timestamp, so the oldest are first.
+    let remove_count = replays.len() - keep;
+    let dir = Directory::new();
+    for path in replays.drain(..remove_count) {
+        if let Err(e) = dir.remove(path.as_str()) {
+            godot_error!(
+                "`Replay` => GodotError at `remove` function: {} ({})",
+                e,
+                path
+            );
+        }
+    }
+}
+
+/// Paths of all replay files, the oldest first.
+pub fn list_replays() -> Vec<String> {
+    let mut replays = Vec::new();
+
+    let dir = Directory::new();
+    if dir.open(REPLAY_DIR).is_err() {
+        return replays;
+    }
+
+    if let Err(e) = dir.list_dir_begin(true, true) {
+        godot_error!("`Replay` => GodotError at `list_dir_begin` function: {}", e);
+        return replays;
+    }
+
+    loop {
+        let file_name = dir.get_next().to_string();
+        if file_name.is_empty() {
+            break;
+        }
+
+        if !dir.current_is_dir() && file_name.ends_with(&format!(".{}", REPLAY_EXTENSION)) {
+            replays.push(format!("{}/{}", REPLAY_DIR, file_name));
+        }
+    }
+    dir.list_dir_end();
+
+    replays.sort();
+    replays
+}
+
+/// Records an event through the `gamestate` singleton, if it's recording.
+///
+/// `node` is the node which received the event, it's stored relative to `World`.
+pub fn record_event(node: &Node, event: &str, args: &[Variant]) {
+    let gamestate = unsafe { utils::get_gamestate_singleton(node) };
+    let world = unsafe { utils::get_world(node) };
+
+    let func_args = VariantArray::new();
+    func_args.push(unsafe { world.get_path_to(node.assume_shared()) });
+    func_args.push(event);
+    func_args.push(
+        args.iter()
+            .cloned()
+            .collect::<VariantArray<Unique>>()
+            .into_shared(),
+    );
+    unsafe {
+        gamestate.callv("record_event", func_args.into_shared());
+    }
+}
+
+/// JSON doesn't know about `Vector2`, storing it as `[x, y]`.
+fn to_json_value(value: &Variant) -> Variant {
+    match value.get_type() {
+        VariantType::Vector2 => {
+            let v = value.to_vector2();
+            let array = VariantArray::new();
+            array.push(v.x);
+            array.push(v.y);
+            array.into_shared().to_variant()
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Roster as `{ "id": "name" }`, JSON object keys are always strings.
+fn roster_to_json(roster: &Dictionary) -> Dictionary {
+    let json = Dictionary::new();
+    for (id, name) in roster.iter() {
+        json.insert(id.to_i64().to_string(), name.to_string());
+    }
+    json.into_shared()
+}
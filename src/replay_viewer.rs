@@ -0,0 +1,996 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::arena;
+use crate::bomb::BombKind;
+use crate::grid::{self, Cell};
+use crate::map::MapLayout;
+use crate::powerup::PowerUpKind;
+use crate::replay::{self, Replay, ReplayEvent};
+use crate::utils;
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// A keyframe is taken every 5 seconds of the replay.
+const KEYFRAME_INTERVAL_MSEC: i64 = 5000;
+
+/// A bomb is freed this long after its explosion (`BLAST_DURATION` of `Bomb`).
+const BLAST_DURATION_MSEC: i64 = 600;
+
+/// Available playback speeds.
+const SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Free camera speed in pixels per second.
+const CAMERA_SPEED: f32 = 400.0;
+const CAMERA_ZOOM_STEP: f32 = 0.1;
+
+/// A round of the replay, the world is rebuilt at its start.
+struct ReplayRound {
+    /// `None` for the map baked into the world scene
+    layout: Option<MapLayout>,
+    /// Rock node name => position
+    rocks: HashMap<String, Vector2>,
+}
+
+/// State of the match at some point of time, used for seeking.
+struct Keyframe {
+    t: i64,
+    /// Index of the first event after the keyframe
+    event_index: usize,
+    /// Index of `ReplayViewer::rounds`
+    round: usize,
+    /// Player node path => (position, animation)
+    players: HashMap<String, (Vector2, String)>,
+    /// Player id => score
+    scores: HashMap<String, i64>,
+    /// Rock node paths
+    destroyed_rocks: HashSet<String>,
+    /// Tiles of the closing arena turned into walls
+    closed_cells: HashSet<Cell>,
+    /// Player node path => power-ups picked up in the round
+    powerups: HashMap<String, Vec<i64>>,
+    /// Rock name => power-up it left behind, still lying in `PowerUps`
+    dropped_powerups: HashMap<String, i64>,
+    /// Result of the round, shown until the next one starts
+    result: Option<String>,
+    /// Player node path => lives left, empty without lives
+    lives: HashMap<String, i64>,
+}
+
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct ReplayViewer {
+    #[property]
+    preload_rock: Ref<PackedScene>,
+    #[property]
+    preload_powerup: Ref<PackedScene>,
+
+    replay: Option<Replay>,
+    keyframes: Vec<Keyframe>,
+    /// The world as it was created, then one more for every `round_started` event.
+    rounds: Vec<ReplayRound>,
+    /// Every tile closed during the hurry-up phase with its tile before, put back when seeking back.
+    arena_cells: Vec<(Cell, i64)>,
+
+    /// Current replay time in milliseconds
+    current_msec: f64,
+    /// Index of the next event to apply
+    next_event: usize,
+    playing: bool,
+    speed: f64,
+
+    /// Used by the free camera, it should move even when the replay is paused.
+    last_ticks_usec: i64,
+
+    /// Button
+    play: Option<Ref<Node>>,
+    /// OptionButton
+    speed_option: Option<Ref<Node>>,
+    /// HSlider
+    timeline: Option<Ref<Node>>,
+    /// Label
+    time_label: Option<Ref<Node>>,
+    /// Button
+    exit: Option<Ref<Node>>,
+}
+
+#[methods]
+#[allow(deprecated)]
+impl ReplayViewer {
+    fn new(_owner: TRef<Node>) -> Self {
+        ReplayViewer {
+            preload_rock: PackedScene::new().into_shared(),
+            preload_powerup: PackedScene::new().into_shared(),
+
+            replay: None,
+            keyframes: Vec::new(),
+            rounds: Vec::new(),
+            arena_cells: Vec::new(),
+
+            current_msec: 0.0,
+            next_event: 0,
+            playing: false,
+            speed: 1.0,
+
+            last_ticks_usec: 0,
+
+            play: None,
+            speed_option: None,
+            timeline: None,
+            time_label: None,
+            exit: None,
+        }
+    }
+
+    #[export]
+    fn _ready(&mut self, owner: TRef<Node>) {
+        self.preload_instances();
+        self.update_child_nodes(owner);
+
+        let speed_option = self.get_speed_option();
+        for speed in SPEEDS.iter() {
+            speed_option.add_item(format!("{}x", speed), -1);
+        }
+        speed_option.select(SPEEDS.iter().position(|s| *s == 1.0).unwrap() as i64);
+
+        if let Err(e) = self.connect_signals(owner) {
+            godot_error!(
+                "`ReplayViewer` => GodotError at `connect_signals` function: {}",
+                e
+            );
+        }
+
+        self.last_ticks_usec = OS::godot_singleton().get_ticks_usec();
+        owner.set_process(true);
+        owner.set_process_unhandled_input(true);
+    }
+
+    #[export]
+    fn _exit_tree(&self, _owner: TRef<Node>) {
+        Engine::godot_singleton().set_time_scale(1.0);
+    }
+
+    /// Loading the replay file, the world must be already created.
+    #[export]
+    fn load(&mut self, owner: TRef<Node>, path: String) -> bool {
+        let replay = match replay::load_replay(&path) {
+            Ok(replay) => replay,
+            Err(e) => {
+                godot_error!("`ReplayViewer` => can't load replay: {}", e);
+                return false;
+            }
+        };
+
+        godot_print!(
+            "replay loaded (version: {}, seed: {}, events: {})",
+            replay.header.version,
+            replay.header.seed,
+            replay.events.len()
+        );
+
+        // Rocks are restored from here when seeking back
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let tilemap = grid::get_tilemap(world);
+        let mut rocks = HashMap::new();
+        for rock in self.get_rocks(owner).get_children().iter() {
+            let rock = rock.try_to_object::<Node2D>().unwrap();
+            let rock = unsafe { rock.assume_safe() };
+            rocks.insert(rock.name().to_string(), rock.position());
+        }
+
+        self.rounds.clear();
+        self.rounds.push(ReplayRound {
+            layout: replay.header.layout.clone(),
+            rocks: rocks.clone(),
+        });
+        for event in replay.events.iter() {
+            if event.event != "round_started" {
+                continue;
+            }
+
+            // Rocks of map files are named by index, like `MapLayout::apply` does
+            let layout = event.args.get(2).to_dictionary();
+            let round = match MapLayout::from_dictionary(&layout) {
+                Ok(layout) => ReplayRound {
+                    rocks: layout
+                        .rocks
+                        .iter()
+                        .enumerate()
+                        .map(|(index, cell)| {
+                            (format!("Rock{}", index), grid::cell_center(tilemap, *cell))
+                        })
+                        .collect(),
+                    layout: Some(layout),
+                },
+                Err(_) => ReplayRound {
+                    layout: None,
+                    rocks: rocks.clone(),
+                },
+            };
+            self.rounds.push(round);
+        }
+
+        self.keyframes = Self::build_keyframes(&replay);
+        self.arena_cells = replay
+            .events
+            .iter()
+            .filter(|event| event.event == "close_cell")
+            .map(event_cell)
+            .map(|cell| (cell, tilemap.get_cell(cell.0 as i64, cell.1 as i64)))
+            .collect();
+
+        let timeline = self.get_timeline();
+        timeline.set_max(replay.duration() as f64);
+        timeline.set_step(1.0);
+
+        self.replay = Some(replay);
+        self.current_msec = 0.0;
+        self.next_event = 0;
+        self.set_playing(true);
+
+        true
+    }
+
+    #[export]
+    fn _process(&mut self, owner: TRef<Node>, delta: f64) {
+        // Camera uses real time, `delta` is scaled by the playback speed.
+        let ticks_usec = OS::godot_singleton().get_ticks_usec();
+        let real_delta = (ticks_usec - self.last_ticks_usec) as f32 / 1_000_000.0;
+        self.last_ticks_usec = ticks_usec;
+        self.move_camera(owner, real_delta);
+
+        if !self.playing {
+            return;
+        }
+
+        let duration = match &self.replay {
+            Some(replay) => replay.duration(),
+            None => return,
+        };
+
+        self.current_msec += delta * 1000.0;
+        self.apply_events_until(owner, self.current_msec as i64, false);
+
+        if self.current_msec as i64 >= duration {
+            self.current_msec = duration as f64;
+            self.set_playing(false);
+        }
+
+        self.update_timeline();
+    }
+
+    #[export]
+    fn _unhandled_input(&self, owner: TRef<Node>, event: Ref<InputEvent>) {
+        let event = unsafe { event.assume_safe() };
+
+        if let Some(event) = event.cast::<InputEventMouseButton>() {
+            if !event.is_pressed() {
+                return;
+            }
+
+            let step = match event.button_index() {
+                GlobalConstants::BUTTON_WHEEL_UP => -CAMERA_ZOOM_STEP,
+                GlobalConstants::BUTTON_WHEEL_DOWN => CAMERA_ZOOM_STEP,
+                _ => return,
+            };
+
+            let camera = self.get_camera(owner);
+            let zoom = (camera.zoom().x + step).clamp(0.2, 3.0);
+            camera.set_zoom(Vector2::new(zoom, zoom));
+        }
+    }
+
+    #[export]
+    fn _on_play_pressed(&mut self, owner: TRef<Node>) {
+        let finished = match &self.replay {
+            Some(replay) => self.current_msec as i64 >= replay.duration(),
+            None => return,
+        };
+
+        if finished {
+            // Play again from the start
+            self.seek_to(owner, 0);
+        }
+
+        self.set_playing(!self.playing);
+    }
+
+    #[export]
+    fn _on_speed_selected(&mut self, _owner: TRef<Node>, index: i64) {
+        self.speed = SPEEDS[index as usize];
+        self.apply_time_scale();
+    }
+
+    #[export]
+    fn _on_timeline_value_changed(&mut self, owner: TRef<Node>, value: f64) {
+        self.seek_to(owner, value as i64);
+    }
+
+    #[export]
+    fn _on_exit_pressed(&self, owner: TRef<Node>) {
+        unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("end_game", VariantArray::new_shared());
+        }
+    }
+
+    /// Seeking: restoring the nearest keyframe before `msec`
+    /// and fast-forwarding the events after it.
+    fn seek_to(&mut self, owner: TRef<Node>, msec: i64) {
+        let keyframe_index = match self.keyframes.iter().rposition(|k| k.t <= msec) {
+            Some(index) => index,
+            None => return,
+        };
+
+        self.restore_keyframe(owner, keyframe_index);
+
+        self.next_event = self.keyframes[keyframe_index].event_index;
+        self.current_msec = msec as f64;
+        self.apply_events_until(owner, msec, true);
+
+        self.update_timeline();
+    }
+
+    fn restore_keyframe(&self, owner: TRef<Node>, keyframe_index: usize) {
+        let keyframe = &self.keyframes[keyframe_index];
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        // Bombs are the only `Area2D` children of `World`.
+        // Removing them right away, bombs with the same names can be created during this frame.
+        for child in world.get_children().iter() {
+            let child = child.try_to_object::<Node>().unwrap();
+            let child = unsafe { child.assume_safe() };
+            if child.cast::<Area2D>().is_some() {
+                world.remove_child(child);
+                child.queue_free();
+            }
+        }
+
+        // A new round on a map file, tiles and rocks are put back by `MapLayout::apply`
+        let round = &self.rounds[keyframe.round];
+        if let Some(layout) = &round.layout {
+            layout.apply(world);
+        }
+
+        // Players
+        for (node, (position, anim)) in keyframe.players.iter() {
+            if let Some(player) = world.get_node(node.as_str()) {
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(*position);
+                    func_args.push(anim.as_str());
+                    player.assume_safe().callv("update_network", func_args);
+                }
+            }
+        }
+
+        // Power-ups and lives of players
+        let players = unsafe { world.get_node("Players").unwrap().assume_safe() };
+        for player in players.get_children().iter() {
+            let player = unsafe { player.try_to_object::<Node>().unwrap().assume_safe() };
+            let path = format!("Players/{}", player.name());
+
+            if let Some(lives) = keyframe.lives.get(&path) {
+                let func_args = VariantArray::new_shared();
+                unsafe {
+                    func_args.push(*lives);
+                    player.callv("restore_lives", func_args);
+                }
+            }
+
+            let kinds = keyframe
+                .powerups
+                .get(&path)
+                .map_or_else(Vec::new, |kinds| kinds.clone());
+
+            let func_args = VariantArray::new_shared();
+            unsafe {
+                func_args.push(
+                    kinds
+                        .into_iter()
+                        .collect::<VariantArray<Unique>>()
+                        .into_shared(),
+                );
+                player.callv("set_powerups", func_args);
+            }
+        }
+
+        // Score
+        let score = unsafe { world.get_node("Score").unwrap().assume_safe() };
+        for (for_who, value) in keyframe.scores.iter() {
+            let func_args = VariantArray::new_shared();
+            unsafe {
+                func_args.push(for_who.as_str());
+                func_args.push(*value);
+                score.callv("set_score", func_args);
+            }
+        }
+
+        // Rocks
+        let rocks = self.get_rocks(owner);
+        let rock_packed_scene = unsafe { self.preload_rock.assume_safe() };
+        for (name, position) in round.rocks.iter() {
+            let destroyed = keyframe
+                .destroyed_rocks
+                .contains(&format!("Rocks/{}", name));
+
+            match rocks.get_node(name.as_str()) {
+                Some(rock) if destroyed => {
+                    let rock = unsafe { rock.assume_safe() };
+                    rocks.remove_child(rock);
+                    rock.queue_free();
+                }
+                Some(rock) => {
+                    // The rock may be in the middle of its `explode` animation
+                    let rock = unsafe { rock.assume_safe() };
+                    unsafe {
+                        rock.get_node("AnimationPlayer")
+                            .unwrap()
+                            .assume_safe()
+                            .cast::<AnimationPlayer>()
+                            .unwrap()
+                            .stop(true);
+                        rock.get_node("Sprite")
+                            .unwrap()
+                            .assume_safe()
+                            .cast::<Sprite>()
+                            .unwrap()
+                            .show();
+                    }
+                }
+                // Freed itself after its explosion
+                None if destroyed => {}
+                None => {
+                    let rock = rock_packed_scene
+                        .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+                        .unwrap();
+                    let rock = unsafe { rock.assume_safe() };
+                    let rock = rock.cast::<KinematicBody2D>().unwrap();
+
+                    rock.set_name(name.as_str());
+                    rock.set_position(*position);
+                    rocks.add_child(rock, false);
+                }
+            }
+        }
+
+        // Power-ups lying around, removed right away like bombs
+        let powerups = unsafe { world.get_node("PowerUps").unwrap().assume_safe() };
+        for powerup in powerups.get_children().iter() {
+            let powerup = unsafe { powerup.try_to_object::<Node>().unwrap().assume_safe() };
+            powerups.remove_child(powerup);
+            powerup.queue_free();
+        }
+        let powerup_packed_scene = unsafe { self.preload_powerup.assume_safe() };
+        for (name, kind) in keyframe.dropped_powerups.iter() {
+            let position = match round.rocks.get(name) {
+                Some(position) => *position,
+                None => continue,
+            };
+
+            let powerup = powerup_packed_scene
+                .instance(PackedScene::GEN_EDIT_STATE_DISABLED)
+                .unwrap();
+            let powerup = unsafe { powerup.assume_safe() };
+            let powerup = powerup.cast::<Area2D>().unwrap();
+
+            powerup.set_name(name.as_str());
+            powerup.set_position(position);
+            powerup.set("kind", *kind);
+            powerups.add_child(powerup, false);
+        }
+
+        // Tiles of the closing arena, after the rocks, which are removed from closed tiles.
+        // Only the baked map needs them back, map files were applied again.
+        let tilemap = grid::get_tilemap(world);
+        let arena_cells = if round.layout.is_none() {
+            self.arena_cells.as_slice()
+        } else {
+            &[]
+        };
+        for (cell, tile) in arena_cells.iter() {
+            tilemap.set_cell(
+                cell.0 as i64,
+                cell.1 as i64,
+                *tile,
+                false,
+                false,
+                false,
+                Vector2::zero(),
+            );
+        }
+        arena::set_closed_rocks(&rocks, 0);
+        for cell in keyframe.closed_cells.iter() {
+            arena::close_cell(world, *cell);
+        }
+
+        // Result of the round
+        unsafe {
+            score.callv("reset_round", VariantArray::new_shared());
+        }
+        if let Some(text) = &keyframe.result {
+            Self::show_result(score, text);
+        }
+    }
+
+    /// Applying events up to `msec`.
+    /// `seeking` skips visuals which would have already finished by `msec`.
+    fn apply_events_until(&mut self, owner: TRef<Node>, msec: i64, seeking: bool) {
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return,
+        };
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        while let Some(event) = replay.events.get(self.next_event) {
+            if event.t > msec {
+                break;
+            }
+            self.next_event += 1;
+
+            let node = match world.get_node(event.node.as_str()) {
+                Some(node) => unsafe { node.assume_safe() },
+                None => continue,
+            };
+
+            match event.event.as_str() {
+                // Played from the keyframe taken right after it
+                "round_started" => {
+                    let index = self.next_event;
+                    if let Some(keyframe_index) =
+                        self.keyframes.iter().position(|k| k.event_index == index)
+                    {
+                        self.restore_keyframe(owner, keyframe_index);
+                    }
+                }
+                "round_finished" => {
+                    let score = unsafe { world.get_node("Score").unwrap().assume_safe() };
+                    Self::show_result(score, &event.args.get(1).to_string());
+                }
+                "update_network" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(replay::from_json_vector2(&event.args.get(0)));
+                        func_args.push(event.args.get(1));
+                        node.callv("update_network", func_args);
+                    }
+                }
+                "setup_bomb" => {
+                    let fuse_msec = event.args.get(3).to_i64();
+                    let kind = event.args.get(4).to_i64();
+
+                    // Catching up with the fuse, bombs without one wait for their `trigger`
+                    let age = if seeking { msec - event.t } else { 0 };
+                    if BombKind::from_i64(kind).has_fuse() && age >= fuse_msec + BLAST_DURATION_MSEC
+                    {
+                        continue;
+                    }
+
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0));
+                        func_args.push(replay::from_json_vector2(&event.args.get(1)));
+                        func_args.push(event.args.get(2).to_i64());
+                        func_args.push((fuse_msec - age).max(0));
+                        func_args.push(kind);
+                        func_args.push(event.args.get(5).to_i64());
+                        node.callv("place_bomb", func_args);
+                    }
+                }
+                // The animation is already in `update_network`, lives are counted by players
+                "stun" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0));
+                        node.callv("stun", func_args);
+                    }
+                }
+                "crush" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(replay::from_json_vector2(&event.args.get(0)));
+                        node.callv("crush", func_args);
+                    }
+                }
+                // Power-ups, from the rock to the player
+                "drop_powerup" | "add_powerup" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0).to_i64());
+                        node.callv(event.event.as_str(), func_args);
+                    }
+                }
+                "picked_up" | "lose_shield" => unsafe {
+                    node.callv(event.event.as_str(), VariantArray::new_shared());
+                },
+                // Kicked and thrown bombs
+                "move_to" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(replay::from_json_vector2(&event.args.get(0)));
+                        func_args.push(event.args.get(1).to_f64());
+                        node.callv("move_to", func_args);
+                    }
+                }
+                // Bombs held by players
+                "pick_up" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0));
+                        node.callv("pick_up", func_args);
+                    }
+                }
+                "put_down" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(replay::from_json_vector2(&event.args.get(0)));
+                        node.callv("put_down", func_args);
+                    }
+                }
+                // Remote bombs and mines
+                "trigger" => unsafe {
+                    node.callv("trigger", VariantArray::new_shared());
+                },
+                // Chain reactions
+                "detonate" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0).to_i64());
+                        func_args.push(event.args.get(1));
+                        node.callv("detonate", func_args);
+                    }
+                }
+                "exploded" => {
+                    if node.has_method("do_explosion") {
+                        if seeking {
+                            node.queue_free();
+                        } else {
+                            unsafe {
+                                node.callv("do_explosion", VariantArray::new_shared());
+                            }
+                        }
+                    }
+                }
+                // Hurry-up phase
+                "close_cell" => arena::close_cell(world, event_cell(event)),
+                "increase_score" => {
+                    let func_args = VariantArray::new_shared();
+                    unsafe {
+                        func_args.push(event.args.get(0));
+                        node.callv("increase_score", func_args);
+                    }
+                }
+                _ => godot_warn!("`ReplayViewer` => unknown event `{}`", event.event),
+            }
+        }
+    }
+
+    /// Walking through all events once and taking a snapshot every `KEYFRAME_INTERVAL_MSEC`.
+    fn build_keyframes(replay: &Replay) -> Vec<Keyframe> {
+        let mut keyframes = Vec::new();
+
+        let mut players = HashMap::new();
+        let mut scores: HashMap<String, i64> = HashMap::new();
+        let mut destroyed_rocks = HashSet::new();
+        let mut closed_cells = HashSet::new();
+        let mut powerups: HashMap<String, Vec<i64>> = HashMap::new();
+        let mut dropped_powerups = HashMap::new();
+        let mut result = None;
+        let mut round = 0;
+
+        // Every round starts with all lives
+        let mut full_lives = HashMap::new();
+        for (id, _) in replay.header.roster.iter() {
+            scores.insert(id.to_string(), 0);
+            if replay.header.lives > 0 {
+                full_lives.insert(format!("Players/{}", id), replay.header.lives);
+            }
+        }
+        let mut lives = full_lives.clone();
+
+        let mut next_keyframe_t = 0;
+        for (index, event) in replay.events.iter().enumerate() {
+            while event.t >= next_keyframe_t {
+                keyframes.push(Keyframe {
+                    t: next_keyframe_t,
+                    event_index: index,
+                    round,
+                    players: players.clone(),
+                    scores: scores.clone(),
+                    destroyed_rocks: destroyed_rocks.clone(),
+                    closed_cells: closed_cells.clone(),
+                    powerups: powerups.clone(),
+                    dropped_powerups: dropped_powerups.clone(),
+                    result: result.clone(),
+                    lives: lives.clone(),
+                });
+                next_keyframe_t += KEYFRAME_INTERVAL_MSEC;
+            }
+
+            match event.event.as_str() {
+                // Every round starts in a new world
+                "round_started" => {
+                    round += 1;
+                    destroyed_rocks.clear();
+                    closed_cells.clear();
+                    powerups.clear();
+                    dropped_powerups.clear();
+                    result = None;
+                    lives = full_lives.clone();
+                    for score in scores.values_mut() {
+                        *score = 0;
+                    }
+
+                    keyframes.push(Keyframe {
+                        t: event.t,
+                        event_index: index + 1,
+                        round,
+                        players: players.clone(),
+                        scores: scores.clone(),
+                        destroyed_rocks: destroyed_rocks.clone(),
+                        closed_cells: closed_cells.clone(),
+                        powerups: powerups.clone(),
+                        dropped_powerups: dropped_powerups.clone(),
+                        result: result.clone(),
+                        lives: lives.clone(),
+                    });
+                }
+                "round_finished" => {
+                    result = Some(event.args.get(1).to_string());
+                }
+                "update_network" => {
+                    players.insert(
+                        event.node.clone(),
+                        (
+                            replay::from_json_vector2(&event.args.get(0)),
+                            event.args.get(1).to_string(),
+                        ),
+                    );
+                }
+                "exploded" if event.node.starts_with("Rocks/") => {
+                    destroyed_rocks.insert(event.node.clone());
+                }
+                "close_cell" => {
+                    closed_cells.insert(event_cell(event));
+                }
+                "stun" => {
+                    if let Some(lives) = lives.get_mut(&event.node) {
+                        *lives = (*lives - 1).max(0);
+                    }
+                }
+                "crush" => {
+                    if let Some(lives) = lives.get_mut(&event.node) {
+                        *lives = 0;
+                    }
+                }
+                "add_powerup" => {
+                    powerups
+                        .entry(event.node.clone())
+                        .or_default()
+                        .push(event.args.get(0).to_i64());
+                }
+                "lose_shield" => {
+                    let shield = PowerUpKind::Shield.to_i64();
+                    if let Some(kinds) = powerups.get_mut(&event.node) {
+                        kinds.retain(|kind| *kind != shield);
+                    }
+                }
+                "drop_powerup" => {
+                    dropped_powerups.insert(
+                        event.node.trim_start_matches("Rocks/").to_string(),
+                        event.args.get(0).to_i64(),
+                    );
+                }
+                "picked_up" => {
+                    dropped_powerups.remove(event.node.trim_start_matches("PowerUps/"));
+                }
+                "increase_score" => {
+                    *scores.entry(event.args.get(0).to_string()).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+
+        if keyframes.is_empty() {
+            keyframes.push(Keyframe {
+                t: 0,
+                event_index: 0,
+                round,
+                players,
+                scores,
+                destroyed_rocks,
+                closed_cells,
+                powerups,
+                dropped_powerups,
+                result,
+                lives,
+            });
+        }
+
+        keyframes
+    }
+
+    /// The recorded result, without the buttons of the match.
+    fn show_result(score: TRef<Node>, text: &str) {
+        let func_args = VariantArray::new_shared();
+        unsafe {
+            func_args.push(text);
+            func_args.push(false);
+            func_args.push(false);
+            score.callv("show_result", func_args);
+        }
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        self.apply_time_scale();
+
+        self.get_play()
+            .set_text(if playing { "Pause" } else { "Play" });
+    }
+
+    /// Bomb and rock animations should follow the playback speed too.
+    fn apply_time_scale(&self) {
+        let time_scale = if self.playing { self.speed } else { 0.0 };
+        Engine::godot_singleton().set_time_scale(time_scale);
+    }
+
+    fn update_timeline(&self) {
+        let timeline = self.get_timeline();
+        let time_label = self.get_time_label();
+
+        // Not seeking to where we already are
+        timeline.set_block_signals(true);
+        timeline.set_value(self.current_msec);
+        timeline.set_block_signals(false);
+
+        let current = self.current_msec as i64 / 1000;
+        let duration = timeline.max() as i64 / 1000;
+        time_label.set_text(format!(
+            "{}:{:02} / {}:{:02}",
+            current / 60,
+            current % 60,
+            duration / 60,
+            duration % 60
+        ));
+    }
+
+    fn move_camera(&self, owner: TRef<Node>, real_delta: f32) {
+        let mut motion = Vector2::zero();
+
+        let input = Input::godot_singleton();
+        if Input::is_action_pressed(input, "ui_left") {
+            motion.x -= 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_right") {
+            motion.x += 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_up") {
+            motion.y -= 1.0;
+        }
+        if Input::is_action_pressed(input, "ui_down") {
+            motion.y += 1.0;
+        }
+
+        if motion != Vector2::zero() {
+            let camera = self.get_camera(owner);
+            camera.set_position(camera.position() + motion * CAMERA_SPEED * real_delta);
+        }
+    }
+
+    fn update_child_nodes(&mut self, owner: TRef<Node>) {
+        self.play = owner.get_node("HUD/Controls/Play");
+        self.speed_option = owner.get_node("HUD/Controls/Speed");
+        self.timeline = owner.get_node("HUD/Controls/Timeline");
+        self.time_label = owner.get_node("HUD/Controls/Time");
+        self.exit = owner.get_node("HUD/Controls/Exit");
+    }
+
+    fn connect_signals(&self, owner: TRef<Node>) -> Result<(), GodotError> {
+        self.get_play().connect(
+            "pressed",
+            owner,
+            "_on_play_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        self.get_speed_option().connect(
+            "item_selected",
+            owner,
+            "_on_speed_selected",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        self.get_timeline().connect(
+            "value_changed",
+            owner,
+            "_on_timeline_value_changed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        self.get_exit().connect(
+            "pressed",
+            owner,
+            "_on_exit_pressed",
+            VariantArray::new_shared(),
+            0,
+        )?;
+
+        Ok(())
+    }
+
+    fn preload_instances(&mut self) {
+        let rock_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/Rock/Rock.tscn", "PackedScene", false)
+            .unwrap();
+        let rock_scene = unsafe { rock_scene.assume_unique().into_shared() };
+
+        self.preload_rock = rock_scene.cast::<PackedScene>().unwrap();
+
+        let powerup_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/PowerUp/PowerUp.tscn", "PackedScene", false)
+            .unwrap();
+        let powerup_scene = unsafe { powerup_scene.assume_unique().into_shared() };
+
+        self.preload_powerup = powerup_scene.cast::<PackedScene>().unwrap();
+    }
+
+    // get child nodes
+
+    fn get_play(&self) -> TRef<Button> {
+        let play = self.play.unwrap();
+        let play = unsafe { play.assume_safe() };
+        play.cast::<Button>().unwrap()
+    }
+
+    fn get_speed_option(&self) -> TRef<OptionButton> {
+        let speed_option = self.speed_option.unwrap();
+        let speed_option = unsafe { speed_option.assume_safe() };
+        speed_option.cast::<OptionButton>().unwrap()
+    }
+
+    fn get_timeline(&self) -> TRef<HSlider> {
+        let timeline = self.timeline.unwrap();
+        let timeline = unsafe { timeline.assume_safe() };
+        timeline.cast::<HSlider>().unwrap()
+    }
+
+    fn get_time_label(&self) -> TRef<Label> {
+        let time_label = self.time_label.unwrap();
+        let time_label = unsafe { time_label.assume_safe() };
+        time_label.cast::<Label>().unwrap()
+    }
+
+    fn get_exit(&self) -> TRef<Button> {
+        let exit = self.exit.unwrap();
+        let exit = unsafe { exit.assume_safe() };
+        exit.cast::<Button>().unwrap()
+    }
+
+    fn get_rocks(&self, owner: TRef<Node>) -> TRef<Node2D> {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        let rocks = world.get_node("Rocks").unwrap();
+        let rocks = unsafe { rocks.assume_safe() };
+        rocks.cast::<Node2D>().unwrap()
+    }
+
+    fn get_camera(&self, owner: TRef<Node>) -> TRef<Camera2D> {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        let camera = world.get_node("Camera2D").unwrap();
+        let camera = unsafe { camera.assume_safe() };
+        camera.cast::<Camera2D>().unwrap()
+    }
+}
+
+/// Tile of a `close_cell` event, numbers of JSON files are floats.
+fn event_cell(event: &ReplayEvent) -> Cell {
+    (
+        event.args.get(0).to_f64() as i32,
+        event.args.get(1).to_f64() as i32,
+    )
+}
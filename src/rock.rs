@@ -1,21 +1,28 @@
+use crate::replay;
 use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
 
 #[derive(NativeClass)]
 #[inherit(KinematicBody2D)]
-pub struct Rock;
+pub struct Rock {
+    /// Playing its explosion, blasts go through it
+    #[property]
+    destroyed: bool,
+}
 
 #[methods]
 #[allow(deprecated)]
 impl Rock {
     fn new(_owner: TRef<KinematicBody2D>) -> Self {
-        Rock
+        Rock { destroyed: false }
     }
 
     /// Sent to everyone else
     #[export(rpc = "puppet")]
-    fn do_explosion(&self, owner: TRef<KinematicBody2D>) {
+    fn do_explosion(&mut self, owner: TRef<KinematicBody2D>) {
+        self.destroyed = true;
+
         // anim_player
         let anim_player = owner.get_node("AnimationPlayer").unwrap();
         let anim_player = unsafe { anim_player.assume_safe() };
@@ -27,7 +34,14 @@ impl Rock {
 
     /// Received by owner of the rock
     #[export(rpc = "master")]
-    fn exploded(&self, owner: TRef<KinematicBody2D>, by_who: Variant) {
+    fn exploded(&mut self, owner: TRef<KinematicBody2D>, by_who: Variant) {
+        // Hit by two blasts at once
+        if self.destroyed {
+            return;
+        }
+
+        replay::record_event(owner.as_ref(), "exploded", std::slice::from_ref(&by_who));
+
         owner.rpc("do_explosion", &[]); // Re-sent to puppet rocks
 
         let world = unsafe { utils::get_world(owner.as_ref()) };
@@ -46,6 +60,45 @@ impl Rock {
 
         score.rpc("increase_score", &[by_who]);
 
+        // A power-up may be left behind, the server rolls for it
+        let kind = unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("roll_powerup", VariantArray::new_shared())
+                .to_i64()
+        };
+        if kind >= 0 {
+            owner.rpc("drop_powerup", &[kind.to_variant()]);
+            self.drop_powerup(owner, kind);
+        }
+
         self.do_explosion(owner);
     }
+
+    /// Server (Sync)
+    /// `kind`: index of `PowerUpKind::ALL`
+    #[export(rpc = "puppet")]
+    fn drop_powerup(&self, owner: TRef<KinematicBody2D>, kind: i64) {
+        replay::record_event(owner.as_ref(), "drop_powerup", &[kind.to_variant()]);
+
+        let powerup_scene = ResourceLoader::godot_singleton()
+            .load("res://scenes/PowerUp/PowerUp.tscn", "PackedScene", false)
+            .unwrap();
+        let powerup_scene = unsafe { powerup_scene.assume_safe() };
+        let powerup_scene = powerup_scene.cast::<PackedScene>().unwrap();
+
+        // PowerUp
+        let powerup = powerup_scene.instance(0).unwrap();
+        let powerup = unsafe { powerup.assume_safe() };
+        let powerup = powerup.cast::<Area2D>().unwrap();
+        //
+
+        powerup.set_name(owner.name()); // Rock names are unique
+        powerup.set_position(owner.position());
+        powerup.set("kind", kind);
+
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+        let powerups = world.get_node("PowerUps").unwrap();
+        let powerups = unsafe { powerups.assume_safe() };
+        powerups.add_child(powerup, false);
+    }
 }
@@ -0,0 +1,207 @@
+use gdnative::prelude::*;
+
+use crate::powerup::PowerUpTable;
+use crate::settings::DEFAULT_FUSE_TIME;
+
+/// Shortest fuse allowed, the blast of a bomb must be over before the next one.
+const MIN_FUSE_TIME: f64 = 0.5;
+
+/// Game modes, chosen by the host in the lobby and sent as an index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameMode {
+    /// Destroying rocks, the highest score wins the round
+    Score,
+    /// Every hit costs a life, the last player standing wins the round
+    Elimination,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 2] = [GameMode::Score, GameMode::Elimination];
+
+    pub fn from_i64(value: i64) -> Self {
+        match value {
+            1 => GameMode::Elimination,
+            _ => GameMode::Score,
+        }
+    }
+
+    pub fn to_i64(self) -> i64 {
+        match self {
+            GameMode::Score => 0,
+            GameMode::Elimination => 1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Score => "Score",
+            GameMode::Elimination => "Elimination",
+        }
+    }
+}
+
+/// Max number of teams, one per color.
+pub const MAX_TEAMS: i64 = 4;
+
+/// Team names, `team` starts from 1 (0 is "no team").
+pub fn team_name(team: i64) -> &'static str {
+    match team {
+        1 => "Red",
+        2 => "Blue",
+        3 => "Green",
+        4 => "Yellow",
+        _ => "",
+    }
+}
+
+/// Tint of players and color of their names.
+pub fn team_color(team: i64) -> Color {
+    match team {
+        1 => Color::rgb(1.0, 0.45, 0.45),
+        2 => Color::rgb(0.45, 0.6, 1.0),
+        3 => Color::rgb(0.45, 1.0, 0.5),
+        4 => Color::rgb(1.0, 0.95, 0.4),
+        _ => Color::rgb(1.0, 1.0, 1.0),
+    }
+}
+
+/// Who wins rounds: the team in team mode, the player otherwise.
+/// `team_of` is in id:team format, empty without teams.
+pub fn side_of(id: i64, team_of: &Dictionary) -> i64 {
+    if team_of.is_empty() {
+        id
+    } else {
+        team_of.get(id).to_i64()
+    }
+}
+
+/// The side with the highest total of `scores` (id:score format),
+/// 0 when nobody scored or the top totals are equal.
+pub fn leading_side(scores: &Dictionary, team_of: &Dictionary) -> i64 {
+    let mut totals = std::collections::BTreeMap::new();
+    for (id, score) in scores.iter() {
+        *totals.entry(side_of(id.to_i64(), team_of)).or_insert(0) += score.to_i64();
+    }
+
+    let mut leader = 0;
+    let mut top_score = 0;
+    let mut tie = false;
+    for (side, total) in totals {
+        if total > top_score {
+            leader = side;
+            top_score = total;
+            tie = false;
+        } else if total == top_score {
+            tie = true;
+        }
+    }
+
+    if tie {
+        0
+    } else {
+        leader
+    }
+}
+
+/// Rules of a match, chosen by the server and sent to every peer with `create_world`.
+#[derive(Debug)]
+pub struct MatchRules {
+    pub mode: GameMode,
+    /// Round wins needed to win the match
+    pub rounds_to_win: i64,
+    /// Elimination only: lives of every player
+    pub lives: i64,
+    /// Number of teams, 0 means everyone for themselves
+    pub teams: i64,
+    /// Blasts hit teammates
+    pub friendly_fire: bool,
+    /// Teams of players in id:team format, balanced by the server
+    pub team_of: Dictionary,
+    /// Chances of rocks dropping power-ups
+    pub powerups: PowerUpTable,
+    /// Seconds between placing a bomb and its explosion
+    pub fuse_time: f64,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            mode: GameMode::Score,
+            rounds_to_win: 1,
+            lives: 1,
+            teams: 0,
+            friendly_fire: true,
+            team_of: Dictionary::new_shared(),
+            powerups: PowerUpTable::default(),
+            fuse_time: DEFAULT_FUSE_TIME,
+        }
+    }
+}
+
+impl MatchRules {
+    pub fn to_dictionary(&self) -> Dictionary {
+        let dict = Dictionary::new();
+
+        dict.insert("mode", self.mode.to_i64());
+        dict.insert("rounds_to_win", self.rounds_to_win);
+        dict.insert("lives", self.lives);
+        dict.insert("teams", self.teams);
+        dict.insert("friendly_fire", self.friendly_fire);
+        dict.insert("team_of", self.team_of.new_ref());
+        dict.insert("powerups", self.powerups.to_dictionary());
+        dict.insert("fuse_time", self.fuse_time);
+
+        dict.into_shared()
+    }
+
+    pub fn side_of(&self, id: i64) -> i64 {
+        side_of(id, &self.team_of)
+    }
+
+    /// Round winner shown to players.
+    pub fn side_name(&self, side: i64, players: &Dictionary) -> String {
+        if self.teams > 0 {
+            format!("TEAM {}", team_name(side).to_uppercase())
+        } else {
+            players.get(side).to_string()
+        }
+    }
+
+    pub fn from_dictionary(dict: &Dictionary) -> Self {
+        let default = MatchRules::default();
+
+        MatchRules {
+            mode: GameMode::from_i64(dict.get("mode").to_i64()),
+            rounds_to_win: dict
+                .get("rounds_to_win")
+                .try_to_i64()
+                .unwrap_or(default.rounds_to_win)
+                .max(1),
+            lives: dict
+                .get("lives")
+                .try_to_i64()
+                .unwrap_or(default.lives)
+                .max(1),
+            teams: dict.get("teams").to_i64().clamp(0, MAX_TEAMS),
+            friendly_fire: dict
+                .get("friendly_fire")
+                .try_to_bool()
+                .unwrap_or(default.friendly_fire),
+            team_of: dict
+                .get("team_of")
+                .try_to_dictionary()
+                .unwrap_or(default.team_of),
+            powerups: dict
+                .get("powerups")
+                .try_to_dictionary()
+                .map_or(default.powerups, |powerups| {
+                    PowerUpTable::from_dictionary(&powerups)
+                }),
+            fuse_time: dict
+                .get("fuse_time")
+                .try_to_f64()
+                .unwrap_or(default.fuse_time)
+                .max(MIN_FUSE_TIME),
+        }
+    }
+}
@@ -1,27 +1,33 @@
+use crate::arena;
+use crate::replay;
+use crate::rules::{self, GameMode, MatchRules};
 use crate::utils;
 use gdnative::api::*;
 use gdnative::prelude::*;
 
 #[derive(NativeClass)]
 #[inherit(HBoxContainer)]
-pub struct Score;
+pub struct Score {
+    /// The winner of the round is already known
+    round_over: bool,
+    /// Team mode only: teams of players in id:team format
+    team_of: Dictionary,
+}
 
 #[methods]
 impl Score {
     fn new(_owner: TRef<HBoxContainer>) -> Self {
-        Score
+        Score {
+            round_over: false,
+            team_of: Dictionary::new_shared(),
+        }
     }
 
     #[export]
     fn _ready(&mut self, owner: TRef<HBoxContainer>) {
         self.get_winner(owner).hide();
 
-        // Exit Button
-        let exit_button = owner.get_node("../Winner/ExitGame").unwrap();
-        let exit_button = unsafe { exit_button.assume_safe() };
-        let exit_button = exit_button.cast::<Button>().unwrap();
-        //
-
+        let exit_button = self.get_exit_button(owner);
         let status_exit_button = exit_button.connect(
             "pressed",
             owner,
@@ -37,16 +43,48 @@ impl Score {
             );
         }
 
+        let rematch_button = self.get_rematch_button(owner);
+        let status_rematch_button = rematch_button.connect(
+            "pressed",
+            owner,
+            "_on_rematch_button_pressed",
+            VariantArray::new_shared(),
+            0,
+        );
+
+        if let Err(e) = status_rematch_button {
+            godot_error!(
+                "`Score` => GodotError at `rematch_button.connect` function: {}",
+                e
+            );
+        }
+
         owner.set_process(true);
     }
 
     #[export]
-    fn _process(&self, owner: TRef<HBoxContainer>, _delta: f64) {
-        let rocks_left = self.get_rocks(owner).get_child_count();
+    fn _process(&mut self, owner: TRef<HBoxContainer>, _delta: f64) {
+        // Replays show the recorded result, see `ReplayViewer`
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if !tree.has_network_peer() {
+            return;
+        }
 
-        if rocks_left == 0 {
-            let mut winner_name = String::from("");
+        let rocks = self.get_rocks(owner);
+        let rocks_left = rocks.get_child_count();
+
+        // Rocks taken by the closing arena weren't destroyed, the time limit decides the round then
+        if rocks_left == 0 && arena::closed_rocks(&rocks) == 0 && !self.round_over {
+            self.round_over = true;
+
+            // The last player standing wins, rocks don't matter
+            if self.is_elimination(owner) {
+                return;
+            }
+
+            let mut winner_id = 0;
             let mut winner_score = 0;
+            let mut draw = false;
 
             for p_label in owner.get_children().iter() {
                 let p_label = p_label.try_to_object::<Label>().unwrap();
@@ -64,27 +102,141 @@ impl Score {
                 println!("p_id: {}, p_score: {}", p_id, p_score);
 
                 if p_score > winner_score {
-                    winner_name = p_label
-                        .text()
-                        .to_string()
-                        .split('\n')
-                        .collect::<Vec<&str>>()[0]
-                        .parse()
-                        .unwrap();
-
                     winner_score = p_score;
+                    winner_id = p_id.to_string().parse().unwrap_or(0);
+                    draw = false;
+                } else if p_score == winner_score {
+                    draw = true;
                 }
             }
 
-            self.get_winner(owner)
-                .set_text(format!("THE WINNER IS:\n{}", winner_name));
-            self.get_winner(owner).show();
+            if draw {
+                winner_id = 0;
+            }
+
+            // Teammates share their scores
+            if !self.team_of.is_empty() {
+                let scores = self.get_scores(owner);
+                winner_id = rules::leading_side(&scores, &self.team_of);
+            }
+
+            self.on_round_over(owner, winner_id);
+        }
+    }
+
+    fn is_elimination(&self, owner: TRef<HBoxContainer>) -> bool {
+        let rules = unsafe {
+            utils::get_gamestate_singleton(owner.as_ref())
+                .callv("get_rules", VariantArray::new_shared())
+                .to_dictionary()
+        };
+        MatchRules::from_dictionary(&rules).mode == GameMode::Elimination
+    }
+
+    /// The server decides the round, it's shown by `show_result` everywhere.
+    fn on_round_over(&self, owner: TRef<HBoxContainer>, winner_id: i64) {
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.is_network_server() {
+            // `GameState` replaces the world, so not while it's being processed
+            unsafe {
+                utils::get_gamestate_singleton(owner.as_ref())
+                    .call_deferred("finish_round", &[winner_id.to_variant()]);
+            }
         }
     }
 
+    /// The next round of a replay, it starts without a result.
+    #[export]
+    fn reset_round(&mut self, owner: TRef<HBoxContainer>) {
+        self.round_over = false;
+        self.get_winner(owner).hide();
+    }
+
+    /// `match_over`: the exit button is shown, `can_rematch`: the rematch button too.
+    #[export]
+    fn show_result(
+        &self,
+        owner: TRef<HBoxContainer>,
+        text: String,
+        match_over: bool,
+        can_rematch: bool,
+    ) {
+        let winner = self.get_winner(owner);
+        winner.set_text(text);
+        winner.show();
+
+        self.get_exit_button(owner).set_visible(match_over);
+        self.get_rematch_button(owner).set_visible(can_rematch);
+    }
+
+    /// Scores in id:score format, used by `GameState` when the time is up.
+    #[export]
+    fn get_scores(&self, owner: TRef<HBoxContainer>) -> Dictionary {
+        let scores = Dictionary::new();
+
+        for p_label in owner.get_children().iter() {
+            let p_label = p_label.try_to_object::<Label>().unwrap();
+            let p_label = unsafe { p_label.assume_safe() };
+
+            let p_id: i64 = p_label.name().to_string().parse().unwrap_or(0);
+            let p_score: i64 = p_label
+                .text()
+                .to_string()
+                .split('\n')
+                .nth(1)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0);
+
+            scores.insert(p_id, p_score);
+        }
+
+        scores.into_shared()
+    }
+
+    /// Team mode: team totals are shown above the individual scores.
+    #[export]
+    fn set_teams(&mut self, owner: TRef<HBoxContainer>, team_of: Dictionary) {
+        self.team_of = team_of;
+        self.update_team_score(owner);
+    }
+
+    fn update_team_score(&self, owner: TRef<HBoxContainer>) {
+        let team_score = self.get_team_score(owner);
+        if self.team_of.is_empty() {
+            team_score.hide();
+            return;
+        }
+
+        let mut totals = std::collections::BTreeMap::new();
+        // Teams without points are shown too
+        for team in self.team_of.values().iter() {
+            totals.entry(team.to_i64()).or_insert(0);
+        }
+        for (id, score) in self.get_scores(owner).iter() {
+            *totals
+                .entry(rules::side_of(id.to_i64(), &self.team_of))
+                .or_insert(0) += score.to_i64();
+        }
+
+        let text = totals
+            .iter()
+            .map(|(team, total)| format!("{} {}", rules::team_name(*team), total))
+            .collect::<Vec<String>>()
+            .join(" - ");
+
+        team_score.set_text(text);
+        team_score.show();
+    }
+
     /// Remote (Sync)
     #[export(rpc = "remote")]
     fn increase_score(&self, owner: TRef<HBoxContainer>, for_who: Variant) {
+        replay::record_event(
+            owner.as_ref(),
+            "increase_score",
+            std::slice::from_ref(&for_who),
+        );
+
         // Player label
         let p_label = owner.get_node(for_who.to_string()).unwrap();
         let p_label = unsafe { p_label.assume_safe() };
@@ -110,6 +262,37 @@ impl Score {
         };
 
         p_label.set_text(format!("{}\n{}", p_name, p_score + 1));
+        self.update_team_score(owner);
+
+        let tree = unsafe { utils::get_tree(owner.as_ref()) };
+        if tree.has_network_peer() && tree.is_network_server() {
+            // Points are scored while `GameState` may be processing the world
+            unsafe {
+                utils::get_gamestate_singleton(owner.as_ref())
+                    .call_deferred("on_point_scored", &[for_who]);
+            }
+        }
+    }
+
+    /// Setting the score directly, used by `ReplayViewer` when seeking.
+    #[export]
+    fn set_score(&self, owner: TRef<HBoxContainer>, for_who: Variant, score: i64) {
+        // Player label
+        let p_label = owner.get_node(for_who.to_string()).unwrap();
+        let p_label = unsafe { p_label.assume_safe() };
+        let p_label = p_label.cast::<Label>().unwrap();
+        //
+
+        let p_name = p_label
+            .text()
+            .to_string()
+            .split('\n')
+            .next()
+            .unwrap_or("")
+            .to_string();
+
+        p_label.set_text(format!("{}\n{}", p_name, score));
+        self.update_team_score(owner);
     }
 
     #[export]
@@ -148,6 +331,24 @@ impl Score {
             .callv("end_game", VariantArray::new_shared());
     }
 
+    /// Host only: the same roster plays again, in a new world replacing this one.
+    #[export]
+    unsafe fn _on_rematch_button_pressed(&self, owner: TRef<HBoxContainer>) {
+        utils::get_gamestate_singleton(owner.as_ref()).call_deferred("start_game", &[]);
+    }
+
+    fn get_exit_button(&self, owner: TRef<HBoxContainer>) -> TRef<Button> {
+        let exit_button = owner.get_node("../Winner/ExitGame").unwrap();
+        let exit_button = unsafe { exit_button.assume_safe() };
+        exit_button.cast::<Button>().unwrap()
+    }
+
+    fn get_rematch_button(&self, owner: TRef<HBoxContainer>) -> TRef<Button> {
+        let rematch_button = owner.get_node("../Winner/Rematch").unwrap();
+        let rematch_button = unsafe { rematch_button.assume_safe() };
+        rematch_button.cast::<Button>().unwrap()
+    }
+
     fn get_winner(&self, owner: TRef<HBoxContainer>) -> TRef<Label> {
         let world = unsafe { utils::get_world(owner.as_ref()) };
 
@@ -156,6 +357,14 @@ impl Score {
         winner.cast::<Label>().unwrap()
     }
 
+    fn get_team_score(&self, owner: TRef<HBoxContainer>) -> TRef<Label> {
+        let world = unsafe { utils::get_world(owner.as_ref()) };
+
+        let team_score = world.get_node("TeamScore").unwrap();
+        let team_score = unsafe { team_score.assume_safe() };
+        team_score.cast::<Label>().unwrap()
+    }
+
     fn get_rocks(&self, owner: TRef<HBoxContainer>) -> TRef<Node2D> {
         let world = unsafe { utils::get_world(owner.as_ref()) };
 
@@ -0,0 +1,373 @@
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+use crate::map::DEFAULT_MAP;
+use crate::mapgen::MapParams;
+use crate::powerup::{PowerUpKind, PowerUpTable};
+
+/// Local settings file, edited by hand or by the game itself.
+pub const SETTINGS_PATH: &str = "user://settings.cfg";
+
+/// How many replays are kept in `user://replays/` by default.
+const DEFAULT_REPLAY_RETENTION: i64 = 20;
+
+/// Round wins needed to win a match by default (best of 3).
+const DEFAULT_ROUNDS_TO_WIN: i64 = 2;
+
+/// Lives of every player in elimination mode by default.
+const DEFAULT_LIVES: i64 = 3;
+
+/// Seconds of a round by default, 0 is no time limit.
+const DEFAULT_TIME_LIMIT: i64 = 0;
+
+/// Seconds before the end of a round when the arena starts closing by default, 0 keeps it open.
+const DEFAULT_HURRY_UP: i64 = 0;
+
+/// Seconds between placing a bomb and its explosion by default.
+pub const DEFAULT_FUSE_TIME: f64 = 2.8;
+
+/// What happens when the time of a round runs out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overtime {
+    /// The highest score wins, equal top scores make a draw
+    HighestScore,
+    /// The next point wins
+    SuddenDeath,
+}
+
+impl Overtime {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "highest-score" => Some(Overtime::HighestScore),
+            "sudden-death" => Some(Overtime::SuddenDeath),
+            _ => None,
+        }
+    }
+}
+
+/// Settings read from `user://settings.cfg` and overridden by command line arguments.
+///
+/// Command line arguments are passed after `--`, for example:
+/// ```
+/// godot -- --record-replay --replay-retention 50
+/// godot --no-window -- --loadtest 11 --connect 127.0.0.1
+/// ```
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// `None` means "use the default": the server records, clients don't.
+    pub record_replays: Option<bool>,
+    /// Max number of replay files kept on disk, the oldest are removed first.
+    pub replay_retention: i64,
+
+    /// Overrides `MAX_PEERS` of the server.
+    pub max_peers: Option<i64>,
+
+    /// Server only: round wins needed to win a match.
+    pub rounds_to_win: i64,
+    /// Server only: lives of every player in elimination mode.
+    pub lives: i64,
+    /// Server only: seconds of a round, 0 means no limit.
+    pub time_limit: i64,
+    /// Server only: what happens when the time runs out.
+    pub overtime: Overtime,
+    /// Server only: seconds before the end of a round when the arena starts closing, 0 means never.
+    pub hurry_up: i64,
+    /// Server only: seconds between placing a bomb and its explosion.
+    pub fuse_time: f64,
+    /// Server only: chances of rocks dropping power-ups.
+    pub powerups: PowerUpTable,
+    /// Server only: map file of every match.
+    pub map_path: String,
+    /// Server only: every round is played on a generated map instead.
+    pub procedural_map: bool,
+    /// Server only: size and rock density of generated maps.
+    pub map_params: MapParams,
+    /// Server only: map files played one after another, one per match, the lobby can't pick.
+    /// `procedural` is a generated map.
+    pub map_rotation: Vec<String>,
+
+    /// `--validate-map PATH`: prints the problems of the map file and quits.
+    pub validate_map: Option<String>,
+    /// `--allow-shared-spawns`: the validator accepts maps with fewer spawn points than `MAX_PEERS`.
+    pub allow_shared_spawns: bool,
+
+    /// `--loadtest N`: number of fake clients to start.
+    pub loadtest_clients: Option<i64>,
+    /// `--connect IP`: server of the load test, hosted in the same process if not set.
+    pub connect_address: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            record_replays: None,
+            replay_retention: DEFAULT_REPLAY_RETENTION,
+
+            max_peers: None,
+
+            rounds_to_win: DEFAULT_ROUNDS_TO_WIN,
+            lives: DEFAULT_LIVES,
+            time_limit: DEFAULT_TIME_LIMIT,
+            overtime: Overtime::HighestScore,
+            hurry_up: DEFAULT_HURRY_UP,
+            fuse_time: DEFAULT_FUSE_TIME,
+            powerups: PowerUpTable::default(),
+            map_path: DEFAULT_MAP.to_string(),
+            procedural_map: false,
+            map_params: MapParams::default(),
+            map_rotation: Vec::new(),
+
+            validate_map: None,
+            allow_shared_spawns: false,
+
+            loadtest_clients: None,
+            connect_address: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Loading settings file and applying command line arguments on top of it.
+    pub fn load() -> Self {
+        let mut settings = Settings::default();
+
+        settings.load_file();
+        settings.apply_cmdline_args(&cmdline_args());
+
+        settings
+    }
+
+    fn load_file(&mut self) {
+        let config = ConfigFile::new();
+        if config.load(SETTINGS_PATH).is_err() {
+            // No settings file yet, defaults are fine.
+            return;
+        }
+
+        if config.has_section_key("replay", "record") {
+            self.record_replays = Some(config.get_value("replay", "record", false).to_bool());
+        }
+
+        self.replay_retention = config
+            .get_value("replay", "retention", self.replay_retention)
+            .to_i64();
+
+        if config.has_section_key("network", "max_peers") {
+            self.max_peers = Some(config.get_value("network", "max_peers", 0).to_i64());
+        }
+
+        self.rounds_to_win = config
+            .get_value("match", "rounds_to_win", self.rounds_to_win)
+            .to_i64();
+        self.lives = config.get_value("match", "lives", self.lives).to_i64();
+        self.time_limit = config
+            .get_value("match", "time_limit", self.time_limit)
+            .to_i64();
+
+        if config.has_section_key("match", "overtime") {
+            let overtime = config.get_value("match", "overtime", "").to_string();
+            match Overtime::parse(&overtime) {
+                Some(overtime) => self.overtime = overtime,
+                None => godot_warn!("`Settings` => unknown `[match] overtime`: {}", overtime),
+            }
+        }
+
+        self.hurry_up = config
+            .get_value("match", "hurry_up", self.hurry_up)
+            .to_i64();
+
+        self.fuse_time = config
+            .get_value("match", "fuse_time", self.fuse_time)
+            .to_f64();
+
+        for kind in PowerUpKind::ALL.iter() {
+            if config.has_section_key("powerups", kind.key()) {
+                let chance = config.get_value("powerups", kind.key(), 0.0).to_f64();
+                self.powerups.set_chance(*kind, chance);
+            }
+        }
+
+        self.map_path = config
+            .get_value("map", "file", self.map_path.as_str())
+            .to_string();
+        self.procedural_map = config
+            .get_value("map", "procedural", self.procedural_map)
+            .to_bool();
+        self.map_params.width = config
+            .get_value("map", "width", self.map_params.width)
+            .to_i64();
+        self.map_params.height = config
+            .get_value("map", "height", self.map_params.height)
+            .to_i64();
+        self.map_params.rock_density = config
+            .get_value("map", "rock_density", self.map_params.rock_density)
+            .to_f64();
+        if config.has_section_key("map", "rotation") {
+            let rotation = config.get_value("map", "rotation", VariantArray::new_shared());
+            self.map_rotation = rotation
+                .to_array()
+                .iter()
+                .map(|path| path.to_string())
+                .collect();
+        }
+    }
+
+    fn apply_cmdline_args(&mut self, args: &[String]) {
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record-replay" => self.record_replays = Some(true),
+                "--no-record-replay" => self.record_replays = Some(false),
+                "--replay-retention" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.replay_retention = value;
+                    } else {
+                        godot_warn!("`Settings` => `--replay-retention` expects a number");
+                    }
+                }
+                "--max-peers" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.max_peers = Some(value);
+                    } else {
+                        godot_warn!("`Settings` => `--max-peers` expects a number");
+                    }
+                }
+                "--rounds-to-win" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.rounds_to_win = value;
+                    } else {
+                        godot_warn!("`Settings` => `--rounds-to-win` expects a number");
+                    }
+                }
+                "--lives" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.lives = value;
+                    } else {
+                        godot_warn!("`Settings` => `--lives` expects a number");
+                    }
+                }
+                "--time-limit" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.time_limit = value;
+                    } else {
+                        godot_warn!("`Settings` => `--time-limit` expects a number of seconds");
+                    }
+                }
+                "--overtime" => {
+                    if let Some(value) = args.next().and_then(|v| Overtime::parse(v)) {
+                        self.overtime = value;
+                    } else {
+                        godot_warn!(
+                            "`Settings` => `--overtime` expects `highest-score` or `sudden-death`"
+                        );
+                    }
+                }
+                "--hurry-up" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.hurry_up = value;
+                    } else {
+                        godot_warn!("`Settings` => `--hurry-up` expects a number of seconds");
+                    }
+                }
+                "--fuse-time" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.fuse_time = value;
+                    } else {
+                        godot_warn!("`Settings` => `--fuse-time` expects a number of seconds");
+                    }
+                }
+                "--powerup" => {
+                    let value = args.next().and_then(|v| {
+                        let (key, chance) = v.split_at(v.find('=')?);
+                        Some((PowerUpKind::parse(key)?, chance[1..].parse().ok()?))
+                    });
+
+                    if let Some((kind, chance)) = value {
+                        self.powerups.set_chance(kind, chance);
+                    } else {
+                        godot_warn!(
+                            "`Settings` => `--powerup` expects `kind=chance`, e.g. `speed=0.1`"
+                        );
+                    }
+                }
+                "--no-powerups" => self.powerups = PowerUpTable::none(),
+                "--map" => {
+                    if let Some(value) = args.next() {
+                        self.map_path = value.clone();
+                    } else {
+                        godot_warn!("`Settings` => `--map` expects a map file path");
+                    }
+                }
+                "--procedural-map" => self.procedural_map = true,
+                "--map-size" => {
+                    let value = args.next().and_then(|v| {
+                        let (width, height) = v.split_at(v.find('x')?);
+                        Some((width.parse().ok()?, height[1..].parse().ok()?))
+                    });
+
+                    if let Some((width, height)) = value {
+                        self.map_params.width = width;
+                        self.map_params.height = height;
+                    } else {
+                        godot_warn!(
+                            "`Settings` => `--map-size` expects `WIDTHxHEIGHT`, e.g. `15x11`"
+                        );
+                    }
+                }
+                "--rock-density" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.map_params.rock_density = value;
+                    } else {
+                        godot_warn!("`Settings` => `--rock-density` expects a number from 0 to 1");
+                    }
+                }
+                "--map-rotation" => {
+                    if let Some(value) = args.next() {
+                        self.map_rotation = value.split(',').map(|path| path.to_string()).collect();
+                    } else {
+                        godot_warn!(
+                            "`Settings` => `--map-rotation` expects map file paths separated by `,`"
+                        );
+                    }
+                }
+                "--validate-map" => {
+                    if let Some(value) = args.next() {
+                        self.validate_map = Some(value.clone());
+                    } else {
+                        godot_warn!("`Settings` => `--validate-map` expects a map file path");
+                    }
+                }
+                "--allow-shared-spawns" => self.allow_shared_spawns = true,
+                "--loadtest" => {
+                    if let Some(value) = args.next().and_then(|v| v.parse().ok()) {
+                        self.loadtest_clients = Some(value);
+                    } else {
+                        godot_warn!("`Settings` => `--loadtest` expects a number of clients");
+                    }
+                }
+                "--connect" => {
+                    if let Some(value) = args.next() {
+                        self.connect_address = Some(value.clone());
+                    } else {
+                        godot_warn!("`Settings` => `--connect` expects an IP address");
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Should this peer record the match into a replay file?
+    pub fn should_record_replay(&self, is_server: bool) -> bool {
+        self.record_replays.unwrap_or(is_server)
+    }
+}
+
+/// Command line arguments passed to the engine (including the ones after `--`).
+pub fn cmdline_args() -> Vec<String> {
+    let args = OS::godot_singleton().get_cmdline_args();
+    let args = args.read();
+
+    args.iter().map(|arg| arg.to_string()).collect()
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use gdnative::api::*;
+use gdnative::prelude::*;
+
+/// Directory where match statistics are stored.
+pub const MATCHES_DIR: &str = "user://matches";
+
+/// Bumped every time the statistics layout changes.
+const STATS_FORMAT_VERSION: i64 = 1;
+
+/// Counters of a single player.
+#[derive(Clone, Copy, Default)]
+pub struct PlayerStats {
+    pub rocks_destroyed: i64,
+    /// Stuns of other players, self-stuns are neither dealt nor received
+    pub stuns_dealt: i64,
+    pub stuns_received: i64,
+    pub self_stuns: i64,
+    pub bombs_placed: i64,
+}
+
+/// Key event of the match timeline.
+struct TimelineEvent {
+    /// Milliseconds since the start of the match
+    t: i64,
+    kind: &'static str,
+    player: i64,
+    /// Stunned player, or the bomb which set off a chain reaction
+    target: Option<String>,
+    /// Chain reactions only: 1 for the first bomb set off, 2 for the next one...
+    chain_depth: Option<i64>,
+}
+
+/// Statistics of the current match, fed by the same events as the replay
+/// (see `replay::record_event`). Every peer keeps them for its profile, only the server saves them.
+pub struct MatchStats {
+    match_id: String,
+    map: String,
+    seed: i64,
+    started_at: i64,
+    started_at_msec: i64,
+    /// `(id, name)` pairs
+    roster: Vec<(i64, String)>,
+    players: BTreeMap<i64, PlayerStats>,
+    timeline: Vec<TimelineEvent>,
+}
+
+impl MatchStats {
+    /// `roster` is `GameState::players` before the world is created (id:name).
+    pub fn start(seed: i64, map: &str, roster: &Dictionary) -> Self {
+        let os = OS::godot_singleton();
+        let started_at = os.get_unix_time();
+
+        let mut players = BTreeMap::new();
+        let mut roster_list = Vec::new();
+        for (id, name) in roster.iter() {
+            players.insert(id.to_i64(), PlayerStats::default());
+            roster_list.push((id.to_i64(), name.to_string()));
+        }
+        roster_list.sort_by_key(|(id, _)| *id);
+
+        MatchStats {
+            match_id: format!("{:012}_{}", started_at, seed),
+            map: map.to_string(),
+            seed,
+            started_at,
+            started_at_msec: os.get_ticks_msec(),
+            roster: roster_list,
+            players,
+            timeline: Vec::new(),
+        }
+    }
+
+    /// Milliseconds since the start of the match.
+    pub fn elapsed_msec(&self) -> i64 {
+        OS::godot_singleton().get_ticks_msec() - self.started_at_msec
+    }
+
+    /// Counters of a player, so far.
+    pub fn player(&self, id: i64) -> PlayerStats {
+        self.players.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Same arguments as `ReplayRecorder::record`, unrelated events are ignored.
+    pub fn record(&mut self, node: &str, event: &str, args: &VariantArray) {
+        match event {
+            // `setup_bomb(bomb_name, bomb_pos, from_player_id, fuse_msec)` on `Players/<id>`
+            "setup_bomb" => {
+                let player = args.get(2).to_i64();
+                self.player_mut(player).bombs_placed += 1;
+                self.push_timeline("bomb_placed", player, None);
+            }
+            // `increase_score(for_who)` on `Score`, every peer receives it once per rock
+            "increase_score" => {
+                let player = args.get(0).to_i64();
+                self.player_mut(player).rocks_destroyed += 1;
+                self.push_timeline("rock_destroyed", player, None);
+            }
+            // `stun(by_who)` on `Players/<id>`, every peer receives it
+            "stun" => {
+                let victim: i64 = node.trim_start_matches("Players/").parse().unwrap_or(0);
+                let by_who = args.get(0).to_i64();
+
+                if victim == by_who {
+                    self.player_mut(victim).self_stuns += 1;
+                } else {
+                    self.player_mut(victim).stuns_received += 1;
+                    self.player_mut(by_who).stuns_dealt += 1;
+                }
+                self.push_timeline("stun", by_who, Some(victim.to_string()));
+            }
+            // `detonate(chain_depth, set_off_by, from_player_id)` on a bomb caught in a blast
+            "detonate" => {
+                let player = args.get(2).to_i64();
+                self.push_timeline("chain_reaction", player, Some(args.get(1).to_string()));
+                if let Some(event) = self.timeline.last_mut() {
+                    event.chain_depth = Some(args.get(0).to_i64());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writing the statistics to `user://matches/<match_id>.json`.
+    /// `winner` is `None` when the match was interrupted.
+    pub fn finish(mut self, winner: Option<i64>) -> Result<String, GodotError> {
+        match winner {
+            Some(winner) => self.push_timeline("match_won", winner, None),
+            None => self.push_timeline("match_interrupted", 0, None),
+        }
+
+        let dir = Directory::new();
+        if !dir.dir_exists(MATCHES_DIR) {
+            dir.make_dir_recursive(MATCHES_DIR)?;
+        }
+
+        let path = format!("{}/{}.json", MATCHES_DIR, self.match_id);
+
+        let file = File::new();
+        file.open(path.as_str(), File::WRITE)?;
+        file.store_string(JSON::godot_singleton().print(self.to_json(winner), "\t", false));
+        file.close();
+
+        godot_print!("match statistics saved: {}", path);
+
+        Ok(path)
+    }
+
+    fn to_json(&self, winner: Option<i64>) -> Dictionary {
+        let json = Dictionary::new();
+        json.insert("version", STATS_FORMAT_VERSION);
+        json.insert("match_id", self.match_id.as_str());
+        json.insert("map", self.map.as_str());
+        json.insert("seed", self.seed);
+        json.insert("started_at", self.started_at);
+        json.insert("duration_msec", self.elapsed_msec());
+        json.insert("completed", winner.is_some());
+        json.insert("winner", winner.map(|w| w.to_variant()).unwrap_or_default());
+
+        let roster = VariantArray::new();
+        for (id, name) in self.roster.iter() {
+            let stats = self.player(*id);
+
+            let player = Dictionary::new();
+            player.insert("id", *id);
+            player.insert("name", name.as_str());
+            player.insert("rocks_destroyed", stats.rocks_destroyed);
+            player.insert("stuns_dealt", stats.stuns_dealt);
+            player.insert("stuns_received", stats.stuns_received);
+            player.insert("self_stuns", stats.self_stuns);
+            player.insert("bombs_placed", stats.bombs_placed);
+            roster.push(player.into_shared());
+        }
+        json.insert("players", roster.into_shared());
+
+        let timeline = VariantArray::new();
+        for event in self.timeline.iter() {
+            let entry = Dictionary::new();
+            entry.insert("t", event.t);
+            entry.insert("event", event.kind);
+            entry.insert("player", event.player);
+            if let Some(target) = &event.target {
+                entry.insert("target", target.as_str());
+            }
+            if let Some(chain_depth) = event.chain_depth {
+                entry.insert("chain_depth", chain_depth);
+            }
+            timeline.push(entry.into_shared());
+        }
+        json.insert("timeline", timeline.into_shared());
+
+        json.into_shared()
+    }
+
+    fn player_mut(&mut self, id: i64) -> &mut PlayerStats {
+        self.players.entry(id).or_default()
+    }
+
+    fn push_timeline(&mut self, kind: &'static str, player: i64, target: Option<String>) {
+        let t = self.elapsed_msec();
+        self.timeline.push(TimelineEvent {
+            t,
+            kind,
+            player,
+            target,
+            chain_depth: None,
+        });
+    }
+}